@@ -0,0 +1,166 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+
+// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub minimum: Vec3,
+    pub maximum: Vec3,
+}
+
+impl Default for Aabb {
+    // An empty box: surrounding it with any other box yields that box
+    fn default() -> Self {
+        Aabb {
+            minimum: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            maximum: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+}
+
+impl Aabb {
+    pub fn aabb(a: Vec3, b: Vec3) -> Aabb {
+        Aabb {
+            minimum: Vec3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            maximum: Vec3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    // Slab test
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
+
+    // Part of [t_min, t_max] for which the ray is inside the box
+    pub fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min: f64 = t_min;
+        let mut t_max: f64 = t_max;
+
+        for a in 0..3 {
+            let inv_d: f64 = 1.0 / r.direction().e[a];
+            let mut t0: f64 = (self.minimum.e[a] - r.origin().e[a]) * inv_d;
+            let mut t1: f64 = (self.maximum.e[a] - r.origin().e[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            // written so that a NaN slab (origin on the plane of a flat box)
+            // does not reject the ray
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some((t_min, t_max))
+    }
+
+    pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+        Aabb {
+            minimum: Vec3::new(
+                box0.minimum.x().min(box1.minimum.x()),
+                box0.minimum.y().min(box1.minimum.y()),
+                box0.minimum.z().min(box1.minimum.z())),
+            maximum: Vec3::new(
+                box0.maximum.x().max(box1.maximum.x()),
+                box0.maximum.y().max(box1.maximum.y()),
+                box0.maximum.z().max(box1.maximum.z())),
+        }
+    }
+
+    // Space inside both boxes, empty if they do not overlap
+    pub fn overlap(box0: &Aabb, box1: &Aabb) -> Aabb {
+        Aabb {
+            minimum: Vec3::new(
+                box0.minimum.x().max(box1.minimum.x()),
+                box0.minimum.y().max(box1.minimum.y()),
+                box0.minimum.z().max(box1.minimum.z())),
+            maximum: Vec3::new(
+                box0.maximum.x().min(box1.maximum.x()),
+                box0.maximum.y().min(box1.maximum.y()),
+                box0.maximum.z().min(box1.maximum.z())),
+        }
+    }
+
+    pub fn include_point(&self, p: &Vec3) -> Aabb {
+        Aabb::surrounding_box(self, &Aabb { minimum: *p, maximum: *p })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.minimum.x() > self.maximum.x()
+            || self.minimum.y() > self.maximum.y()
+            || self.minimum.z() > self.maximum.z()
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.maximum - self.minimum
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d: Vec3 = self.extent();
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d: Vec3 = self.extent();
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_hit() {
+        let b: Aabb = Aabb::aabb(Vec3::new(-1.0, -1.0, -1.0), Vec3::one());
+        let r: Ray = Ray::ray(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(b.hit(&r, 0.0, f64::INFINITY));
+        assert!(!b.hit(&r, 0.0, 3.0));
+        assert_eq!(b.intersect(&r, 0.0, f64::INFINITY), Some((4.0, 6.0)));
+        assert_eq!(b.intersect(&r, 5.0, 5.5), Some((5.0, 5.5)));
+
+        let miss: Ray = Ray::ray(Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!b.hit(&miss, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_aabb_hit_flat_box() {
+        let b: Aabb = Aabb::aabb(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0));
+        let r: Ray = Ray::ray(Vec3::new(0.2, 3.0, 0.1), Vec3::new(0.0, -1.0, 0.0));
+        assert!(b.hit(&r, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_aabb_surrounding_box() {
+        let b0: Aabb = Aabb::aabb(Vec3::zero(), Vec3::one());
+        let b1: Aabb = Aabb::aabb(Vec3::new(-1.0, 2.0, 0.5), Vec3::new(0.0, 3.0, 0.5));
+        let s: Aabb = Aabb::surrounding_box(&b0, &b1);
+        assert_eq!(s.minimum, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(s.maximum, Vec3::new(1.0, 3.0, 1.0));
+        assert_eq!(Aabb::surrounding_box(&Aabb::default(), &b0), b0);
+    }
+
+    #[test]
+    fn test_aabb_surface_area() {
+        let b: Aabb = Aabb::aabb(Vec3::zero(), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(b.surface_area(), 22.0);
+        assert_eq!(Aabb::default().surface_area(), 0.0);
+        assert_eq!(b.longest_axis(), 2);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::image_reader::Image;
+use crate::distribution::Distribution2D;
+use crate::tone_map::luminance;
+use crate::sky::PhysicalSky;
+use std::sync::Arc;
+
+// Radiance arriving along rays that leave the scene
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    Color(Vec3),
+    // Blends from `bottom` looking straight down to `top` looking straight up
+    Gradient { bottom: Vec3, top: Vec3 },
+    Image { map: Arc<EnvironmentMap> },
+    PhysicalSky { sky: Arc<PhysicalSky> },
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::sky()
+    }
+}
+
+impl Background {
+    // White to light blue, the sky every scene used before lights existed
+    pub fn sky() -> Background {
+        Background::Gradient { bottom: Vec3::one(), top: Vec3::new(0.5, 0.7, 1.0) }
+    }
+
+    pub fn value(&self, r: &Ray) -> Vec3 {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let unit_direction: Vec3 = Utils::unit_vector(&r.direction());
+                let t: f64 = 0.5 * (unit_direction.y() + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            },
+            Background::Image { map } => map.value(&r.direction()),
+            Background::PhysicalSky { sky } => sky.value(&r.direction()),
+        }
+    }
+
+    // Backgrounds that light the scene unevenly are sampled like lights,
+    // smooth ones are left to the BSDF
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Background::Image { .. } | Background::PhysicalSky { .. })
+    }
+
+    // Density over solid angle of random(), for sampled backgrounds
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        match self {
+            Background::Image { map } => map.pdf_value(direction),
+            Background::PhysicalSky { sky } => sky.pdf_value(direction),
+            _ => 0.0,
+        }
+    }
+
+    pub fn random(&self) -> Vec3 {
+        match self {
+            Background::Image { map } => map.random(),
+            Background::PhysicalSky { sky } => sky.random(),
+            _ => Utils::random_unit_vector(),
+        }
+    }
+}
+
+// Equirectangular coordinates, both in [0, 1), of a direction: v runs
+// from straight up to straight down, and u = 0.5 looks down -z
+pub fn direction_to_equirect(direction: &Vec3) -> (f64, f64) {
+    let d: Vec3 = Utils::unit_vector(direction);
+    let u: f64 = 0.5 + d.x().atan2(-d.z()) / (2.0 * Utils::pi());
+    let v: f64 = d.y().clamp(-1.0, 1.0).acos() / Utils::pi();
+    (u.rem_euclid(1.0), v.min(1.0 - f64::EPSILON))
+}
+
+// Unit direction at equirectangular coordinates, and the sine of its angle
+// to the vertical
+pub fn equirect_to_direction(u: f64, v: f64) -> (Vec3, f64) {
+    let phi: f64 = (u - 0.5) * 2.0 * Utils::pi();
+    let theta: f64 = v * Utils::pi();
+    let sin_theta: f64 = theta.sin();
+    (Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos()), sin_theta)
+}
+
+// -------- Environment map ----------------
+// Equirectangular image around the scene: the top row looks straight up,
+// and the center of the image looks down -z, where cameras face by default.
+// Directions are importance sampled by pixel luminance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvironmentMap {
+    image: Image,
+    // Turn about the vertical axis, as a fraction of a full turn
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    // `rotation` in degrees turns the map about the vertical axis
+    pub fn environment_map(image: Image, rotation: f64, intensity: f64) -> EnvironmentMap {
+        let (width, height): (u32, u32) = (image.width(), image.height());
+
+        // Rows near the poles cover less solid angle than the ones at the
+        // horizon
+        let mut func: Vec<f64> = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let sin_theta: f64 = (Utils::pi() * (y as f64 + 0.5) / height as f64).sin();
+            func.extend((0..width).map(|x| luminance(&image.pixel(x, y)) * sin_theta));
+        }
+        let distribution: Distribution2D = Distribution2D::distribution_2d(&func, width as usize, height as usize);
+
+        EnvironmentMap { image, rotation: rotation / 360.0, intensity, distribution }
+    }
+
+    // Image coordinates, both in [0, 1), seen along a direction
+    fn direction_to_uv(&self, direction: &Vec3) -> (f64, f64) {
+        let (u, v): (f64, f64) = direction_to_equirect(direction);
+        ((u - self.rotation).rem_euclid(1.0), v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> (Vec3, f64) {
+        equirect_to_direction((u + self.rotation).rem_euclid(1.0), v)
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Vec3 {
+        let (u, v): (f64, f64) = self.direction_to_uv(direction);
+        let x: u32 = ((u * self.image.width() as f64) as u32).min(self.image.width() - 1);
+        let y: u32 = ((v * self.image.height() as f64) as u32).min(self.image.height() - 1);
+        self.image.pixel(x, y) * self.intensity
+    }
+
+    // The (u, v) density is spread over 2 pi^2 sin(theta) of solid angle
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (u, v): (f64, f64) = self.direction_to_uv(direction);
+        let sin_theta: f64 = (v * Utils::pi()).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * Utils::pi() * Utils::pi() * sin_theta)
+    }
+
+    pub fn random(&self) -> Vec3 {
+        let (u, v, _): (f64, f64, f64) = self.distribution.sample(Utils::random_double(), Utils::random_double());
+        self.uv_to_direction(u, v).0
+    }
+}
+// -----------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dim 8x4 map with one bright pixel just above the horizon
+    fn bright_spot_map(rotation: f64) -> EnvironmentMap {
+        let pixels: Vec<Vec3> = (0..32)
+            .map(|i| if i == 8 + 5 { Vec3::new(400.0, 300.0, 200.0) } else { Vec3::one() * 0.1 })
+            .collect();
+        EnvironmentMap::environment_map(Image::image(8, 4, pixels), rotation, 2.0)
+    }
+
+    #[test]
+    fn test_environment_map_lookup() {
+        let map: EnvironmentMap = bright_spot_map(0.0);
+        // Straight ahead is the middle of the image, straight up the top row
+        assert_eq!(map.direction_to_uv(&Vec3::new(0.0, 0.0, -1.0)), (0.5, 0.5));
+        assert_eq!(map.direction_to_uv(&Vec3::new(0.0, 1.0, 0.0)).1, 0.0);
+        assert_eq!(map.value(&Vec3::new(0.0, -1.0, 0.0)), Vec3::one() * 0.2);
+
+        // Pixel (5, 1) is a little right of straight ahead and above the
+        // horizon, until the map is turned a quarter away
+        let (spot, _): (Vec3, f64) = map.uv_to_direction(5.5 / 8.0, 1.5 / 4.0);
+        assert!(spot.x() > 0.0 && spot.y() > 0.0 && spot.z() < 0.0);
+        assert_eq!(map.value(&spot), Vec3::new(800.0, 600.0, 400.0));
+        assert_eq!(bright_spot_map(90.0).value(&spot), Vec3::one() * 0.2);
+
+        for (u, v) in [(0.1, 0.3), (0.7, 0.9), (0.95, 0.5)] {
+            let (d, _): (Vec3, f64) = bright_spot_map(30.0).uv_to_direction(u, v);
+            let (u2, v2): (f64, f64) = bright_spot_map(30.0).direction_to_uv(&d);
+            assert!((u - u2).abs() < 1e-12 && (v - v2).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_environment_map_sampling() {
+        // Most samples head for the bright pixel, and the density is a
+        // proper one over the sphere of directions
+        for rotation in [0.0, 45.0] {
+            let map: EnvironmentMap = bright_spot_map(rotation);
+            let (spot, _): (Vec3, f64) = map.uv_to_direction(5.5 / 8.0, 1.5 / 4.0);
+            Utils::seed_random(8);
+
+            let n: usize = 100_000;
+            let mut hits: usize = 0;
+            for _ in 0..n {
+                let d: Vec3 = map.random();
+                assert!(map.pdf_value(&d) > 0.0);
+                if map.value(&d) == map.value(&spot) {
+                    hits += 1;
+                }
+            }
+            assert!(hits as f64 > 0.9 * n as f64);
+
+            let integral: f64 = (0..n)
+                .map(|_| map.pdf_value(&Utils::random_unit_vector()))
+                .sum::<f64>() * 4.0 * Utils::pi() / n as f64;
+            assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        }
+    }
+}
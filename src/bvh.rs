@@ -0,0 +1,267 @@
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use std::sync::Arc;
+
+// Number of centroid bins tried per axis when evaluating SAH splits
+const SAH_BINS: usize = 16;
+// Largest group of objects that may be kept together in a leaf
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting one object
+const TRAVERSAL_COST: f64 = 0.125;
+
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+struct BuildItem {
+    object: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    // Builds a bounding volume hierarchy over `objects` using a binned
+    // surface area heuristic. Every object must have a bounding box. The
+    // returned root is a BvhNode unless the objects are cheaper to test
+    // as a flat leaf.
+    pub fn build(objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+        let items: Vec<BuildItem> = objects
+            .into_iter()
+            .map(|object| {
+                let mut bbox: Aabb = Aabb::default();
+                assert!(object.bounding_box(&mut bbox), "No bounding box in BvhNode::build");
+                BuildItem { object, bbox }
+            })
+            .collect();
+
+        BvhNode::build_recursive(items)
+    }
+
+    fn make_leaf(mut items: Vec<BuildItem>) -> Box<dyn Hittable> {
+        if items.len() == 1 {
+            return items.pop().unwrap().object;
+        }
+
+        let mut leaf: HittableList = HittableList::default();
+        for item in items {
+            leaf.add(item.object);
+        }
+        Box::new(leaf)
+    }
+
+    fn build_recursive(mut items: Vec<BuildItem>) -> Box<dyn Hittable> {
+        let n: usize = items.len();
+        assert!(n > 0, "BvhNode::build called without objects");
+        if n == 1 {
+            return BvhNode::make_leaf(items);
+        }
+
+        let mut bbox: Aabb = Aabb::default();
+        let mut centroid_bounds: Aabb = Aabb::default();
+        for item in &items {
+            bbox = Aabb::surrounding_box(&bbox, &item.bbox);
+            centroid_bounds = centroid_bounds.include_point(&item.bbox.centroid());
+        }
+
+        let axis: usize = centroid_bounds.longest_axis();
+        let c_min: f64 = centroid_bounds.minimum.e[axis];
+        let c_extent: f64 = centroid_bounds.extent().e[axis];
+
+        let mid: usize = if c_extent <= 0.0 {
+            // All centroids coincide, SAH cannot separate them
+            if n <= MAX_LEAF_SIZE {
+                return BvhNode::make_leaf(items);
+            }
+            n / 2
+        } else {
+            let bin_of = |item: &BuildItem| -> usize {
+                let offset: f64 = (item.bbox.centroid().e[axis] - c_min) / c_extent;
+                ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+            };
+
+            let mut bin_boxes: [Aabb; SAH_BINS] = [Aabb::default(); SAH_BINS];
+            let mut bin_counts: [usize; SAH_BINS] = [0; SAH_BINS];
+            for item in &items {
+                let b: usize = bin_of(item);
+                bin_counts[b] += 1;
+                bin_boxes[b] = Aabb::surrounding_box(&bin_boxes[b], &item.bbox);
+            }
+
+            // Sweep from the right to get the area and count right of each plane
+            let mut right_area: [f64; SAH_BINS] = [0.0; SAH_BINS];
+            let mut right_count: [usize; SAH_BINS] = [0; SAH_BINS];
+            let mut acc_box: Aabb = Aabb::default();
+            let mut acc_count: usize = 0;
+            for b in (1..SAH_BINS).rev() {
+                acc_box = Aabb::surrounding_box(&acc_box, &bin_boxes[b]);
+                acc_count += bin_counts[b];
+                right_area[b] = acc_box.surface_area();
+                right_count[b] = acc_count;
+            }
+
+            // Split plane p separates bins [0, p) from [p, SAH_BINS)
+            let mut best_cost: f64 = f64::INFINITY;
+            let mut best_split: usize = 1;
+            acc_box = Aabb::default();
+            acc_count = 0;
+            for p in 1..SAH_BINS {
+                acc_box = Aabb::surrounding_box(&acc_box, &bin_boxes[p - 1]);
+                acc_count += bin_counts[p - 1];
+                if acc_count == 0 || right_count[p] == 0 {
+                    continue;
+                }
+
+                let cost: f64 = acc_box.surface_area() * acc_count as f64
+                    + right_area[p] * right_count[p] as f64;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_split = p;
+                }
+            }
+
+            let parent_area: f64 = bbox.surface_area();
+            let split_cost: f64 = if parent_area > 0.0 {
+                TRAVERSAL_COST + best_cost / parent_area
+            } else {
+                TRAVERSAL_COST + n as f64
+            };
+            if n <= MAX_LEAF_SIZE && split_cost >= n as f64 {
+                return BvhNode::make_leaf(items);
+            }
+
+            if best_cost.is_finite() {
+                // Stable partition keeps the build deterministic
+                let (mut left, right): (Vec<BuildItem>, Vec<BuildItem>) =
+                    items.into_iter().partition(|item| bin_of(item) < best_split);
+                let mid: usize = left.len();
+                left.extend(right);
+                items = left;
+                mid
+            } else {
+                // Everything landed in one bin, fall back to a median split
+                items.sort_by(|a, b| {
+                    a.bbox.centroid().e[axis]
+                        .partial_cmp(&b.bbox.centroid().e[axis])
+                        .unwrap()
+                });
+                n / 2
+            }
+        };
+
+        let right_items: Vec<BuildItem> = items.split_off(mid);
+        Box::new(BvhNode {
+            left: BvhNode::build_recursive(items),
+            right: BvhNode::build_recursive(right_items),
+            bbox,
+        })
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left: bool = self.left.hit(r, t_min, t_max, rec);
+        let hit_right: bool =
+            self.right.hit(r, t_min, if hit_left { rec.t } else { t_max }, rec);
+
+        hit_left || hit_right
+    }
+
+    fn hit_surface(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left: bool = self.left.hit_surface(r, t_min, t_max, rec);
+        let hit_right: bool =
+            self.right.hit_surface(r, t_min, if hit_left { rec.t } else { t_max }, rec);
+
+        hit_left || hit_right
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return 1.0;
+        }
+
+        self.left.transmittance(r, t_min, t_max) * self.right.transmittance(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        self.left.collect_lights(lights);
+        self.right.collect_lights(lights);
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        self.left.collect_light_copies(lights);
+        self.right.collect_light_copies(lights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::sphere::Sphere;
+    use crate::utils::Utils;
+
+    fn random_spheres(count: usize) -> HittableList {
+        Utils::seed_random(3);
+        let mut world: HittableList = HittableList::default();
+        for _ in 0..count {
+            world.add(
+                Box::new(
+                    Sphere::sphere(
+                        Utils::random_vec3_min_max(-10.0, 10.0),
+                        Utils::random_double_min_max(0.1, 1.0),
+                        Box::new(Material::Dielectric{
+                            dielectric: DielectricMaterial::dielectric(1.5)}))));
+        }
+        world
+    }
+
+    #[test]
+    fn test_bvh_matches_linear_list() {
+        let linear: HittableList = random_spheres(300);
+        let bvh: HittableList = random_spheres(300).into_bvh();
+
+        Utils::seed_random(11);
+        for _ in 0..2000 {
+            let r: Ray = Ray::ray(
+                Utils::random_vec3_min_max(-15.0, 15.0),
+                Utils::random_vec3_min_max(-1.0, 1.0));
+
+            let mut rec_linear: HitRecord = HitRecord::default();
+            let mut rec_bvh: HitRecord = HitRecord::default();
+            let hit_linear: bool = linear.hit(&r, 0.001, Utils::infinity(), &mut rec_linear);
+            let hit_bvh: bool = bvh.hit(&r, 0.001, Utils::infinity(), &mut rec_bvh);
+
+            assert_eq!(hit_linear, hit_bvh);
+            if hit_linear {
+                assert_eq!(rec_linear.t, rec_bvh.t);
+                assert_eq!(rec_linear.normal, rec_bvh.normal);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bvh_bounding_box() {
+        let mut expected: Aabb = Aabb::default();
+        assert!(random_spheres(50).bounding_box(&mut expected));
+
+        let mut actual: Aabb = Aabb::default();
+        assert!(random_spheres(50).into_bvh().bounding_box(&mut actual));
+        assert_eq!(expected, actual);
+    }
+}
@@ -10,9 +10,13 @@ pub struct Camera {
     u : Vec3,
     v : Vec3,
     lens_radius: f64,
+    // Rays are spread evenly over the time the shutter is open
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn camera(
             lookfrom: Vec3,
             lookat: Vec3,
@@ -20,7 +24,9 @@ impl Camera {
             vfov: f64,
             aspect_ratio: f64,
             aperture: f64,
-            focus_dist: f64) -> Camera {
+            focus_dist: f64,
+            shutter_open: f64,
+            shutter_close: f64) -> Camera {
 
         let theta = Utils::degree_to_radians(vfov);
         let viewport_height_half: f64 = (theta / 2.0).tan();
@@ -43,6 +49,8 @@ impl Camera {
             u: temp_u,
             v: temp_v,
             lens_radius: aperture / 2.0,
+            shutter_open,
+            shutter_close,
         }
     }
 
@@ -56,6 +64,72 @@ impl Camera {
                 self.lower_left_corner
                 + s * self.horizontal + t * self.vertical
                 - self.origin - offset,
+            _time : Utils::random_double_min_max(self.shutter_open, self.shutter_close),
+        }
+    }
+}
+
+// Camera parameters without the aspect ratio, which is only known once the
+// output resolution has been chosen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraSettings {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl CameraSettings {
+    #[allow(clippy::too_many_arguments)]
+    pub fn camera_settings(
+            lookfrom: Vec3,
+            lookat: Vec3,
+            vup: Vec3,
+            vfov: f64,
+            aperture: f64,
+            focus_dist: f64,
+            shutter_open: f64,
+            shutter_close: f64) -> CameraSettings {
+        CameraSettings { lookfrom, lookat, vup, vfov, aperture, focus_dist, shutter_open, shutter_close }
+    }
+
+    pub fn camera(&self, aspect_ratio: f64) -> Camera {
+        Camera::camera(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.shutter_open,
+            self.shutter_close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_upright_from_any_side() {
+        // The top of the image must look up and its right side to the right
+        // of the view direction, however the camera is turned about vup.
+        // A cross product with wrong signs once mirrored these views and
+        // turned those from along x upside down.
+        for lookfrom in [Vec3::new(0.0, 2.0, 10.0), Vec3::new(13.0, 2.0, 3.0), Vec3::new(-8.0, 1.0, -4.0)] {
+            let cam: Camera = Camera::camera(
+                lookfrom, Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.0, 10.0, 0.0, 0.0);
+            let forward: Vec3 = Utils::unit_vector(&-lookfrom);
+            let top: Vec3 = Utils::unit_vector(&cam.get_ray(0.5, 1.0).direction());
+            let bottom: Vec3 = Utils::unit_vector(&cam.get_ray(0.5, 0.0).direction());
+            let right: Vec3 = cam.get_ray(1.0, 0.5).direction() - cam.get_ray(0.0, 0.5).direction();
+            assert!(top.y() > bottom.y(), "upside down from {:?}", lookfrom);
+            assert!(Utils::cross(&right, &forward).y() > 0.0, "mirrored from {:?}", lookfrom);
         }
     }
 }
@@ -0,0 +1,266 @@
+use crate::image_writer::ImageFormat;
+use crate::renderer::{RenderSettings, LightSampling};
+use crate::tone_map::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneSource {
+    File(PathBuf),
+    Builtin(String),
+    Obj(PathBuf),
+}
+
+// Everything given on the command line for a render. Unset values keep the
+// scene's own settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub source: SceneSource,
+    pub output: Option<PathBuf>,
+    // From --format or the output path, checked before anything is rendered
+    pub format: ImageFormat,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub light_sampling: Option<LightSampling>,
+    pub pipeline: ColorPipeline,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Render(RenderOptions),
+    List,
+    Help,
+}
+
+pub fn usage() -> String {
+    "\
+Usage:
+    ray_tracer render <scene.toml> [options]    Render a scene file
+    ray_tracer builtin <name> [options]         Render a built-in scene
+    ray_tracer obj <model.obj> [options]        Render an OBJ model on a ground plane
+    ray_tracer list                             List the built-in scenes
+
+Options:
+    -o, --output <path>         Output image, defaults to <scene>.png
+    -f, --format <name>         p3, p6, png, hdr, exr, exr-float, exr-half-raw or
+                                exr-float-raw; picked from the extension if unset
+        --width <pixels>        Image width
+        --height <pixels>       Image height; with only one of width and height
+                                the scene's aspect ratio is kept
+    -s, --spp <count>           Samples per pixel
+    -d, --max-depth <count>     Maximum number of bounces
+        --seed <number>         Sampling seed
+    -j, --threads <count>       Worker threads, 0 uses every core
+        --light-sampling <mode> none, or balance or power to sample lights
+                                directly with that MIS heuristic (default power)
+        --tonemap <operator>    clamp, reinhard, reinhard-extended, aces or hable
+        --exposure <stops>      Exposure adjustment before tone mapping
+    -h, --help                  Print this message
+".to_string()
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str, expected: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value '{}' for {}, expected {}", value, flag, expected))
+}
+
+fn parse_count(flag: &str, value: &str) -> Result<u32, String> {
+    match parse_number::<u32>(flag, value, "a positive integer")? {
+        0 => Err(format!("{} must be at least 1", flag)),
+        count => Ok(count),
+    }
+}
+
+// Parses the arguments that follow the program name
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        return Ok(Command::Help);
+    }
+
+    let source: SceneSource = match (args[0].as_str(), args.get(1)) {
+        ("list", _) if args.len() == 1 => return Ok(Command::List),
+        ("list", _) => return Err("'list' takes no arguments".to_string()),
+        ("render", Some(path)) => SceneSource::File(PathBuf::from(path)),
+        ("builtin", Some(name)) => SceneSource::Builtin(name.clone()),
+        ("obj", Some(path)) => SceneSource::Obj(PathBuf::from(path)),
+        ("render" | "builtin" | "obj", None) => return Err(format!("'{}' needs a scene", args[0])),
+        (other, _) => return Err(format!("unknown command '{}'", other)),
+    };
+
+    let mut options: RenderOptions = RenderOptions {
+        source,
+        output: None,
+        format: ImageFormat::Png,
+        width: None,
+        height: None,
+        samples_per_pixel: None,
+        max_depth: None,
+        seed: None,
+        threads: None,
+        light_sampling: None,
+        pipeline: ColorPipeline::default(),
+    };
+
+    let mut format: Option<ImageFormat> = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        // Both "--flag value" and "--flag=value" are accepted
+        let (flag, inline_value): (&str, Option<&str>) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        if !flag.starts_with('-') {
+            return Err(format!("unexpected argument '{}'", arg));
+        }
+        let value: &str = match inline_value {
+            Some(value) => value,
+            None => rest.next().ok_or_else(|| format!("{} needs a value", flag))?,
+        };
+
+        match flag {
+            "-o" | "--output" => options.output = Some(PathBuf::from(value)),
+            "-f" | "--format" => format = Some(ImageFormat::from_name(value)
+                .ok_or_else(|| format!("unknown image format '{}'", value))?),
+            "--width" => options.width = Some(parse_count(flag, value)?),
+            "--height" => options.height = Some(parse_count(flag, value)?),
+            "-s" | "--spp" => options.samples_per_pixel = Some(parse_count(flag, value)?),
+            "-d" | "--max-depth" => options.max_depth = Some(parse_count(flag, value)?),
+            "--seed" => options.seed = Some(parse_number(flag, value, "a non-negative integer")?),
+            "-j" | "--threads" => options.threads = Some(parse_number(flag, value, "a non-negative integer")?),
+            "--light-sampling" => options.light_sampling = Some(LightSampling::from_name(value)
+                .ok_or_else(|| format!("unknown light sampling mode '{}'", value))?),
+            "--tonemap" => options.pipeline.operator = ToneMapOperator::from_name(value)
+                .ok_or_else(|| format!("unknown tone mapping operator '{}'", value))?,
+            "--exposure" => options.pipeline.exposure = parse_number(flag, value, "a number of stops")?,
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+    }
+
+    let output: PathBuf = options.output_path();
+    options.format = format.or_else(|| ImageFormat::from_path(&output)).ok_or_else(|| format!(
+        "unknown image format for {}, use .png, .ppm, .hdr or .exr or give --format", output.display()))?;
+
+    Ok(Command::Render(options))
+}
+
+impl RenderOptions {
+    // Overrides the scene's settings with the ones given on the command line
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        let aspect_ratio: f64 = settings.image_width as f64 / settings.image_height as f64;
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                settings.image_width = width;
+                settings.image_height = height;
+            },
+            (Some(width), None) => {
+                settings.image_width = width;
+                settings.image_height = ((width as f64 / aspect_ratio) as u32).max(1);
+            },
+            (None, Some(height)) => {
+                settings.image_width = ((height as f64 * aspect_ratio) as u32).max(1);
+                settings.image_height = height;
+            },
+            (None, None) => {},
+        }
+
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(threads) = self.threads {
+            settings.threads = threads;
+        }
+        if let Some(light_sampling) = self.light_sampling {
+            settings.light_sampling = light_sampling;
+        }
+    }
+
+    // Defaults to a PNG named after the scene, in the current directory
+    pub fn output_path(&self) -> PathBuf {
+        if let Some(output) = &self.output {
+            return output.clone();
+        }
+
+        let stem: String = match &self.source {
+            SceneSource::Builtin(name) => name.clone(),
+            SceneSource::File(path) | SceneSource::Obj(path) => path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "render".to_string()),
+        };
+        Path::new(&stem).with_extension("png")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    fn render_options(line: &str) -> RenderOptions {
+        match parse_args(&args(line)) {
+            Ok(Command::Render(options)) => options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_commands() {
+        assert_eq!(parse_args(&[]), Ok(Command::Help));
+        assert_eq!(parse_args(&args("builtin final --help")), Ok(Command::Help));
+        assert_eq!(parse_args(&args("list")), Ok(Command::List));
+        assert!(parse_args(&args("render")).is_err());
+        assert!(parse_args(&args("draw scene.toml")).is_err());
+
+        let options: RenderOptions = render_options("render scenes/a.toml");
+        assert_eq!(options.source, SceneSource::File(PathBuf::from("scenes/a.toml")));
+        assert_eq!(options.output_path(), PathBuf::from("a.png"));
+        assert_eq!(options.format, ImageFormat::Png);
+        assert_eq!(options.pipeline, ColorPipeline::default());
+    }
+
+    #[test]
+    fn test_cli_options() {
+        let options: RenderOptions = render_options(
+            "builtin final -o out.exr --format=exr-float --width 300 -s 16 -d 8 --seed 7 -j 2 \
+             --light-sampling balance --tonemap aces --exposure=-1.5");
+        assert_eq!(options.source, SceneSource::Builtin("final".to_string()));
+        assert_eq!(options.output_path(), PathBuf::from("out.exr"));
+        assert_eq!(Some(options.format), ImageFormat::from_name("exr-float"));
+        assert_eq!(options.pipeline, ColorPipeline { exposure: -1.5, operator: ToneMapOperator::AcesFilmic });
+
+        let mut settings: RenderSettings = RenderSettings::render_settings(1200, 800, 500, 50);
+        options.apply(&mut settings);
+        assert_eq!((settings.image_width, settings.image_height), (300, 200));
+        assert_eq!((settings.samples_per_pixel, settings.max_depth), (16, 8));
+        assert_eq!((settings.seed, settings.threads), (7, 2));
+        assert_eq!(settings.light_sampling, LightSampling::Balance);
+
+        let mut settings: RenderSettings = RenderSettings::render_settings(1200, 800, 500, 50);
+        render_options("obj bunny.obj --height 100").apply(&mut settings);
+        assert_eq!((settings.image_width, settings.image_height), (150, 100));
+    }
+
+    #[test]
+    fn test_cli_errors() {
+        assert_eq!(parse_args(&args("builtin final --spp 0")), Err("--spp must be at least 1".to_string()));
+        assert_eq!(parse_args(&args("builtin final --width")), Err("--width needs a value".to_string()));
+        assert_eq!(parse_args(&args("builtin final --format gif")), Err("unknown image format 'gif'".to_string()));
+        assert_eq!(parse_args(&args("builtin final --verbose 1")), Err("unknown option '--verbose'".to_string()));
+        assert_eq!(parse_args(&args("builtin final -o out.gif")),
+            Err("unknown image format for out.gif, use .png, .ppm, .hdr or .exr or give --format".to_string()));
+        assert!(parse_args(&args("builtin final -o out.gif --format png")).is_ok());
+        assert_eq!(parse_args(&args("builtin final extra")), Err("unexpected argument 'extra'".to_string()));
+        assert!(parse_args(&args("builtin final --seed -3")).is_err());
+    }
+}
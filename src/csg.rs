@@ -0,0 +1,247 @@
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::aabb::Aabb;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CsgOperation {
+    // Inside either solid
+    Union,
+    // Inside both
+    Intersection,
+    // Inside the left solid but not the right one
+    Difference,
+}
+
+impl CsgOperation {
+    pub fn from_name(name: &str) -> Option<CsgOperation> {
+        match name.to_ascii_lowercase().as_str() {
+            "union" => Some(CsgOperation::Union),
+            "intersection" => Some(CsgOperation::Intersection),
+            "difference" => Some(CsgOperation::Difference),
+            _ => None,
+        }
+    }
+
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOperation::Union => left || right,
+            CsgOperation::Intersection => left && right,
+            CsgOperation::Difference => left && !right,
+        }
+    }
+}
+
+// Solid combining two closed objects, which may be Csg nodes themselves.
+// Its surface is made of pieces of theirs and keeps their materials, so a
+// hole cut with a differently colored object shows that color inside.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn csg(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Csg { operation, left, right }
+    }
+}
+
+impl Csg {
+    // Walks the boundaries of both objects in order, keeping track of
+    // which of them the ray is inside. The combined solid's surface is
+    // wherever being inside it changes. Each such boundary is passed to
+    // `boundary`, with front_face telling whether the ray enters there,
+    // until it returns false.
+    fn sweep(&self, r: &Ray, t_min: f64, mut boundary: impl FnMut(HitRecord) -> bool) {
+        let events = |intervals: Vec<HitInterval>| intervals.into_iter()
+            .flat_map(|interval| [(interval.enter, true), (interval.exit, false)])
+            .peekable();
+        let mut left = events(self.left.hit_intervals(r, t_min));
+        let mut right = events(self.right.hit_intervals(r, t_min));
+
+        let (mut in_left, mut in_right): (bool, bool) = (false, false);
+        loop {
+            // Both sides are in order already, so the next boundary is
+            // whichever comes first
+            let is_left: bool = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => l.0.t <= r.0.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return,
+            };
+            let (mut rec, entering): (HitRecord, bool) = match if is_left { left.next() } else { right.next() } {
+                Some(event) => event,
+                None => return,
+            };
+
+            let was_inside: bool = self.operation.inside(in_left, in_right);
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+            let inside: bool = self.operation.inside(in_left, in_right);
+            if inside == was_inside {
+                continue;
+            }
+
+            // The normal already faces the ray, but a surface of the right
+            // object bounds a difference from its other side
+            rec.front_face = inside;
+            if !boundary(rec) {
+                return;
+            }
+        }
+    }
+}
+
+impl Hittable for Csg {
+    // Stops at the first boundary in range instead of collecting them all
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut found: bool = false;
+        self.sweep(r, t_min, |end| {
+            if end.t > t_max {
+                return false;
+            }
+            if end.t >= t_min {
+                *rec = end;
+                found = true;
+                return false;
+            }
+            true
+        });
+        found
+    }
+
+    fn hit_intervals(&self, r: &Ray, t_min: f64) -> Vec<HitInterval> {
+        let mut enter: Option<HitRecord> = None;
+        let mut intervals: Vec<HitInterval> = Vec::new();
+        self.sweep(r, t_min, |rec| {
+            if rec.front_face {
+                enter = Some(rec);
+            } else if let Some(enter) = enter.take() {
+                intervals.push(HitInterval { enter, exit: rec });
+            }
+            true
+        });
+        if let Some(enter) = enter {
+            intervals.push(HitInterval { enter, exit: open_end(f64::INFINITY) });
+        }
+        intervals
+    }
+
+    // Differences and intersections fit inside the left object, and
+    // intersections inside the right one too
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let mut left: Aabb = Aabb::default();
+        let mut right: Aabb = Aabb::default();
+        let has_left: bool = self.left.bounding_box(&mut left);
+        let has_right: bool = self.right.bounding_box(&mut right);
+
+        let bbox: Aabb = match (self.operation, has_left, has_right) {
+            (CsgOperation::Union, true, true) => Aabb::surrounding_box(&left, &right),
+            (CsgOperation::Union, _, _) => return false,
+            (CsgOperation::Intersection, true, true) => Aabb::overlap(&left, &right),
+            (CsgOperation::Intersection, false, true) => right,
+            (_, true, _) => left,
+            (_, false, _) => return false,
+        };
+        *output_box = bbox;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::cuboid::Cuboid;
+    use crate::utils::Utils;
+    use crate::vec3::Vec3;
+
+    fn sphere(x: f64, radius: f64) -> Box<dyn Hittable> {
+        Box::new(Sphere::sphere(Vec3::new(x, 0.0, 0.0), radius, Box::default()))
+    }
+
+    // Entry and exit distances of a ray along +x from x = -10
+    fn spans(object: &dyn Hittable) -> Vec<(f64, f64)> {
+        let r: Ray = Ray::ray(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        object.hit_intervals(&r, 0.001).iter()
+            .map(|interval| (interval.enter.t - 10.0, interval.exit.t - 10.0))
+            .collect()
+    }
+
+    fn close(a: &[(f64, f64)], b: &[(f64, f64)]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9)
+    }
+
+    #[test]
+    fn test_csg_intervals() {
+        // Two overlapping spheres along x, from -1 to 1 and from 0 to 2
+        assert!(close(&spans(sphere(0.0, 1.0).as_ref()), &[(-1.0, 1.0)]));
+        let union: Csg = Csg::csg(CsgOperation::Union, sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert!(close(&spans(&union), &[(-1.0, 2.0)]));
+        let intersection: Csg = Csg::csg(CsgOperation::Intersection, sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert!(close(&spans(&intersection), &[(0.0, 1.0)]));
+        let difference: Csg = Csg::csg(CsgOperation::Difference, sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert!(close(&spans(&difference), &[(-1.0, 0.0)]));
+
+        // A hollow ball is crossed twice, and the nested node keeps working
+        // when another sphere is cut out of its far side
+        let hollow: Csg = Csg::csg(CsgOperation::Difference, sphere(0.0, 1.0), sphere(0.0, 0.5));
+        assert!(close(&spans(&hollow), &[(-1.0, -0.5), (0.5, 1.0)]));
+        let bitten: Csg = Csg::csg(CsgOperation::Difference, Box::new(hollow), sphere(1.0, 0.25));
+        assert!(close(&spans(&bitten), &[(-1.0, -0.5), (0.5, 0.75)]));
+
+        // From inside, the interval reaches back behind the ray
+        let r: Ray = Ray::ray(Vec3::new(-0.8, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let inside: Vec<HitInterval> = union.hit_intervals(&r, 0.001);
+        assert_eq!(inside.len(), 1);
+        assert!(inside[0].enter.t == f64::NEG_INFINITY && (inside[0].exit.t - 2.8).abs() < 1e-9);
+
+        // So far away that a fixed gap after each crossing would be lost in
+        // rounding, and the same crossing found over and over
+        let far: Ray = Ray::ray(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let slab = |x0: f64, x1: f64| -> Box<dyn Hittable> {
+            Box::new(Cuboid::cuboid(Vec3::new(1e10 + x0, -1.0, -1.0), Vec3::new(1e10 + x1, 1.0, 1.0), Box::default()))
+        };
+        let walls: Csg = Csg::csg(CsgOperation::Difference, slab(0.0, 4.0), slab(1.0, 2.0));
+        let ends: Vec<(f64, f64)> = walls.hit_intervals(&far, 0.001).iter()
+            .map(|interval| (interval.enter.t - 1e10, interval.exit.t - 1e10))
+            .collect();
+        assert_eq!(ends, vec![(0.0, 1.0), (2.0, 4.0)]);
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(walls.hit(&far, 1e10 + 0.5, Utils::infinity(), &mut rec) && rec.t == 1e10 + 1.0);
+    }
+
+    #[test]
+    fn test_csg_hit() {
+        let mut rec: HitRecord = HitRecord::default();
+
+        // A ball cut out of the middle of a cube leaves nothing along the z
+        // axis. Nearer the edges the ray goes in through the cube and out
+        // through the ball's surface, seen from inside the ball.
+        let cube: Box<dyn Hittable> = Box::new(Cuboid::cuboid(-Vec3::one(), Vec3::one(), Box::default()));
+        let carved: Csg = Csg::csg(CsgOperation::Difference, cube, sphere(0.0, 1.2));
+        let r: Ray = Ray::ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!carved.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        let corner: Ray = Ray::ray(Vec3::new(0.8, 0.8, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(carved.hit(&corner, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9 && rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        let z: f64 = (1.2f64 * 1.2 - 0.8 * 0.8 * 2.0).sqrt();
+        assert!(carved.hit(&corner, rec.t + 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - (5.0 - z)).abs() < 1e-9 && !rec.front_face);
+        assert!(rec.normal.dot(&corner.direction()) < 0.0);
+
+        // Only hits within [t_min, t_max] count
+        assert!(!carved.hit(&corner, 0.001, 3.9, &mut rec));
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(carved.bounding_box(&mut bbox));
+        assert_eq!(bbox, Aabb::aabb(-Vec3::one(), Vec3::one()));
+        let lens: Csg = Csg::csg(CsgOperation::Intersection, sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert!(lens.bounding_box(&mut bbox));
+        assert_eq!(bbox, Aabb::aabb(Vec3::new(0.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)));
+    }
+}
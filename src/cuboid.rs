@@ -0,0 +1,144 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+
+// Solid box spanned by three edges from a corner q, intersected with one
+// slab test in the box's own coordinates. The edges need not be axis
+// aligned, or even perpendicular.
+pub struct Cuboid {
+    q: Vec3,
+    edges: [Vec3; 3],
+    // duals[i] . (p - q) is the coordinate of p along edges[i], from 0 on
+    // one face to 1 on the opposite one
+    duals: [Vec3; 3],
+    // Outward unit normals of the faces at coordinate 1
+    normals: [Vec3; 3],
+    mat_ptr: Box<Material>,
+}
+
+impl Cuboid {
+    // Axis-aligned box between two opposite corners
+    pub fn cuboid(min: Vec3, max: Vec3, m: Box<Material>) -> Self {
+        let size: Vec3 = max - min;
+        Cuboid::oriented(
+            min, Vec3::new(size.x(), 0.0, 0.0), Vec3::new(0.0, size.y(), 0.0), Vec3::new(0.0, 0.0, size.z()), m)
+    }
+
+    pub fn oriented(q: Vec3, u: Vec3, v: Vec3, w: Vec3, m: Box<Material>) -> Self {
+        let edges: [Vec3; 3] = [u, v, w];
+        let det: f64 = u.dot(&Utils::cross(&v, &w));
+        let duals: [Vec3; 3] = [0, 1, 2]
+            .map(|i| Utils::cross(&edges[(i + 1) % 3], &edges[(i + 2) % 3]) / det);
+        let normals: [Vec3; 3] = duals.map(|dual| Utils::unit_vector(&dual));
+
+        Cuboid { q, edges, duals, normals, mat_ptr: m }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let origin: Vec3 = r.origin() - self.q;
+        let local_origin: [f64; 3] = self.duals.map(|dual| dual.dot(&origin));
+        let local_direction: [f64; 3] = self.duals.map(|dual| dual.dot(&r.direction()));
+
+        // Where the ray enters and leaves the box, and through which face:
+        // (t, axis, side) with side +1 for the face at coordinate 1
+        let mut enter: (f64, usize, f64) = (f64::NEG_INFINITY, 0, 0.0);
+        let mut leave: (f64, usize, f64) = (f64::INFINITY, 0, 0.0);
+        for axis in 0..3 {
+            let (o, d): (f64, f64) = (local_origin[axis], local_direction[axis]);
+            if d.abs() < 1e-12 {
+                if !(0.0..=1.0).contains(&o) {
+                    return false;
+                }
+                continue;
+            }
+
+            let (t0, t1): (f64, f64) = (-o / d, (1.0 - o) / d);
+            let (near, far): ((f64, f64), (f64, f64)) = if d > 0.0 { ((t0, -1.0), (t1, 1.0)) } else { ((t1, 1.0), (t0, -1.0)) };
+            if near.0 > enter.0 {
+                enter = (near.0, axis, near.1);
+            }
+            if far.0 < leave.0 {
+                leave = (far.0, axis, far.1);
+            }
+        }
+        if enter.0 > leave.0 {
+            return false;
+        }
+
+        // From inside the box the first hit is on the way out
+        let (t, axis, side): (f64, usize, f64) = if t_min <= enter.0 && enter.0 <= t_max {
+            enter
+        } else if t_min <= leave.0 && leave.0 <= t_max {
+            leave
+        } else {
+            return false;
+        };
+
+        // Each face is mapped by the coordinates along the other two edges
+        let local = |i: usize| local_origin[i] + t * local_direction[i];
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        rec.u = local((axis + 1) % 3).clamp(0.0, 1.0);
+        rec.v = local((axis + 2) % 3).clamp(0.0, 1.0);
+        rec.set_face_normal(r, &(self.normals[axis] * side));
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let [u, v, w]: [Vec3; 3] = self.edges;
+        *output_box = [u, v, w, u + v, v + w, w + u, u + v + w]
+            .iter()
+            .fold(Aabb::aabb(self.q, self.q), |bbox, edge| bbox.include_point(&(self.q + *edge)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cuboid_hit() {
+        let cuboid: Cuboid = Cuboid::cuboid(Vec3::new(-1.0, 0.0, -4.0), Vec3::new(1.0, 2.0, -2.0), Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        // Through the front face at z = -2, a quarter of the way along x
+        let r: Ray = Ray::ray(Vec3::new(-0.5, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cuboid.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-12 && rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+
+        // From inside, the far face is seen from the back
+        assert!(cuboid.hit(&r, 2.5, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-12 && !rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let past: Ray = Ray::ray(Vec3::new(-1.5, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!cuboid.hit(&past, 0.001, Utils::infinity(), &mut rec));
+
+        // A unit cube turned 45 degrees about y, with its nearest edge
+        // straight ahead, is hit just right of that edge
+        let s: f64 = 0.5f64.sqrt();
+        let turned: Cuboid = Cuboid::oriented(
+            Vec3::new(0.0, 0.0, -5.0), Vec3::new(s, 0.0, -s), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-s, 0.0, -s),
+            Box::default());
+        let diagonal: Ray = Ray::ray(Vec3::new(0.01, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(turned.hit(&diagonal, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 5.01).abs() < 1e-9 && rec.front_face);
+        assert!((rec.normal - Vec3::new(s, 0.0, s)).length() < 1e-12);
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(turned.bounding_box(&mut bbox));
+        assert!((bbox.minimum - Vec3::new(-s, 0.0, -5.0 - 2.0 * s)).length() < 1e-12);
+        assert!((bbox.maximum - Vec3::new(s, 1.0, -5.0)).length() < 1e-12);
+    }
+}
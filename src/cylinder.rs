@@ -0,0 +1,189 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::plane::disk_bounding_box;
+
+// Capped cylinder from a base to a top point. The radius may change
+// linearly along the way, which makes cones and truncated cones cylinders
+// too.
+pub struct Cylinder {
+    base: Vec3,
+    // w points from the base to the top
+    uvw: Onb,
+    height: f64,
+    base_radius: f64,
+    top_radius: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl Cylinder {
+    pub fn cylinder(base: Vec3, top: Vec3, radius: f64, m: Box<Material>) -> Self {
+        Cylinder::cone(base, top, radius, radius, m)
+    }
+
+    // A top radius of zero closes the cone in a point
+    pub fn cone(base: Vec3, top: Vec3, base_radius: f64, top_radius: f64, m: Box<Material>) -> Self {
+        let axis: Vec3 = top - base;
+        Cylinder { base, uvw: Onb::onb(&axis), height: axis.length(), base_radius, top_radius, mat_ptr: m }
+    }
+
+    fn radius_at(&self, z: f64) -> f64 {
+        self.base_radius + (self.top_radius - self.base_radius) * z / self.height
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let o: Vec3 = self.uvw.world_to_local(&(r.origin() - self.base));
+        let d: Vec3 = self.uvw.world_to_local(&r.direction());
+        let slope: f64 = (self.top_radius - self.base_radius) / self.height;
+
+        // Side: x^2 + y^2 = radius(z)^2, a quadratic in t. The closest
+        // candidate t in range wins, with None marking the side and
+        // Some(z) a cap at height z.
+        let mut closest: Option<(f64, Option<f64>)> = None;
+        let mut consider = |t: f64, cap: Option<f64>| {
+            if t_min <= t && t <= t_max && closest.is_none_or(|(best, _)| t < best) {
+                closest = Some((t, cap));
+            }
+        };
+
+        let radius_o: f64 = self.radius_at(o.z());
+        let a: f64 = d.x() * d.x() + d.y() * d.y() - slope * slope * d.z() * d.z();
+        let half_b: f64 = o.x() * d.x() + o.y() * d.y() - radius_o * slope * d.z();
+        let c: f64 = o.x() * o.x() + o.y() * o.y() - radius_o * radius_o;
+        let roots: Vec<f64> = if a.abs() < 1e-12 * d.length_squared() {
+            // Parallel to the axis of a cylinder, or to a line on a cone
+            if half_b.abs() > 0.0 { vec![-c / (2.0 * half_b)] } else { vec![] }
+        } else {
+            let discriminant: f64 = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd: f64 = discriminant.sqrt();
+                vec![(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            } else {
+                vec![]
+            }
+        };
+        for t in roots {
+            let z: f64 = o.z() + t * d.z();
+            if (0.0..=self.height).contains(&z) {
+                consider(t, None);
+            }
+        }
+
+        if d.z().abs() > 1e-12 {
+            for (z, radius) in [(0.0, self.base_radius), (self.height, self.top_radius)] {
+                let t: f64 = (z - o.z()) / d.z();
+                let (x, y): (f64, f64) = (o.x() + t * d.x(), o.y() + t * d.y());
+                if radius > 0.0 && x * x + y * y <= radius * radius {
+                    consider(t, Some(z));
+                }
+            }
+        }
+
+        let (t, cap): (f64, Option<f64>) = match closest {
+            Some(closest) => closest,
+            None => return false,
+        };
+
+        // u is the angle around the axis, v the height along the side or
+        // the distance from the center of a cap as a fraction of its radius
+        let p: Vec3 = o + d * t;
+        let distance: f64 = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let outward_normal: Vec3 = match cap {
+            Some(z) => {
+                rec.v = distance / self.radius_at(z);
+                if z > 0.0 { self.uvw.w } else { -self.uvw.w }
+            },
+            None => {
+                rec.v = p.z() / self.height;
+                let gradient: Vec3 = Vec3::new(p.x(), p.y(), -self.radius_at(p.z()) * slope);
+                // The tip of a cone has no normal of its own
+                if gradient.near_zero() { self.uvw.w } else { Utils::unit_vector(&self.uvw.local(gradient.x(), gradient.y(), gradient.z())) }
+            },
+        };
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        rec.u = (p.y().atan2(p.x()) / (2.0 * Utils::pi())).rem_euclid(1.0);
+        rec.set_face_normal(r, &outward_normal);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let top: Vec3 = self.base + self.uvw.w * self.height;
+        *output_box = Aabb::surrounding_box(
+            &disk_bounding_box(&self.base, &self.uvw.w, self.base_radius),
+            &disk_bounding_box(&top, &self.uvw.w, self.top_radius));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cylinder_hit() {
+        let cylinder: Cylinder = Cylinder::cylinder(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 1.0, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        let side: Ray = Ray::ray(Vec3::new(0.0, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cylinder.hit(&side, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-12 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((rec.v - 0.75).abs() < 1e-12);
+
+        let top: Ray = Ray::ray(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(&top, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 3.0).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+
+        // Out through the side from inside, and past the caps
+        let inside: Ray = Ray::ray(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cylinder.hit(&inside, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-12 && !rec.front_face);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-12);
+        let above: Ray = Ray::ray(Vec3::new(0.0, 2.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!cylinder.hit(&above, 0.001, Utils::infinity(), &mut rec));
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(cylinder.bounding_box(&mut bbox));
+        assert!((bbox.minimum - Vec3::new(-1.0, 0.0, -1.0)).length() < 1e-12);
+        assert!((bbox.maximum - Vec3::new(1.0, 2.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_cone_hit() {
+        // 45 degree cone of height 1 on the ground
+        let cone: Cylinder = Cylinder::cone(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.0, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        let side: Ray = Ray::ray(Vec3::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cone.hit(&side, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.5).abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 1.0) / 2f64.sqrt()).length() < 1e-12);
+
+        // Parallel to a line on the far side of the cone, the side is only
+        // crossed once
+        let along: Ray = Ray::ray(Vec3::new(0.0, 2.0, -1.5), Vec3::new(0.0, -1.0, 1.0));
+        assert!(cone.hit(&along, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.p - Vec3::new(0.0, 0.75, -0.25)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, -1.0) / 2f64.sqrt()).length() < 1e-12);
+        assert!(cone.hit(&along, rec.t + 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.p - Vec3::new(0.0, 0.0, 0.5)).length() < 1e-9 && !rec.front_face);
+        let beside: Ray = Ray::ray(Vec3::new(0.0, 1.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!cone.hit(&beside, 0.001, Utils::infinity(), &mut rec));
+
+        let bottom: Ray = Ray::ray(Vec3::new(0.2, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(cone.hit(&bottom, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-12 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+    }
+}
@@ -0,0 +1,142 @@
+// Piecewise-constant distributions for importance sampling tabulated
+// functions, as in pbrt's Distribution1D and Distribution2D
+
+// Density proportional to a non-negative step function over [0, 1)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    // cdf[i] is the probability of landing below step i, with one more
+    // entry than func that is always 1
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    // A function that is zero everywhere is sampled uniformly instead
+    pub fn distribution_1d(func: Vec<f64>) -> Distribution1D {
+        assert!(!func.is_empty());
+        let n: usize = func.len();
+        let mut cdf: Vec<f64> = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f.max(0.0) / n as f64);
+        }
+
+        let integral: f64 = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if integral > 0.0 { *c / integral } else { i as f64 / n as f64 };
+        }
+        Distribution1D { func, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    // Mean of the function over [0, 1)
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Maps u in [0, 1) to a point x in [0, 1), returning x, its density
+    // and the step it falls in
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // Last step whose cdf is at most u, skipping empty steps
+        let step: usize = (self.cdf.partition_point(|c| *c <= u) - 1).min(self.count() - 1);
+        let width: f64 = self.cdf[step + 1] - self.cdf[step];
+        let offset: f64 = if width > 0.0 { (u - self.cdf[step]) / width } else { 0.0 };
+        let x: f64 = ((step as f64 + offset) / self.count() as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(step), step)
+    }
+
+    // Density of the points in a step
+    pub fn pdf(&self, step: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[step].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    fn step(&self, x: f64) -> usize {
+        ((x * self.count() as f64) as usize).min(self.count() - 1)
+    }
+}
+
+// Density proportional to a step function over [0, 1)^2, sampled by
+// picking v from the marginal distribution of the rows and then u along
+// that row
+#[derive(Clone, PartialEq, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // `func` holds nv rows of nu values each
+    pub fn distribution_2d(func: &[f64], nu: usize, nv: usize) -> Distribution2D {
+        assert_eq!(func.len(), nu * nv);
+        let rows: Vec<Distribution1D> = func.chunks(nu)
+            .map(|row| Distribution1D::distribution_1d(row.to_vec()))
+            .collect();
+        let marginal: Distribution1D = Distribution1D::distribution_1d(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    // Returns (u, v) and its density
+    pub fn sample(&self, u1: f64, u2: f64) -> (f64, f64, f64) {
+        let (v, pdf_v, row): (f64, f64, usize) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _): (f64, f64, usize) = self.rows[row].sample_continuous(u1);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row: usize = self.marginal.step(v);
+        self.marginal.pdf(row) * self.rows[row].pdf(self.rows[row].step(u))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+
+    #[test]
+    fn test_distribution_1d() {
+        let d: Distribution1D = Distribution1D::distribution_1d(vec![1.0, 0.0, 3.0]);
+        assert!((d.integral() - 4.0 / 3.0).abs() < 1e-12);
+        assert_eq!(d.sample_continuous(0.0), (0.0, 0.75, 0));
+        // The empty middle step is never returned
+        let (x, pdf, step): (f64, f64, usize) = d.sample_continuous(0.25);
+        assert_eq!((step, pdf), (2, 2.25));
+        assert!((x - 2.0 / 3.0).abs() < 1e-12);
+        let (x, _, _): (f64, f64, usize) = d.sample_continuous(0.625);
+        assert!((x - 5.0 / 6.0).abs() < 1e-12);
+
+        let flat: Distribution1D = Distribution1D::distribution_1d(vec![0.0, 0.0]);
+        assert_eq!(flat.sample_continuous(0.75), (0.75, 1.0, 1));
+    }
+
+    #[test]
+    fn test_distribution_2d() {
+        // Samples follow the function, and the reported densities agree
+        // with pdf() and integrate to one
+        let func: Vec<f64> = vec![0.0, 1.0, 2.0, 3.0, 4.0, 0.5];
+        let d: Distribution2D = Distribution2D::distribution_2d(&func, 3, 2);
+        let n: usize = 120_000;
+        let mut counts: [usize; 6] = [0; 6];
+        Utils::seed_random(17);
+        for _ in 0..n {
+            let (u, v, pdf): (f64, f64, f64) = d.sample(Utils::random_double(), Utils::random_double());
+            assert!((pdf - d.pdf(u, v)).abs() < 1e-12);
+            counts[(v * 2.0) as usize * 3 + (u * 3.0) as usize] += 1;
+        }
+        let total: f64 = func.iter().sum();
+        for (count, f) in counts.iter().zip(func.iter()) {
+            assert!((*count as f64 / n as f64 - f / total).abs() < 0.01);
+        }
+
+        let integral: f64 = (0..6).map(|i| d.pdf((i % 3) as f64 / 3.0 + 0.1, (i / 3) as f64 / 2.0 + 0.1)).sum::<f64>() / 6.0;
+        assert!((integral - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::sdf::{sphere_trace, gradient_normal, BOUNDS_MARGIN};
+
+// Orbits that get this far from the origin escape to infinity
+const ESCAPE_RADIUS: f64 = 4.0;
+// The distance estimates of the escape-time fractals are only close to the
+// true distance, so rays step a little short of them
+const STEP_SCALE: f64 = 0.9;
+
+// Closest approach of an orbit to the origin and to the coordinate planes,
+// which colors the surface by how the points near it behave
+#[derive(Copy, Clone, Debug)]
+struct OrbitTrap {
+    point: f64,
+    plane: f64,
+}
+
+impl OrbitTrap {
+    fn orbit_trap() -> Self {
+        OrbitTrap { point: f64::INFINITY, plane: f64::INFINITY }
+    }
+
+    fn visit(&mut self, z: &[f64]) {
+        self.point = self.point.min(z.iter().map(|x| x * x).sum::<f64>().sqrt());
+        self.plane = self.plane.min(z.iter().take(3).fold(f64::INFINITY, |plane, x| plane.min(x.abs())));
+    }
+}
+
+// Fractals centered on the origin, each with a distance estimate that
+// sharpens with every iteration
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fractal {
+    // z -> z^power + p, with the power taken in spherical coordinates about
+    // the y axis
+    Mandelbulb { power: f64 },
+    // Julia set of q -> q^2 + c over the quaternions, cut through the
+    // space where the last component of q is zero
+    Julia { c: [f64; 4] },
+    // Cube of half size 1 with the middle crosses of its thirds taken out,
+    // once per iteration
+    Menger,
+}
+
+impl Fractal {
+    // Radius of a sphere around the whole set
+    pub fn bounding_radius(&self) -> f64 {
+        match self {
+            // Beyond these, |z| only grows from one iteration to the next
+            Fractal::Mandelbulb { power } => 2f64.powf(1.0 / (power - 1.0)),
+            Fractal::Julia { c } => {
+                let c: f64 = c.iter().map(|x| x * x).sum::<f64>().sqrt();
+                0.5 * (1.0 + (1.0 + 4.0 * c).sqrt())
+            },
+            Fractal::Menger => 3f64.sqrt(),
+        }
+    }
+
+    fn estimate(&self, p: &Vec3, iterations: u32, trap: &mut OrbitTrap) -> f64 {
+        match self {
+            // Hubbard-Douady estimate 0.5 |z| ln |z| / |z'|, with the
+            // derivative's length carried along as dr
+            Fractal::Mandelbulb { power } => {
+                let mut z: Vec3 = *p;
+                let mut dr: f64 = 1.0;
+                let mut r: f64 = z.length();
+                for _ in 0..iterations {
+                    if r > ESCAPE_RADIUS {
+                        break;
+                    }
+                    trap.visit(&z.e);
+                    let theta: f64 = (z.y() / r.max(f64::MIN_POSITIVE)).clamp(-1.0, 1.0).acos() * power;
+                    let phi: f64 = z.z().atan2(z.x()) * power;
+                    dr = power * r.powf(power - 1.0) * dr + 1.0;
+                    z = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) * r.powf(*power) + *p;
+                    r = z.length();
+                }
+                let r: f64 = r.max(f64::MIN_POSITIVE);
+                0.5 * r * r.ln() / dr
+            },
+            Fractal::Julia { c } => {
+                let mut q: [f64; 4] = [p.x(), p.y(), p.z(), 0.0];
+                let mut dr: f64 = 1.0;
+                let mut r: f64 = p.length();
+                for _ in 0..iterations {
+                    if r > ESCAPE_RADIUS {
+                        break;
+                    }
+                    trap.visit(&q);
+                    dr *= 2.0 * r;
+                    q = [q[0] * q[0] - q[1] * q[1] - q[2] * q[2] - q[3] * q[3] + c[0],
+                         2.0 * q[0] * q[1] + c[1], 2.0 * q[0] * q[2] + c[2], 2.0 * q[0] * q[3] + c[3]];
+                    r = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+                }
+                let r: f64 = r.max(f64::MIN_POSITIVE);
+                0.5 * r * r.ln() / dr
+            },
+            // Each iteration cuts the crosses out of the copies of the cube
+            // at the next scale, found by folding p into one of them
+            Fractal::Menger => {
+                let q: Vec3 = Vec3::new(p.x().abs(), p.y().abs(), p.z().abs()) - Vec3::one();
+                let outside: Vec3 = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+                let mut d: f64 = outside.length() + q.x().max(q.y()).max(q.z()).min(0.0);
+                let mut scale: f64 = 1.0;
+                for _ in 0..iterations {
+                    let a: [f64; 3] = p.e.map(|x| (x * scale).rem_euclid(2.0) - 1.0);
+                    trap.visit(&a);
+                    scale *= 3.0;
+                    let [x, y, z]: [f64; 3] = a.map(|a| (1.0 - 3.0 * a.abs()).abs());
+                    let cross: f64 = (x.max(y).min(y.max(z)).min(z.max(x)) - 1.0) / scale;
+                    d = d.max(cross);
+                }
+                d
+            },
+        }
+    }
+}
+
+// Fractal surface found by sphere tracing its distance estimate, but only
+// through its bounding sphere. The orbit traps at the hit point become the
+// surface coordinates: u is how close the orbit came to the origin and v to
+// the nearest coordinate plane, both clamped to [0, 1], so a ramp texture
+// can color it.
+pub struct FractalHittable {
+    fractal: Fractal,
+    iterations: u32,
+    radius: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl FractalHittable {
+    pub fn fractal_hittable(fractal: Fractal, iterations: u32, m: Box<Material>) -> Self {
+        FractalHittable { fractal, iterations, radius: fractal.bounding_radius() + BOUNDS_MARGIN, mat_ptr: m }
+    }
+}
+
+impl Hittable for FractalHittable {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let oc: Vec3 = r.origin();
+        let a: f64 = r.direction().length_squared();
+        let half_b: f64 = oc.dot(&r.direction());
+        let c: f64 = oc.length_squared() - self.radius * self.radius;
+        let discriminant: f64 = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let sqrtd: f64 = discriminant.sqrt();
+        let (t_start, t_end): (f64, f64) = (((-half_b - sqrtd) / a).max(t_min), ((-half_b + sqrtd) / a).min(t_max));
+        if t_start > t_end {
+            return false;
+        }
+
+        let distance = |p: &Vec3| self.fractal.estimate(p, self.iterations, &mut OrbitTrap::orbit_trap());
+        let t: f64 = match sphere_trace(&distance, r, t_start, t_end, STEP_SCALE) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        let outward_normal: Vec3 = gradient_normal(&distance, &rec.p);
+        rec.set_face_normal(r, &outward_normal);
+        let mut trap: OrbitTrap = OrbitTrap::orbit_trap();
+        self.fractal.estimate(&rec.p, self.iterations, &mut trap);
+        (rec.u, rec.v) = (trap.point.min(1.0), trap.plane.min(1.0));
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::aabb(-Vec3::one() * self.radius, Vec3::one() * self.radius);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+
+    fn estimate(fractal: Fractal, p: Vec3) -> f64 {
+        fractal.estimate(&p, 8, &mut OrbitTrap::orbit_trap())
+    }
+
+    #[test]
+    fn test_fractal_estimates() {
+        // Points whose coordinates never fall in a middle third are inside
+        // the sponge. It is hollow along its axes, and its faces are flat
+        // around the holes.
+        assert!(estimate(Fractal::Menger, Vec3::new(0.5, -0.5, 0.5)) < 0.0);
+        assert!(estimate(Fractal::Menger, Vec3::new(0.0, 0.0, 0.9)) > 0.0);
+        assert!((estimate(Fractal::Menger, Vec3::new(3.0, 0.5, 0.5)) - 2.0).abs() < 1e-12);
+
+        // Escape-time estimates stay below the distance to the bounding
+        // sphere from outside it, and vanish at points that never escape
+        let bulb: Fractal = Fractal::Mandelbulb { power: 8.0 };
+        let julia: Fractal = Fractal::Julia { c: [-0.2, 0.6, 0.2, 0.2] };
+        for fractal in [bulb, julia] {
+            let radius: f64 = fractal.bounding_radius();
+            for p in [Vec3::new(3.0, 0.0, 0.0), Vec3::new(-1.0, 2.0, 2.0), Vec3::new(0.5, -3.5, 0.2)] {
+                let d: f64 = estimate(fractal, p);
+                assert!(0.0 < d && d < p.length() - radius + 1.0, "{:?} at {:?} gave {}", fractal, p, d);
+            }
+        }
+        assert!(estimate(bulb, Vec3::zero()).abs() < 1e-12);
+        assert!((bulb.bounding_radius() - 2f64.powf(1.0 / 7.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fractal_hittable_hit() {
+        let sponge: FractalHittable = FractalHittable::fractal_hittable(Fractal::Menger, 3, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        // Onto a face, and straight through the tunnel along z
+        let face: Ray = Ray::ray(Vec3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sponge.hit(&face, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-3 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+        assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+        assert!(!sponge.hit(&face, 0.001, 3.9, &mut rec));
+        let tunnel: Ray = Ray::ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!sponge.hit(&tunnel, 0.001, Utils::infinity(), &mut rec));
+
+        // Rays past the bounding sphere are culled, and those through it
+        // land on the bulb's surface without passing through it first
+        let bulb: FractalHittable = FractalHittable::fractal_hittable(Fractal::Mandelbulb { power: 8.0 }, 8, Box::default());
+        let past: Ray = Ray::ray(Vec3::new(0.0, 1.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!bulb.hit(&past, 0.001, Utils::infinity(), &mut rec));
+        let r: Ray = Ray::ray(Vec3::new(0.1, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bulb.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!(rec.p.length() < bulb.radius);
+        for i in 0..100 {
+            let t: f64 = rec.t * i as f64 / 100.0;
+            assert!(estimate(Fractal::Mandelbulb { power: 8.0 }, r.point_at_parameter(t)) > 0.0);
+        }
+    }
+}
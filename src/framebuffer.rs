@@ -0,0 +1,80 @@
+use crate::vec3::Vec3;
+use crate::tone_map::ColorPipeline;
+
+// In-memory image of linear radiance values, stored row by row with the
+// top row first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn framebuffer(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3::zero(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    // Copies a row-major block of pixels whose top left corner is (x, y)
+    pub fn set_block(&mut self, x: u32, y: u32, block_width: u32, block: &[Vec3]) {
+        for (row, chunk) in block.chunks(block_width as usize).enumerate() {
+            let start: usize = self.index(x, y + row as u32);
+            self.pixels[start..start + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+
+    // 8-bit display RGB triples, row by row
+    pub fn to_rgb8(&self, pipeline: &ColorPipeline) -> Vec<u8> {
+        self.pixels()
+            .iter()
+            .flat_map(|c| pipeline.encode_rgb8(c))
+            .collect()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) outside the framebuffer", x, y);
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framebuffer_set_block() {
+        let mut fb: Framebuffer = Framebuffer::framebuffer(4, 3);
+        let block: Vec<Vec3> = (0..4).map(|i| Vec3::new(i as f64, 0.0, 0.0)).collect();
+        fb.set_block(2, 1, 2, &block);
+
+        let pixels: &[Vec3] = fb.pixels();
+        assert_eq!(pixels[4 + 2], Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(pixels[4 + 3], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(pixels[8 + 2], Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(pixels[8 + 3], Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(pixels[0], Vec3::zero());
+    }
+
+    #[test]
+    fn test_framebuffer_to_rgb8() {
+        let mut fb: Framebuffer = Framebuffer::framebuffer(2, 1);
+        fb.set_block(0, 0, 1, &[Vec3::new(0.25, 1.0, 4.0)]);
+        assert_eq!(fb.to_rgb8(&ColorPipeline::default()), vec![137, 255, 255, 0, 0, 0]);
+    }
+}
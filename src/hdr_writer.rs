@@ -0,0 +1,442 @@
+use crate::vec3::Vec3;
+use crate::framebuffer::Framebuffer;
+use crate::image_writer::ImageWriter;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io;
+use std::io::Write;
+
+// ----------- Radiance RGBE (.hdr) -----------------
+
+// Shared 8-bit mantissas with a common exponent, as in Greg Ward's rgbe.c
+pub fn rgbe_from_color(color: &Vec3) -> [u8; 4] {
+    let v: f64 = color.r().max(color.g()).max(color.b());
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // frexp: v = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent: i32 = v.log2().floor() as i32 + 1;
+    let mut mantissa: f64 = v / 2f64.powi(exponent);
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+
+    let scale: f64 = mantissa * 256.0 / v;
+    [
+        (color.r().max(0.0) * scale) as u8,
+        (color.g().max(0.0) * scale) as u8,
+        (color.b().max(0.0) * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+// Run-length encodes one component of a scanline. Runs of at least four
+// equal bytes are stored as (128 + count, value), everything else as
+// literal blocks of (count, bytes...).
+fn rle_encode_component(data: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let mut cur: usize = 0;
+
+    while cur < data.len() {
+        // Find the next run long enough to be worth encoding
+        let mut beg_run: usize = cur;
+        let mut run_count: usize = 0;
+        while run_count < MIN_RUN && beg_run < data.len() {
+            beg_run += run_count;
+            run_count = 1;
+            while beg_run + run_count < data.len()
+                && run_count < 127
+                && data[beg_run] == data[beg_run + run_count] {
+                run_count += 1;
+            }
+        }
+        if run_count < MIN_RUN {
+            beg_run = data.len();
+        }
+
+        // Literal bytes before the run
+        while cur < beg_run {
+            let count: usize = (beg_run - cur).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[cur..cur + count]);
+            cur += count;
+        }
+
+        if run_count >= MIN_RUN {
+            out.push((128 + run_count) as u8);
+            out.push(data[beg_run]);
+            cur += run_count;
+        }
+    }
+}
+
+pub struct RadianceHdrWriter {}
+
+impl ImageWriter for RadianceHdrWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", fb.height(), fb.width())?;
+
+        let width: usize = fb.width() as usize;
+        for row in fb.pixels().chunks(width.max(1)) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(rgbe_from_color).collect();
+
+            // The new RLE scheme only supports these widths
+            if !(8..=0x7fff).contains(&width) {
+                for pixel in &rgbe {
+                    out.write_all(pixel)?;
+                }
+                continue;
+            }
+
+            let mut line: Vec<u8> = vec![2, 2, (width >> 8) as u8, (width & 0xff) as u8];
+            for component in 0..4 {
+                let data: Vec<u8> = rgbe.iter().map(|p| p[component]).collect();
+                rle_encode_component(&data, &mut line);
+            }
+            out.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+}
+// -----------------------------------------
+
+// ----------- OpenEXR (.exr) -----------------
+
+// Converts to IEEE 754 half precision, rounding to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits: u32 = value.to_bits();
+    let sign: u16 = ((bits >> 16) & 0x8000) as u16;
+    let exponent: i32 = ((bits >> 23) & 0xff) as i32;
+    let mantissa: u32 = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Inf stays inf, NaN keeps a quiet payload bit
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent: i32 = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        // Subnormal half, or zero once shifted out completely
+        if half_exponent < -10 {
+            return sign;
+        }
+        let full: u32 = mantissa | 0x80_0000;
+        let shift: u32 = (14 - half_exponent) as u32;
+        let half_mantissa: u32 = full >> shift;
+        let remainder: u32 = full & ((1 << shift) - 1);
+        let halfway: u32 = 1 << (shift - 1);
+        let round_up: bool = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    let half_mantissa: u32 = mantissa >> 13;
+    let remainder: u32 = mantissa & 0x1fff;
+    let round_up: bool = remainder > 0x1000 || (remainder == 0x1000 && half_mantissa & 1 == 1);
+    // A carry out of the mantissa correctly bumps the exponent
+    let half: u32 = ((half_exponent as u32) << 10 | half_mantissa) + round_up as u32;
+    sign | half as u16
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+impl ExrPixelType {
+    fn code(&self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExrCompression {
+    None,
+    // zlib over blocks of 16 scanlines
+    Zip,
+}
+
+impl ExrCompression {
+    fn code(&self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(&self) -> usize {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+}
+
+// Single-part scanline OpenEXR with linear R, G and B channels
+pub struct ExrWriter {
+    pub pixel_type: ExrPixelType,
+    pub compression: ExrCompression,
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// The ZIP predictor: interleave even and odd bytes, then delta encode
+fn zip_preprocess(raw: &[u8]) -> Vec<u8> {
+    let mut tmp: Vec<u8> = Vec::with_capacity(raw.len());
+    tmp.extend(raw.iter().step_by(2));
+    tmp.extend(raw.iter().skip(1).step_by(2));
+
+    let mut previous: u8 = tmp.first().copied().unwrap_or(0);
+    for byte in tmp.iter_mut().skip(1) {
+        let current: u8 = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    tmp
+}
+
+impl ExrWriter {
+    fn header(&self, fb: &Framebuffer) -> Vec<u8> {
+        let mut header: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+        // Channels have to be listed alphabetically
+        let mut channels: Vec<u8> = Vec::new();
+        for name in ["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&self.pixel_type.code().to_le_bytes());
+            // pLinear and reserved bytes
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        write_attribute(&mut header, "channels", "chlist", &channels);
+
+        write_attribute(&mut header, "compression", "compression", &[self.compression.code()]);
+
+        let mut window: Vec<u8> = Vec::new();
+        for v in [0, 0, fb.width() as i32 - 1, fb.height() as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+
+        // Increasing y
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+
+        let mut center: Vec<u8> = Vec::new();
+        center.extend_from_slice(&0f32.to_le_bytes());
+        center.extend_from_slice(&0f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &center);
+        write_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+
+        header.push(0);
+        header
+    }
+
+    // Uncompressed data of a block: per scanline, all B values, then G, then R
+    fn raw_block(&self, fb: &Framebuffer, y0: usize, y1: usize) -> Vec<u8> {
+        let width: usize = fb.width() as usize;
+        let mut raw: Vec<u8> = Vec::new();
+
+        for row in fb.pixels()[y0 * width..y1 * width].chunks(width) {
+            for channel in [2, 1, 0] {
+                for pixel in row {
+                    let value: f32 = pixel.e[channel] as f32;
+                    match self.pixel_type {
+                        ExrPixelType::Half => raw.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+                        ExrPixelType::Float => raw.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+        }
+
+        raw
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let header: Vec<u8> = self.header(fb);
+        let height: usize = fb.height() as usize;
+        let lines: usize = self.compression.lines_per_block();
+
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        for y0 in (0..height).step_by(lines) {
+            let y1: usize = (y0 + lines).min(height);
+            let raw: Vec<u8> = self.raw_block(fb, y0, y1);
+
+            let data: Vec<u8> = match self.compression {
+                ExrCompression::None => raw,
+                ExrCompression::Zip => {
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&zip_preprocess(&raw))?;
+                    let compressed: Vec<u8> = encoder.finish()?;
+                    // Readers treat a block of the raw size as uncompressed
+                    if compressed.len() < raw.len() { compressed } else { raw }
+                }
+            };
+
+            let mut chunk: Vec<u8> = Vec::with_capacity(data.len() + 8);
+            chunk.extend_from_slice(&(y0 as i32).to_le_bytes());
+            chunk.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            chunk.extend_from_slice(&data);
+            chunks.push(chunk);
+        }
+
+        // Offset table with the absolute file position of every chunk
+        let mut offset: u64 = (header.len() + chunks.len() * 8) as u64;
+        out.write_all(&header)?;
+        for chunk in &chunks {
+            out.write_all(&offset.to_le_bytes())?;
+            offset += chunk.len() as u64;
+        }
+        for chunk in &chunks {
+            out.write_all(chunk)?;
+        }
+
+        Ok(())
+    }
+}
+// -----------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn gradient(width: u32, height: u32) -> Framebuffer {
+        let mut fb: Framebuffer = Framebuffer::framebuffer(width, height);
+        let pixels: Vec<Vec3> = (0..width * height)
+            .map(|i| Vec3::new(i as f64 * 0.5, 1.0, 100.0))
+            .collect();
+        fb.set_block(0, 0, width, &pixels);
+        fb
+    }
+
+    #[test]
+    fn test_rgbe_from_color() {
+        assert_eq!(rgbe_from_color(&Vec3::zero()), [0, 0, 0, 0]);
+        assert_eq!(rgbe_from_color(&Vec3::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+        assert_eq!(rgbe_from_color(&Vec3::new(0.0, 1000.0, 0.0)), [0, 250, 0, 138]);
+    }
+
+    #[test]
+    fn test_rle_encode_component() {
+        let mut out: Vec<u8> = Vec::new();
+        rle_encode_component(&[1, 2, 3, 7, 7, 7, 7, 7, 9], &mut out);
+        assert_eq!(out, vec![3, 1, 2, 3, 133, 7, 1, 9]);
+    }
+
+    #[test]
+    fn test_radiance_hdr_writer() {
+        let mut out: Vec<u8> = Vec::new();
+        RadianceHdrWriter {}.write(&gradient(10, 2), &mut out).unwrap();
+
+        let header: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 10\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(&out[header.len()..header.len() + 4], &[2, 2, 0, 10]);
+    }
+
+    #[test]
+    fn test_f32_to_f16() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+        // Smallest subnormal and rounding to even
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 / 2048.0), 0x3c02);
+    }
+
+    fn read_i32(data: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_exr_writer_uncompressed_float() {
+        let fb: Framebuffer = gradient(3, 2);
+        let mut out: Vec<u8> = Vec::new();
+        ExrWriter { pixel_type: ExrPixelType::Float, compression: ExrCompression::None }
+            .write(&fb, &mut out)
+            .unwrap();
+
+        assert_eq!(&out[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+        // One chunk per scanline, each 3 channels * 3 pixels * 4 bytes
+        let table: usize = out.len() - 2 * (8 + 36) - 16;
+        let first: usize = u64::from_le_bytes(out[table..table + 8].try_into().unwrap()) as usize;
+        assert_eq!(first, table + 16);
+        assert_eq!(read_i32(&out, first), 0);
+        assert_eq!(read_i32(&out, first + 4), 36);
+
+        // B channel of the first scanline comes first
+        let b0: f32 = f32::from_le_bytes(out[first + 8..first + 12].try_into().unwrap());
+        assert_eq!(b0, 100.0);
+        let r1: f32 = f32::from_le_bytes(out[first + 8 + 24 + 4..first + 8 + 24 + 8].try_into().unwrap());
+        assert_eq!(r1, 0.5);
+    }
+
+    #[test]
+    fn test_exr_writer_zip_half_round_trip() {
+        let fb: Framebuffer = gradient(64, 20);
+        let writer: ExrWriter = ExrWriter { pixel_type: ExrPixelType::Half, compression: ExrCompression::Zip };
+        let mut out: Vec<u8> = Vec::new();
+        writer.write(&fb, &mut out).unwrap();
+
+        // 20 scanlines make two blocks, so the table has two entries
+        let header_len: usize = writer.header(&fb).len();
+        let first: usize = u64::from_le_bytes(out[header_len..header_len + 8].try_into().unwrap()) as usize;
+        assert_eq!(first, header_len + 16);
+        assert_eq!(read_i32(&out, first), 0);
+
+        let size: usize = read_i32(&out, first + 4) as usize;
+        let mut decoded: Vec<u8> = Vec::new();
+        ZlibDecoder::new(&out[first + 8..first + 8 + size]).read_to_end(&mut decoded).unwrap();
+
+        // Undo the predictor and the byte interleaving
+        for i in 1..decoded.len() {
+            decoded[i] = decoded[i].wrapping_add(decoded[i - 1]).wrapping_sub(128);
+        }
+        let half: usize = decoded.len().div_ceil(2);
+        let mut raw: Vec<u8> = Vec::with_capacity(decoded.len());
+        for i in 0..half {
+            raw.push(decoded[i]);
+            if half + i < decoded.len() {
+                raw.push(decoded[half + i]);
+            }
+        }
+
+        assert_eq!(raw, writer.raw_block(&fb, 0, 16));
+    }
+}
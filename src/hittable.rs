@@ -1,6 +1,9 @@
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::utils::Utils;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct HitRecord {
@@ -8,6 +11,9 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat_ptr: Box<Material>,
     pub t: f64,
+    // Surface coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -19,10 +25,134 @@ impl HitRecord {
             self.normal *= -1f64;
         }
     }
+
+    // Like set_face_normal, but front_face comes from the geometric normal
+    // while the stored normal is the (interpolated) shading normal.
+    pub fn set_shading_normal(&mut self, r: &Ray, geometric_normal: &Vec3, shading_normal: &Vec3) {
+        self.front_face = r.direction().dot(geometric_normal) < 0.0;
+        self.normal = *shading_normal;
+        if !self.front_face {
+            self.normal *= -1f64;
+        }
+    }
+}
+
+// Stretch of a ray inside a solid, between the hits where it enters and
+// leaves. A ray that starts inside has an entry at t = -infinity, and one
+// that never leaves an exit at t = infinity; neither end carries a surface.
+pub struct HitInterval {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+// Hit standing for the open end of an interval
+pub fn open_end(t: f64) -> HitRecord {
+    HitRecord { t, ..HitRecord::default() }
+}
+
+// Gap left after each crossing before looking for the next one. Far along
+// the ray it grows to a few ulps of t so that t keeps moving.
+const CROSSING_EPSILON: f64 = 1e-7;
+// Crossings looked for along one ray at most
+const MAX_CROSSINGS: usize = 1000;
+
+// Pairs up surface crossings sorted along a ray, counting how many solids
+// the ray is inside of. Every crossing up to infinity must be present,
+// since the count at the start is unknown until then.
+pub fn intervals_from_crossings(crossings: Vec<HitRecord>) -> Vec<HitInterval> {
+    let exits: usize = crossings.iter().filter(|rec| !rec.front_face).count();
+    let mut depth: usize = (2 * exits).saturating_sub(crossings.len());
+    let mut enter: Option<HitRecord> = if depth > 0 { Some(open_end(f64::NEG_INFINITY)) } else { None };
+
+    let mut intervals: Vec<HitInterval> = Vec::new();
+    for rec in crossings {
+        if rec.front_face {
+            depth += 1;
+            if depth == 1 {
+                enter = Some(rec);
+            }
+        } else if depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                if let Some(enter) = enter.take() {
+                    intervals.push(HitInterval { enter, exit: rec });
+                }
+            }
+        }
+    }
+    if let Some(enter) = enter {
+        intervals.push(HitInterval { enter, exit: open_end(f64::INFINITY) });
+    }
+    intervals
+}
+
+// Intervals of an object found by following the ray from hit to hit
+pub fn crossing_intervals<T: Hittable + ?Sized>(object: &T, r: &Ray, t_min: f64) -> Vec<HitInterval> {
+    let step: f64 = CROSSING_EPSILON / r.direction().length();
+    let mut crossings: Vec<HitRecord> = Vec::new();
+    let mut t: f64 = t_min;
+    while crossings.len() < MAX_CROSSINGS {
+        let mut rec: HitRecord = HitRecord::default();
+        if !object.hit(r, t, Utils::infinity(), &mut rec) {
+            break;
+        }
+        if crossings.last().is_some_and(|last| rec.t <= last.t) {
+            break;
+        }
+        t = rec.t + step.max(rec.t.abs() * 4.0 * f64::EPSILON);
+        crossings.push(rec);
+    }
+    intervals_from_crossings(crossings)
 }
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+// Hittables are shared read-only between render threads.
+pub trait Hittable: Send + Sync {
+    fn hit(&self, _r: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    // Like hit, but sees through participating media, which only dim what
+    // is behind them. Used for shadow rays together with transmittance().
+    fn hit_surface(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit(r, t_min, t_max, rec)
+    }
+
+    // Every interval of the ray inside the object that ends after t_min, in
+    // order. Only meaningful for closed surfaces, whose front faces are
+    // where rays enter. By default the crossings are found one hit at a
+    // time.
+    fn hit_intervals(&self, r: &Ray, t_min: f64) -> Vec<HitInterval> {
+        crossing_intervals(self, r, t_min)
+    }
+
+    // Fraction of light carried along the ray between t_min and t_max that
+    // is not absorbed or scattered away by media. May be a random estimate
+    // whose mean is the transmittance.
+    fn transmittance(&self, _r: &Ray, _t_min: f64, _t_max: f64) -> f64 {
+        1.0
+    }
+
+    // Objects without a finite extent (e.g. infinite planes) return false
+    fn bounding_box(&self, _output_box: &mut Aabb) -> bool {
         false
     }
+
+    // Density over solid angle, seen from `origin`, with which random()
+    // returns `direction`. Only shapes that can be sampled as lights
+    // implement this and random().
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Direction from `origin` towards a random point on the surface
+    fn random(&self, _origin: &Vec3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Adds every emissive primitive to `lights`
+    fn collect_lights<'a>(&'a self, _lights: &mut Vec<&'a dyn Hittable>) {}
+
+    // Like collect_lights, but adds copies that can outlive the borrow, for
+    // instances that sample their object's lights
+    fn collect_light_copies(&self, _lights: &mut Vec<Arc<dyn Hittable>>) {}
 }
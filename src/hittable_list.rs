@@ -1,5 +1,8 @@
 use crate::hittable::*;
 use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct HittableList {
@@ -11,13 +14,43 @@ impl HittableList {
         self.objects.push(object);
     }
 
+    // Moves all objects of another list into this one
+    pub fn append(&mut self, other: HittableList) {
+        self.objects.extend(other.objects);
+    }
+
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.objects.clear();
     }
+
+    // Moves every bounded object into a BVH. Unbounded objects cannot be
+    // placed in the hierarchy and stay in the list next to it.
+    pub fn into_bvh(self) -> HittableList {
+        let mut bounded: Vec<Box<dyn Hittable>> = Vec::new();
+        let mut result: HittableList = HittableList::default();
+
+        for object in self.objects {
+            let mut bbox: Aabb = Aabb::default();
+            if object.bounding_box(&mut bbox) {
+                bounded.push(object);
+            } else {
+                result.add(object);
+            }
+        }
+
+        if !bounded.is_empty() {
+            result.objects.insert(0, BvhNode::build(bounded));
+        }
+
+        result
+    }
 }
 
-impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+impl HittableList {
+    // Closest hit among the objects, as reported by `hit_object`
+    fn closest_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord,
+                   hit_object: impl Fn(&dyn Hittable, &Ray, f64, f64, &mut HitRecord) -> bool) -> bool {
         let mut temp_rec: HitRecord = HitRecord::default();
         let mut hit_anything: bool = false;
         let mut closest_so_far: f64 = t_max;
@@ -25,18 +58,74 @@ impl Hittable for HittableList {
         // iterate over all hittables and call hit
         // Return the closest hit
         for object in &self.objects {
-            if object.hit(r, t_min, closest_so_far, &mut temp_rec) {
+            if hit_object(object.as_ref(), r, t_min, closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
 
                 rec.t = temp_rec.t;
                 rec.p = temp_rec.p;
                 rec.normal = temp_rec.normal;
+                rec.u = temp_rec.u;
+                rec.v = temp_rec.v;
                 rec.front_face = temp_rec.front_face;
                 rec.mat_ptr = temp_rec.mat_ptr.clone();
             }
         }
 
-        return hit_anything;
+        hit_anything
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.closest_hit(r, t_min, t_max, rec, |object, r, t_min, t_max, rec| object.hit(r, t_min, t_max, rec))
+    }
+
+    fn hit_surface(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.closest_hit(r, t_min, t_max, rec, |object, r, t_min, t_max, rec| object.hit_surface(r, t_min, t_max, rec))
+    }
+
+    // A list of one object, as scene files make for nested objects, passes
+    // the query on so that object's own intervals are used
+    fn hit_intervals(&self, r: &Ray, t_min: f64) -> Vec<HitInterval> {
+        match self.objects.as_slice() {
+            [object] => object.hit_intervals(r, t_min),
+            _ => crossing_intervals(self, r, t_min),
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.objects.iter().map(|object| object.transmittance(r, t_min, t_max)).product()
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        if self.objects.is_empty() {
+            return false;
+        }
+
+        let mut temp_box: Aabb = Aabb::default();
+        let mut result: Aabb = Aabb::default();
+
+        for object in &self.objects {
+            if !object.bounding_box(&mut temp_box) {
+                return false;
+            }
+            result = Aabb::surrounding_box(&result, &temp_box);
+        }
+
+        *output_box = result;
+        true
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        for object in &self.objects {
+            object.collect_light_copies(lights);
+        }
     }
 }
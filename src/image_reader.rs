@@ -0,0 +1,353 @@
+use crate::vec3::Vec3;
+use crate::tone_map::srgb_decode;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Decoded image in linear color, rows stored from the top
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl Image {
+    pub fn image(width: u32, height: u32, pixels: Vec<Vec3>) -> Image {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Image { width, height, pixels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// 8 and 16-bit samples are sRGB encoded
+fn decode_sample(value: u32, max_value: u32) -> f64 {
+    srgb_decode(value as f64 / max_value as f64)
+}
+
+// Splits a PPM header into its four fields and returns where the pixel data
+// starts. Comments run from '#' to the end of the line.
+fn ppm_header(data: &[u8]) -> io::Result<(Vec<String>, usize)> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut pos: usize = 0;
+
+    while fields.len() < 4 {
+        match data.get(pos) {
+            None => return Err(invalid_data("truncated PPM header".to_string())),
+            Some(b'#') => {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+            },
+            Some(c) if c.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start: usize = pos;
+                while pos < data.len() && !data[pos].is_ascii_whitespace() && data[pos] != b'#' {
+                    pos += 1;
+                }
+                fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+            },
+        }
+    }
+
+    // A single whitespace character separates the header from binary data
+    Ok((fields, pos + 1))
+}
+
+// ASCII (P3) or binary (P6) PPM
+pub fn decode_ppm(data: &[u8]) -> io::Result<Image> {
+    let (fields, data_start): (Vec<String>, usize) = ppm_header(data)?;
+    let parse = |field: &String, what: &str| -> io::Result<u32> {
+        field.parse::<u32>().map_err(|_| invalid_data(format!("invalid PPM {} '{}'", what, field)))
+    };
+
+    let width: u32 = parse(&fields[1], "width")?;
+    let height: u32 = parse(&fields[2], "height")?;
+    let max_value: u32 = parse(&fields[3], "maximum value")?;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("unsupported PPM dimensions or maximum value".to_string()));
+    }
+    let count: usize = (width as usize).checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(3))
+        .ok_or_else(|| invalid_data(format!("PPM size {}x{} is too large", width, height)))?;
+
+    let samples: Vec<u32> = match fields[0].as_str() {
+        "P3" => {
+            let text: String = String::from_utf8_lossy(data.get(data_start..).unwrap_or(&[])).into_owned();
+            text.split_whitespace()
+                .take(count)
+                .map(|s| s.parse::<u32>().map_err(|_| invalid_data(format!("invalid PPM sample '{}'", s))))
+                .collect::<io::Result<Vec<u32>>>()?
+        },
+        "P6" => {
+            let bytes: &[u8] = data.get(data_start..).unwrap_or(&[]);
+            if max_value < 256 {
+                bytes.iter().take(count).map(|&b| b as u32).collect()
+            } else {
+                bytes.chunks_exact(2).take(count).map(|b| ((b[0] as u32) << 8) | b[1] as u32).collect()
+            }
+        },
+        other => return Err(invalid_data(format!("unsupported PPM type '{}', expected P3 or P6", other))),
+    };
+
+    if samples.len() < count {
+        return Err(invalid_data("truncated PPM pixel data".to_string()));
+    }
+
+    let pixels: Vec<Vec3> = samples.chunks(3)
+        .map(|rgb| Vec3::new(
+            decode_sample(rgb[0], max_value),
+            decode_sample(rgb[1], max_value),
+            decode_sample(rgb[2], max_value)))
+        .collect();
+    Ok(Image::image(width, height, pixels))
+}
+
+// Any PNG; palettes are expanded, alpha is dropped
+pub fn decode_png(data: &[u8]) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+
+    let mut buffer: Vec<u8> = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+
+    let channels: usize = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(invalid_data("unexpanded PNG palette".to_string())),
+    };
+    let (bytes_per_sample, max_value): (usize, u32) = match info.bit_depth {
+        png::BitDepth::Sixteen => (2, 65535),
+        _ => (1, 255),
+    };
+
+    let sample = |bytes: &[u8], index: usize| -> f64 {
+        let value: u32 = if bytes_per_sample == 2 {
+            ((bytes[2 * index] as u32) << 8) | bytes[2 * index + 1] as u32
+        } else {
+            bytes[index] as u32
+        };
+        decode_sample(value, max_value)
+    };
+
+    let pixels: Vec<Vec3> = buffer[..info.buffer_size()]
+        .chunks_exact(info.line_size)
+        .flat_map(|row| (0..info.width as usize).map(move |x| {
+            let first: usize = x * channels;
+            if channels < 3 {
+                let gray: f64 = sample(row, first);
+                Vec3::new(gray, gray, gray)
+            } else {
+                Vec3::new(sample(row, first), sample(row, first + 1), sample(row, first + 2))
+            }
+        }))
+        .collect();
+    Ok(Image::image(info.width, info.height, pixels))
+}
+
+// Radiance RGBE pixel, the inverse of hdr_writer::rgbe_from_color
+fn color_from_rgbe(rgbe: &[u8]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+    let scale: f64 = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Vec3::new(rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale)
+}
+
+// One scanline of run-length encoded components, each stored as runs of
+// (128 + count, value) and literal blocks of (count, bytes...)
+fn decode_rle_scanline(data: &[u8], pos: &mut usize, width: usize) -> io::Result<Vec<u8>> {
+    let truncated = || invalid_data("truncated Radiance HDR scanline".to_string());
+    let mut line: Vec<u8> = vec![0; width * 4];
+    for component in 0..4 {
+        let mut x: usize = 0;
+        while x < width {
+            let count: usize = *data.get(*pos).ok_or_else(truncated)? as usize;
+            *pos += 1;
+            let (count, run): (usize, bool) = if count > 128 { (count - 128, true) } else { (count, false) };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad run length in Radiance HDR scanline".to_string()));
+            }
+            for i in 0..count {
+                let byte_pos: usize = if run { *pos } else { *pos + i };
+                line[(x + i) * 4 + component] = *data.get(byte_pos).ok_or_else(truncated)?;
+            }
+            *pos += if run { 1 } else { count };
+            x += count;
+        }
+    }
+    Ok(line)
+}
+
+// Radiance .hdr files with top-to-bottom rows (-Y h +X w), either flat or
+// with the run-length encoding of newer versions
+pub fn decode_hdr(data: &[u8]) -> io::Result<Image> {
+    let mut lines = data.split(|c| *c == b'\n');
+    let mut pos: usize = 0;
+    let mut next_line = || -> io::Result<String> {
+        let line: &[u8] = lines.next().ok_or_else(|| invalid_data("truncated Radiance HDR header".to_string()))?;
+        pos += line.len() + 1;
+        Ok(String::from_utf8_lossy(line).into_owned())
+    };
+
+    let magic: String = next_line()?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err(invalid_data("not a Radiance HDR file".to_string()));
+    }
+    // Header variables until an empty line
+    loop {
+        let line: String = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported Radiance HDR format '{}'", format)));
+            }
+        }
+    }
+
+    let resolution: String = next_line()?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width): (usize, usize) = match fields[..] {
+        ["-Y", h, "+X", w] => match (h.parse::<usize>(), w.parse::<usize>()) {
+            (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+            _ => return Err(invalid_data(format!("invalid Radiance HDR size '{}'", resolution))),
+        },
+        _ => return Err(invalid_data(format!("unsupported Radiance HDR orientation '{}'", resolution))),
+    };
+
+    // Even run-length encoded, a run of at most 127 pixels takes two bytes
+    // per component, so the data left must be at least a sixteenth of the
+    // pixel count before any of it is reserved
+    let count: usize = width.checked_mul(height)
+        .filter(|count| count / 16 <= data.len().saturating_sub(pos))
+        .ok_or_else(|| invalid_data(format!("Radiance HDR size '{}' is larger than its data", resolution)))?;
+    let mut pixels: Vec<Vec3> = Vec::with_capacity(count);
+    for _ in 0..height {
+        let start: &[u8] = data.get(pos..pos + 4).unwrap_or(&[]);
+        let rle: bool = (8..=0x7fff).contains(&width) && start.len() == 4
+            && start[0] == 2 && start[1] == 2 && ((start[2] as usize) << 8 | start[3] as usize) == width;
+
+        if rle {
+            pos += 4;
+            let line: Vec<u8> = decode_rle_scanline(data, &mut pos, width)?;
+            pixels.extend(line.chunks_exact(4).map(color_from_rgbe));
+        } else {
+            let line: &[u8] = data.get(pos..pos + width * 4)
+                .ok_or_else(|| invalid_data("truncated Radiance HDR data".to_string()))?;
+            pixels.extend(line.chunks_exact(4).map(color_from_rgbe));
+            pos += width * 4;
+        }
+    }
+    Ok(Image::image(width as u32, height as u32, pixels))
+}
+
+// Picks the decoder from the file extension
+pub fn load_image(path: &Path) -> io::Result<Image> {
+    let extension: String = path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let data: Vec<u8> = fs::read(path)?;
+
+    match extension.as_str() {
+        "png" => decode_png(&data),
+        "ppm" => decode_ppm(&data),
+        "hdr" => decode_hdr(&data),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported,
+            format!("unsupported image type '.{}', expected .png, .ppm or .hdr", extension))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::image_writer::*;
+    use crate::hdr_writer::RadianceHdrWriter;
+    use crate::tone_map::ColorPipeline;
+
+    #[test]
+    fn test_decode_ppm() {
+        let ascii: &[u8] = b"P3\n# two pixels\n2 1\n255\n255 0 0\n0 0 255\n";
+        let image: Image = decode_ppm(ascii).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixel(0, 0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixel(1, 0), Vec3::new(0.0, 0.0, 1.0));
+
+        let binary: &[u8] = b"P6 1 1 65535\n\xff\xff\x00\x00\xff\xff";
+        assert_eq!(decode_ppm(binary).unwrap().pixel(0, 0), Vec3::new(1.0, 0.0, 1.0));
+
+        assert!(decode_ppm(b"P6 2 2 255\n\x00\x00").is_err());
+        assert!(decode_ppm(b"P5 1 1 255\n\x00").is_err());
+        // Sizes whose sample count overflows are errors, not panics
+        assert!(decode_ppm(b"P3 70000 70000 255\n1 2 3\n").is_err());
+        assert!(decode_ppm(b"P6 4294967295 4294967295 255\n\x00").is_err());
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        // Pixels written through the color pipeline come back as the same
+        // linear values, up to 8-bit quantization
+        let pixels: Vec<Vec3> = (0..6)
+            .map(|i| Vec3::new((i % 3) as f64 / 2.0, (i / 3) as f64, 0.25))
+            .collect();
+        let mut fb: Framebuffer = Framebuffer::framebuffer(3, 2);
+        fb.set_block(0, 0, 3, &pixels);
+
+        let mut png_data: Vec<u8> = Vec::new();
+        PngWriter { pipeline: ColorPipeline::default() }.write(&fb, &mut png_data).unwrap();
+        let image: Image = decode_png(&png_data).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        for (i, expected) in fb.pixels().iter().enumerate() {
+            assert!((image.pixel(i as u32 % 3, i as u32 / 3) - *expected).length() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_hdr_round_trip() {
+        // Wide rows are run-length encoded, narrow ones are written flat
+        for width in [3, 40] {
+            let pixels: Vec<Vec3> = (0..width * 2)
+                .map(|i| Vec3::new(if i < width { 100.0 } else { 0.0 }, 0.5, (i / 4) as f64 * 0.125))
+                .collect();
+            let mut fb: Framebuffer = Framebuffer::framebuffer(width as u32, 2);
+            fb.set_block(0, 0, width as u32, &pixels);
+
+            let mut hdr_data: Vec<u8> = Vec::new();
+            RadianceHdrWriter {}.write(&fb, &mut hdr_data).unwrap();
+            let image: Image = decode_hdr(&hdr_data).unwrap();
+            assert_eq!((image.width(), image.height()), (width as u32, 2));
+            for (i, expected) in pixels.iter().enumerate() {
+                let pixel: Vec3 = image.pixel((i % width) as u32, (i / width) as u32);
+                assert!((pixel - *expected).length() <= 0.01 * expected.length(), "{:?} vs {:?}", pixel, expected);
+            }
+            assert!(decode_hdr(&hdr_data[..hdr_data.len() - 1]).is_err());
+        }
+
+        assert!(decode_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\x80\x80\x80\x81").is_err());
+        assert!(decode_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x80\x80\x80\x81").is_err());
+        assert_eq!(decode_hdr(b"#?RGBE\n\n-Y 1 +X 1\n\x80\x40\x20\x81").unwrap().pixel(0, 0), Vec3::new(1.0, 0.5, 0.25));
+        assert!(decode_hdr(b"#?RGBE\n\n-Y 4000000000 +X 4000000000\n\x80\x40\x20\x81").is_err());
+        assert!(decode_hdr(b"#?RGBE\n\n-Y 10000000000 +X 10000000000\n\x80\x40\x20\x81").is_err());
+    }
+}
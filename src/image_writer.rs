@@ -0,0 +1,174 @@
+use crate::framebuffer::Framebuffer;
+use crate::hdr_writer::*;
+use crate::tone_map::ColorPipeline;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Encodes a framebuffer into an image file format
+pub trait ImageWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()>;
+}
+
+// ASCII PPM, one "r g b" triple per line
+pub struct PpmAsciiWriter {
+    pub pipeline: ColorPipeline,
+}
+
+impl ImageWriter for PpmAsciiWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n{}", fb.width(), fb.height(), 255)?;
+        for rgb in fb.to_rgb8(&self.pipeline).chunks(3) {
+            writeln!(out, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+        }
+        Ok(())
+    }
+}
+
+// Binary PPM
+pub struct PpmBinaryWriter {
+    pub pipeline: ColorPipeline,
+}
+
+impl ImageWriter for PpmBinaryWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n{}\n", fb.width(), fb.height(), 255)?;
+        out.write_all(&fb.to_rgb8(&self.pipeline))
+    }
+}
+
+// 8-bit RGB PNG
+pub struct PngWriter {
+    pub pipeline: ColorPipeline,
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, fb: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, fb.width(), fb.height());
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&fb.to_rgb8(&self.pipeline)).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    PpmAscii,
+    PpmBinary,
+    Png,
+    // Linear float formats, not tone mapped
+    RadianceHdr,
+    OpenExr { pixel_type: ExrPixelType, compression: ExrCompression },
+}
+
+impl ImageFormat {
+    // Picks the format from a file extension; ".ppm" means binary PPM
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension: String = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::PpmBinary),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::RadianceHdr),
+            "exr" => ImageFormat::from_name("exr"),
+            _ => None,
+        }
+    }
+
+    // Format names accepted on the command line
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "p3" => Some(ImageFormat::PpmAscii),
+            "p6" | "ppm" => Some(ImageFormat::PpmBinary),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::RadianceHdr),
+            "exr" | "exr-half" => Some(ImageFormat::OpenExr {
+                pixel_type: ExrPixelType::Half, compression: ExrCompression::Zip }),
+            "exr-float" => Some(ImageFormat::OpenExr {
+                pixel_type: ExrPixelType::Float, compression: ExrCompression::Zip }),
+            "exr-half-raw" => Some(ImageFormat::OpenExr {
+                pixel_type: ExrPixelType::Half, compression: ExrCompression::None }),
+            "exr-float-raw" => Some(ImageFormat::OpenExr {
+                pixel_type: ExrPixelType::Float, compression: ExrCompression::None }),
+            _ => None,
+        }
+    }
+
+    // The color pipeline is only used by the 8-bit display formats
+    pub fn writer(&self, pipeline: &ColorPipeline) -> Box<dyn ImageWriter> {
+        match self {
+            ImageFormat::PpmAscii => Box::new(PpmAsciiWriter { pipeline: *pipeline }),
+            ImageFormat::PpmBinary => Box::new(PpmBinaryWriter { pipeline: *pipeline }),
+            ImageFormat::Png => Box::new(PngWriter { pipeline: *pipeline }),
+            ImageFormat::RadianceHdr => Box::new(RadianceHdrWriter {}),
+            ImageFormat::OpenExr { pixel_type, compression } =>
+                Box::new(ExrWriter { pixel_type: *pixel_type, compression: *compression }),
+        }
+    }
+}
+
+pub fn save_image(fb: &Framebuffer, path: &Path, format: ImageFormat,
+                  pipeline: &ColorPipeline) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    format.writer(pipeline).write(fb, &mut out)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn two_by_one() -> Framebuffer {
+        let mut fb: Framebuffer = Framebuffer::framebuffer(2, 1);
+        fb.set_block(0, 0, 1, &[Vec3::new(1.0, 0.25, 0.0)]);
+        fb
+    }
+
+    fn encode(format: ImageFormat, fb: &Framebuffer) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        format.writer(&ColorPipeline::default()).write(fb, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_ppm_ascii_writer() {
+        let out: Vec<u8> = encode(ImageFormat::PpmAscii, &two_by_one());
+        assert_eq!(String::from_utf8(out).unwrap(), "P3\n2 1\n255\n255 137 0\n0 0 0\n");
+    }
+
+    #[test]
+    fn test_ppm_binary_writer() {
+        let out: Vec<u8> = encode(ImageFormat::PpmBinary, &two_by_one());
+        let mut expected: Vec<u8> = b"P6\n2 1\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 137, 0, 0, 0, 0]);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_png_writer_round_trip() {
+        let out: Vec<u8> = encode(ImageFormat::Png, &two_by_one());
+
+        let decoder = png::Decoder::new(out.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(&buf[..info.buffer_size()], &[255, 137, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_image_format_from_path() {
+        assert_eq!(ImageFormat::from_path(Path::new("out/final.PNG")), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path(Path::new("final.ppm")), Some(ImageFormat::PpmBinary));
+        assert_eq!(ImageFormat::from_path(Path::new("final")), None);
+        assert_eq!(ImageFormat::from_name("P3"), Some(ImageFormat::PpmAscii));
+        assert_eq!(ImageFormat::from_path(Path::new("beauty.hdr")), Some(ImageFormat::RadianceHdr));
+        assert_eq!(ImageFormat::from_path(Path::new("beauty.exr")),
+            Some(ImageFormat::OpenExr { pixel_type: ExrPixelType::Half, compression: ExrCompression::Zip }));
+    }
+}
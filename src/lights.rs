@@ -0,0 +1,129 @@
+use crate::vec3::Vec3;
+use crate::hittable::Hittable;
+use crate::background::Background;
+use crate::utils::Utils;
+
+// Emissive primitives of a world, sampled directly for next-event
+// estimation, and the background if it is bright enough in some directions
+// to be worth sampling. Lights are borrowed from the world, not copied.
+#[derive(Default)]
+pub struct LightList<'a> {
+    lights: Vec<&'a dyn Hittable>,
+    environment: Option<&'a Background>,
+}
+
+impl<'a> LightList<'a> {
+    pub fn light_list(world: &'a dyn Hittable) -> LightList<'a> {
+        let mut lights: Vec<&'a dyn Hittable> = Vec::new();
+        world.collect_lights(&mut lights);
+        LightList { lights, environment: None }
+    }
+
+    // Adds the background if it can be importance sampled
+    pub fn add_environment(&mut self, background: &'a Background) {
+        if background.is_sampled() {
+            self.environment = Some(background);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty() && self.environment.is_none()
+    }
+
+    // Whether rays leaving the scene found a light
+    pub fn samples_environment(&self) -> bool {
+        self.environment.is_some()
+    }
+
+    fn count(&self) -> usize {
+        self.lights.len() + self.environment.is_some() as usize
+    }
+
+    // Lights are picked uniformly, so the density is the average of theirs
+    pub fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let mut sum: f64 = self.lights.iter().map(|light| light.pdf_value(origin, direction)).sum();
+        if let Some(environment) = self.environment {
+            sum += environment.pdf_value(direction);
+        }
+        sum / self.count() as f64
+    }
+
+    pub fn random(&self, origin: &Vec3) -> Vec3 {
+        let count: usize = self.count();
+        let index: usize = ((Utils::random_double() * count as f64) as usize).min(count - 1);
+        match self.environment {
+            Some(environment) if index == self.lights.len() => environment.random(),
+            _ => self.lights[index].random(origin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::*;
+    use crate::sphere::Sphere;
+    use crate::triangle::Triangle;
+    use crate::quad::Quad;
+
+    fn light() -> Box<Material> {
+        Box::new(Material::DiffuseLight { diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::one()) })
+    }
+
+    fn lit_world() -> HittableList {
+        let mut world: HittableList = HittableList::default();
+        world.add(Box::new(Sphere::sphere(Vec3::new(0.0, 3.0, 0.0), 1.0, light())));
+        world.add(Box::new(Quad::quad(
+            Vec3::new(-1.0, -2.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.5, 3.0), light())));
+        world.add(Box::new(Triangle::triangle(
+            Vec3::new(1.5, -1.0, -1.0), Vec3::new(1.5, 1.0, -1.0), Vec3::new(1.5, 0.0, 1.5), light())));
+        // Not emissive, so not a light
+        world.add(Box::new(Sphere::sphere(Vec3::new(0.0, 0.0, 5.0), 1.0, Box::default())));
+        world.into_bvh()
+    }
+
+    #[test]
+    fn test_light_list_collects_emitters() {
+        let world: HittableList = lit_world();
+        assert_eq!(LightList::light_list(&world).lights.len(), 3);
+        assert!(LightList::light_list(&HittableList::default()).is_empty());
+    }
+
+    // Every light's density must integrate to one over the sphere of
+    // directions, and its samples must land on the light
+    #[test]
+    fn test_light_pdfs_integrate_to_one() {
+        let world: HittableList = lit_world();
+        let lights: LightList = LightList::light_list(&world);
+        let origin: Vec3 = Vec3::new(0.2, 0.1, -0.3);
+        Utils::seed_random(11);
+
+        for light in &lights.lights {
+            for _ in 0..100 {
+                assert!(light.pdf_value(&origin, &light.random(&origin)) > 0.0);
+            }
+
+            let samples: usize = 200_000;
+            let integral: f64 = (0..samples)
+                .map(|_| light.pdf_value(&origin, &Utils::random_unit_vector()))
+                .sum::<f64>() * 4.0 * Utils::pi() / samples as f64;
+            assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        }
+
+        // Also from inside a spherical light
+        let inside: Vec3 = Vec3::new(0.0, 3.2, 0.1);
+        let sphere: &dyn Hittable = *lights.lights.iter()
+            .find(|light| light.pdf_value(&inside, &Vec3::new(0.0, 1.0, 0.0)) > 0.0)
+            .unwrap();
+        let samples: usize = 100_000;
+        let integral: f64 = (0..samples)
+            .map(|_| sphere.pdf_value(&inside, &Utils::random_unit_vector()))
+            .sum::<f64>() * 4.0 * Utils::pi() / samples as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+}
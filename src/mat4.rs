@@ -0,0 +1,268 @@
+// 4x4 matrix and affine transform class
+
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+use crate::aabb::Aabb;
+use std::ops;
+
+// Row-major 4x4 matrix acting on column vectors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    // Constructors
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: &Vec3) -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: &Vec3) -> Mat4 {
+        Mat4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counterclockwise rotation by `degrees` about `axis` (Rodrigues)
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Mat4 {
+        let a: Vec3 = Utils::unit_vector(axis);
+        let (sin_theta, cos_theta): (f64, f64) = Utils::degree_to_radians(degrees).sin_cos();
+        let t: f64 = 1.0 - cos_theta;
+        let (x, y, z): (f64, f64, f64) = (a.x(), a.y(), a.z());
+
+        Mat4::new([
+            [t * x * x + cos_theta, t * x * y - sin_theta * z, t * x * z + sin_theta * y, 0.0],
+            [t * x * y + sin_theta * z, t * y * y + cos_theta, t * y * z - sin_theta * x, 0.0],
+            [t * x * z - sin_theta * y, t * y * z + sin_theta * x, t * z * z + cos_theta, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Math functions
+    pub fn transpose(&self) -> Mat4 {
+        let mut result: Mat4 = *self;
+        for (i, row) in result.m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        result
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a: [[f64; 4]; 4] = self.m;
+        let mut inv: [[f64; 4]; 4] = Mat4::identity().m;
+
+        for col in 0..4 {
+            let pivot: usize = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap_or(col);
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale: f64 = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor: f64 = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4::new(inv))
+    }
+
+    // Determinant of the upper left 3x3 block, i.e. the volume scale
+    pub fn determinant3(&self) -> f64 {
+        let m: &[[f64; 4]; 4] = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Applies the full affine map, including the translation
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m: &[[f64; 4]; 4] = &self.m;
+        Vec3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3])
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m: &[[f64; 4]; 4] = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z())
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut result: Mat4 = Mat4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        result
+    }
+}
+
+// Invertible affine transform, kept together with its inverse so neither
+// has to be recomputed per ray
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    // None if the matrix is singular, e.g. a scale by zero
+    pub fn transform(matrix: Mat4) -> Option<Transform> {
+        Some(Transform { matrix, inverse: matrix.inverse()? })
+    }
+
+    pub fn identity() -> Transform {
+        Transform { matrix: Mat4::identity(), inverse: Mat4::identity() }
+    }
+
+    pub fn translate(offset: &Vec3) -> Transform {
+        Transform { matrix: Mat4::translation(offset), inverse: Mat4::translation(&-*offset) }
+    }
+
+    pub fn scale(factors: &Vec3) -> Option<Transform> {
+        Transform::transform(Mat4::scaling(factors))
+    }
+
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Transform {
+        let matrix: Mat4 = Mat4::rotation(axis, degrees);
+        Transform { matrix, inverse: matrix.transpose() }
+    }
+
+    // Applies `self` first and `next` after it
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform { matrix: next.matrix * self.matrix, inverse: self.inverse * next.inverse }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals go through the inverse transpose to stay perpendicular to the
+    // surface under non-uniform scaling. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    // Box around the eight transformed corners
+    pub fn bounding_box(&self, b: &Aabb) -> Aabb {
+        let mut result: Aabb = Aabb::default();
+        for i in 0..8 {
+            let corner: Vec3 = Vec3::new(
+                if i & 1 == 0 { b.minimum.x() } else { b.maximum.x() },
+                if i & 2 == 0 { b.minimum.y() } else { b.maximum.y() },
+                if i & 4 == 0 { b.minimum.z() } else { b.maximum.z() });
+            result = result.include_point(&self.point(&corner));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (*a - *b).length() < 1e-9
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let m: Mat4 = Mat4::translation(&Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotation(&Vec3::new(1.0, 1.0, 0.0), 33.0)
+            * Mat4::scaling(&Vec3::new(2.0, 0.5, -3.0));
+        let product: Mat4 = m * m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected: f64 = if i == j { 1.0 } else { 0.0 };
+                assert!((product.m[i][j] - expected).abs() < 1e-12);
+            }
+        }
+
+        assert!((m.determinant3() + 3.0).abs() < 1e-12);
+        assert!(Mat4::scaling(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_transform_points_vectors_normals() {
+        let rotate: Transform = Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert!(close(&rotate.vector(&Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 0.0, -1.0)));
+
+        // Scale, then rotate, then move
+        let t: Transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0)).unwrap()
+            .then(&rotate)
+            .then(&Transform::translate(&Vec3::new(0.0, 5.0, 0.0)));
+        assert!(close(&t.point(&Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 5.0, -2.0)));
+        assert!(close(&t.vector(&Vec3::new(1.0, 0.0, 0.0)), &Vec3::new(0.0, 0.0, -2.0)));
+        assert!(close(&t.inverse().point(&Vec3::new(0.0, 5.0, -2.0)), &Vec3::new(1.0, 0.0, 0.0)));
+
+        // The normal of the plane x + y = 0 stays perpendicular to it
+        let tangent: Vec3 = Vec3::new(1.0, -1.0, 0.0);
+        let normal: Vec3 = t.normal(&Vec3::new(1.0, 1.0, 0.0));
+        assert!(normal.dot(&t.vector(&tangent)).abs() < 1e-12);
+
+        let b: Aabb = t.bounding_box(&Aabb::aabb(Vec3::zero(), Vec3::one()));
+        assert!(close(&b.minimum, &Vec3::new(0.0, 5.0, -2.0)));
+        assert!(close(&b.maximum, &Vec3::new(1.0, 6.0, 0.0)));
+    }
+}
@@ -2,27 +2,65 @@ use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::hittable::HitRecord;
 use crate::utils::Utils;
+use crate::texture::*;
+use crate::onb::Onb;
+use crate::microfacet::*;
+use crate::tone_map::luminance;
 
+// A direction sampled by Scatter::scatter. The path throughput is
+// multiplied by f / pdf.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ScatterRecord {
+    // Unit vector pointing away from the surface
+    pub direction: Vec3,
+    // BSDF times the cosine term for `direction`
+    pub f: Vec3,
+    // Density over solid angle of picking `direction`
+    pub pdf: f64,
+    // Delta lobes (mirrors, glass) have no finite f or pdf, so f holds the
+    // weight and pdf is 1. They cannot be reached by light sampling.
+    pub is_specular: bool,
+}
+
+// Directions follow the usual convention: `wo` points back along the
+// incoming ray and `wi` towards where light comes from, both away from
+// the surface and normalized.
 pub trait Scatter {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
         false
     }
+
+    // Radiance given off at the hit point, black for anything but lights
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // BSDF times the cosine term. Zero for delta lobes.
+    fn eval(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // Density with which scatter() returns `wi` for a given `wo`. Zero for
+    // delta lobes.
+    fn pdf(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> f64 {
+        0.0
+    }
 }
 
 // ----------- Lambertian material -----------------
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct LambertianMaterial {
-    albedo: Vec3,
+    albedo: Texture,
 }
 
 impl LambertianMaterial {
-    pub fn lambertian(a: Vec3) -> LambertianMaterial {
+    pub fn lambertian(a: Texture) -> LambertianMaterial {
         LambertianMaterial { albedo: a }
     }
 }
 
 impl Scatter for LambertianMaterial {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: & mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let mut scatter_direction: Vec3 = rec.normal + Utils::random_unit_vector();
 
         // Catch degenerate scatter direction
@@ -30,34 +68,90 @@ impl Scatter for LambertianMaterial {
             scatter_direction = rec.normal;
         }
 
-        *scattered = Ray::ray(rec.p, scatter_direction);
-        *attenuation = self.albedo;
-        true
+        let wi: Vec3 = Utils::unit_vector(&scatter_direction);
+        let wo: Vec3 = -Utils::unit_vector(&r_in.direction());
+        srec.direction = wi;
+        srec.f = self.eval(rec, &wi, &wo);
+        srec.pdf = self.pdf(rec, &wi, &wo);
+        srec.is_specular = false;
+        srec.pdf > 0.0
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(rec, wi, wo)
+    }
+
+    // normal + random_unit_vector() is cosine distributed
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, _wo: &Vec3) -> f64 {
+        rec.normal.dot(wi).max(0.0) / Utils::pi()
     }
 }
 // -----------------------------------------
 
 
 // -------- Metal material -----------------
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct MetalMaterial {
-    albedo: Vec3,
+    albedo: Texture,
     fuzz: f64,
 }
 
 impl MetalMaterial {
-    pub fn metal(a: Vec3, f: f64) -> MetalMaterial {
+    pub fn metal(a: Texture, f: f64) -> MetalMaterial {
         MetalMaterial { albedo: a, fuzz: f }
     }
 }
 
+// A perfect mirror without fuzz. Fuzzy reflection jitters the mirror
+// direction by a random point in a ball of radius `fuzz`, a glossy lobe
+// whose density has a closed form.
 impl Scatter for MetalMaterial {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let reflected: Vec3 = Utils::unit_vector(&r_in.direction()).reflect(rec.normal);
-        *scattered = Ray::ray(rec.p, reflected + self.fuzz * Utils::random_in_unit_shpere());
-        *attenuation = self.albedo;
+        let direction: Vec3 = reflected + self.fuzz * Utils::random_in_unit_shpere();
+
+        srec.direction = Utils::unit_vector(&direction);
+        if self.fuzz == 0.0 {
+            srec.f = self.albedo.value(rec.u, rec.v, &rec.p);
+            srec.pdf = 1.0;
+            srec.is_specular = true;
+        } else {
+            let wo: Vec3 = -Utils::unit_vector(&r_in.direction());
+            srec.f = self.eval(rec, &srec.direction, &wo);
+            srec.pdf = self.pdf(rec, &srec.direction, &wo);
+            srec.is_specular = false;
+        }
+        direction.dot(&rec.normal) > 0.0_f64
+    }
+
+    // Directions below the surface are absorbed, so f / pdf is the albedo
+    // wherever the ray leaves
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        if wi.dot(&rec.normal) <= 0.0 {
+            return Vec3::zero();
+        }
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(rec, wi, wo)
+    }
 
-        scattered.direction().dot(&rec.normal) > 0.0_f64
+    // The jittered point is uniform in the ball around the unit mirror
+    // direction, so the density of its direction is the integral of r^2
+    // along the chord through the ball, over the ball's volume
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        if self.fuzz == 0.0 {
+            return 0.0;
+        }
+        let reflected: Vec3 = (-*wo).reflect(rec.normal);
+        let b: f64 = wi.dot(&reflected);
+        let discriminant: f64 = b * b - (1.0 - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let far: f64 = b + discriminant.sqrt();
+        let near: f64 = (b - discriminant.sqrt()).max(0.0);
+        if far <= 0.0 {
+            return 0.0;
+        }
+        (far.powi(3) - near.powi(3)) / (4.0 * Utils::pi() * self.fuzz.powi(3))
     }
 }
 // -----------------------------------------
@@ -83,9 +177,7 @@ impl DielectricMaterial {
     }
 }
 impl Scatter for DielectricMaterial {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
-        *attenuation = Vec3::one();
-
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let mut refraction_ratio: f64 = self.ir;
         if rec.front_face {
             refraction_ratio = 1.0 / self.ir;
@@ -96,49 +188,572 @@ impl Scatter for DielectricMaterial {
         let sin_theta: f64 = (1.0_f64 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract: bool = refraction_ratio * sin_theta > 1.0_f64;
-        let direction: Vec3;// = Vec3::zero();
+        let direction: Vec3 =
+            if cannot_refract || DielectricMaterial::refractance(cos_theta, refraction_ratio) > Utils::random_double() {
+                unit_direction.reflect(rec.normal)
+            } else {
+                unit_direction.refract(rec.normal, refraction_ratio)
+            };
+
+        srec.direction = Utils::unit_vector(&direction);
+        srec.f = Vec3::one();
+        srec.pdf = 1.0;
+        srec.is_specular = true;
+        true
+    }
+}
+// -----------------------------------------
 
-        if cannot_refract || DielectricMaterial::refractance(cos_theta, refraction_ratio) > Utils::random_double() {
-            direction = unit_direction.reflect(rec.normal);
+// -------- Conductor material -------------
+// Rough metal with a GGX microfacet distribution, colored by the Fresnel
+// reflectance of its complex index of refraction eta + i k
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConductorMaterial {
+    eta: Vec3,
+    k: Vec3,
+    ggx: Ggx,
+}
+
+impl ConductorMaterial {
+    pub fn conductor(eta: Vec3, k: Vec3, roughness: f64) -> ConductorMaterial {
+        ConductorMaterial { eta, k, ggx: Ggx::ggx(roughness) }
+    }
+
+    // Gold, copper, aluminum or silver
+    pub fn preset(name: &str, roughness: f64) -> Option<ConductorMaterial> {
+        CONDUCTOR_PRESETS.iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(_, eta, k)| ConductorMaterial::conductor(
+                Vec3::new(eta[0], eta[1], eta[2]), Vec3::new(k[0], k[1], k[2]), roughness))
+    }
+}
+
+impl Scatter for ConductorMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let wo: Vec3 = frame.world_to_local(&-Utils::unit_vector(&r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if self.ggx.is_smooth() {
+            srec.direction = frame.local(-wo.x(), -wo.y(), wo.z());
+            srec.f = fresnel_conductor(wo.z(), &self.eta, &self.k);
+            srec.pdf = 1.0;
+            srec.is_specular = true;
+            return true;
+        }
+
+        let m: Vec3 = self.ggx.sample_visible_normal(&wo, Utils::random_double(), Utils::random_double());
+        let wi: Vec3 = m * (2.0 * wo.dot(&m)) - wo;
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        let wo: Vec3 = frame.local(wo.x(), wo.y(), wo.z());
+        srec.direction = frame.local(wi.x(), wi.y(), wi.z());
+        srec.f = self.eval(rec, &srec.direction, &wo);
+        srec.pdf = self.pdf(rec, &srec.direction, &wo);
+        srec.is_specular = false;
+        srec.pdf > 0.0
+    }
+
+    // F D G / (4 cos_o): the cosine of wi cancels against the BRDF's
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi, wo): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        if self.ggx.is_smooth() || wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Vec3::zero();
+        }
+
+        let h: Vec3 = Utils::unit_vector(&(wi + wo));
+        fresnel_conductor(wo.dot(&h), &self.eta, &self.k) * (self.ggx.d(&h) * self.ggx.g(&wo, &wi) / (4.0 * wo.z()))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi, wo): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        if self.ggx.is_smooth() || wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let h: Vec3 = Utils::unit_vector(&(wi + wo));
+        self.ggx.visible_normal_pdf(&wo, &h) / (4.0 * wo.dot(&h))
+    }
+}
+// -----------------------------------------
+
+// -------- Rough dielectric material ------
+// Glass with a GGX microfacet surface that both reflects and transmits,
+// choosing between the two by the Fresnel reflectance of the sampled
+// microfacet. Transmitted radiance is scaled by 1 / eta^2 as it is squeezed
+// into a different solid angle.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoughDielectricMaterial {
+    ir: f64,
+    ggx: Ggx,
+}
+
+impl RoughDielectricMaterial {
+    pub fn rough_dielectric(index_of_refraction: f64, roughness: f64) -> RoughDielectricMaterial {
+        RoughDielectricMaterial { ir: index_of_refraction, ggx: Ggx::ggx(roughness) }
+    }
+
+    // Index on the far side of the surface over the one on the side of wo
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face { self.ir } else { 1.0 / self.ir }
+    }
+
+    // Direction of light through a surface with normal m coming from wo,
+    // None on total internal reflection
+    fn refract(wo: &Vec3, m: &Vec3, eta: f64) -> Option<Vec3> {
+        let cos_i: f64 = wo.dot(m);
+        let sin2_t: f64 = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+        if sin2_t >= 1.0 {
+            return None;
+        }
+        let cos_t: f64 = (1.0 - sin2_t).sqrt();
+        Some(-*wo / eta + *m * (cos_i / eta - cos_t))
+    }
+
+    // Microfacet normal that turns wo into wi, and the relative index along
+    // the way (1 for reflection). None for configurations no facet allows.
+    fn half_vector(wi: &Vec3, wo: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        if wi.z() == 0.0 || wo.z() <= 0.0 {
+            return None;
+        }
+        let etap: f64 = if wi.z() > 0.0 { 1.0 } else { eta };
+        let wm: Vec3 = *wi * etap + *wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+        let wm: Vec3 = Utils::unit_vector(&if wm.z() < 0.0 { -wm } else { wm });
+
+        // Back-facing microfacets
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+}
+
+impl Scatter for RoughDielectricMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let wo: Vec3 = frame.world_to_local(&-Utils::unit_vector(&r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let eta: f64 = self.eta(rec);
+
+        let m: Vec3 = if self.ggx.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.ggx.sample_visible_normal(&wo, Utils::random_double(), Utils::random_double())
+        };
+        let reflectance: f64 = fresnel_dielectric(wo.dot(&m), eta);
+
+        let wi: Vec3 = if Utils::random_double() < reflectance {
+            m * (2.0 * wo.dot(&m)) - wo
         } else {
-            direction = unit_direction.refract(rec.normal, refraction_ratio);
+            match RoughDielectricMaterial::refract(&wo, &m, eta) {
+                Some(wi) => wi,
+                None => return false,
+            }
+        };
+
+        srec.direction = Utils::unit_vector(&frame.local(wi.x(), wi.y(), wi.z()));
+        if self.ggx.is_smooth() {
+            // Reflection or transmission was picked with its own probability
+            srec.f = if wi.z() > 0.0 { Vec3::one() } else { Vec3::one() / (eta * eta) };
+            srec.pdf = 1.0;
+            srec.is_specular = true;
+            return true;
         }
 
-        *scattered = Ray::ray(rec.p, direction);
+        let wo: Vec3 = frame.local(wo.x(), wo.y(), wo.z());
+        srec.f = self.eval(rec, &srec.direction, &wo);
+        srec.pdf = self.pdf(rec, &srec.direction, &wo);
+        srec.is_specular = false;
+        srec.pdf > 0.0
+    }
 
-        true
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi, wo): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        let eta: f64 = self.eta(rec);
+        let (wm, etap): (Vec3, f64) = match RoughDielectricMaterial::half_vector(&wi, &wo, eta) {
+            Some(half) if !self.ggx.is_smooth() => half,
+            _ => return Vec3::zero(),
+        };
+
+        let reflectance: f64 = fresnel_dielectric(wo.dot(&wm), eta);
+        let dg: f64 = self.ggx.d(&wm) * self.ggx.g(&wo, &wi);
+        if wi.z() > 0.0 {
+            return Vec3::one() * (reflectance * dg / (4.0 * wo.z()));
+        }
+
+        let denom: f64 = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2) * wo.z();
+        let transmitted: f64 = (1.0 - reflectance) * dg * (wi.dot(&wm) * wo.dot(&wm) / denom).abs();
+        Vec3::one() * (transmitted / (etap * etap))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi, wo): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        let eta: f64 = self.eta(rec);
+        let (wm, etap): (Vec3, f64) = match RoughDielectricMaterial::half_vector(&wi, &wo, eta) {
+            Some(half) if !self.ggx.is_smooth() => half,
+            _ => return 0.0,
+        };
+
+        let reflectance: f64 = fresnel_dielectric(wo.dot(&wm), eta);
+        let visible: f64 = self.ggx.visible_normal_pdf(&wo, &wm);
+        if wi.z() > 0.0 {
+            visible / (4.0 * wo.dot(&wm)) * reflectance
+        } else {
+            let dwm_dwi: f64 = wi.dot(&wm).abs() / (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+            visible * dwm_dwi * (1.0 - reflectance)
+        }
+    }
+}
+// -----------------------------------------
+
+// -------- Principled material ------------
+// Disney's principled BSDF (Burley 2012 and 2015), driven by parameters in
+// [0, 1] that may each be a texture. Scalar parameters read the mean of
+// the texture's channels.
+//
+// It layers a Burley diffuse with sheen, a GGX specular blending from a
+// dielectric highlight to a metallic color, rough glass for transmission
+// and a clearcoat. scatter() picks a single lobe, while eval() and pdf()
+// sum all of them with the same selection probabilities, so light sampling
+// and MIS see the density scatter() actually uses.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PrincipledMaterial {
+    base_color: Texture,
+    metallic: Texture,
+    roughness: Texture,
+    // Dielectric reflectance at normal incidence is 0.08 * specular, which
+    // also sets the index of refraction of transmission
+    specular: Texture,
+    sheen: Texture,
+    sheen_tint: Texture,
+    clearcoat: Texture,
+    clearcoat_gloss: Texture,
+    transmission: Texture,
+}
+
+// Smoother GGX lobes would be deltas, which cannot be mixed with the others
+const PRINCIPLED_MIN_ROUGHNESS: f64 = 0.05;
+
+// The lobes at one hit point, with every texture looked up
+struct PrincipledLobes {
+    base_color: Vec3,
+    sheen: Vec3,
+    specular_color: Vec3,
+    roughness: f64,
+    clearcoat: f64,
+    // Fractions of the material that are diffuse and glass
+    diffuse_weight: f64,
+    glass_weight: f64,
+    specular: Ggx,
+    coat: Ggx,
+    glass: RoughDielectricMaterial,
+    // Probabilities of sampling the diffuse, specular, glass and clearcoat
+    // lobes
+    selection: [f64; 4],
+}
+
+impl PrincipledMaterial {
+    #[allow(clippy::too_many_arguments)]
+    pub fn principled(
+            base_color: Texture,
+            metallic: Texture,
+            roughness: Texture,
+            specular: Texture,
+            sheen: Texture,
+            sheen_tint: Texture,
+            clearcoat: Texture,
+            clearcoat_gloss: Texture,
+            transmission: Texture) -> PrincipledMaterial {
+        PrincipledMaterial {
+            base_color, metallic, roughness, specular, sheen, sheen_tint, clearcoat, clearcoat_gloss, transmission }
+    }
+
+    fn scalar(texture: &Texture, rec: &HitRecord) -> f64 {
+        let value: Vec3 = texture.value(rec.u, rec.v, &rec.p);
+        ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let base_color: Vec3 = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic: f64 = PrincipledMaterial::scalar(&self.metallic, rec);
+        let roughness: f64 = PrincipledMaterial::scalar(&self.roughness, rec).max(PRINCIPLED_MIN_ROUGHNESS);
+        let specular: f64 = PrincipledMaterial::scalar(&self.specular, rec);
+        let sheen_tint: f64 = PrincipledMaterial::scalar(&self.sheen_tint, rec);
+        let clearcoat: f64 = PrincipledMaterial::scalar(&self.clearcoat, rec);
+        let clearcoat_gloss: f64 = PrincipledMaterial::scalar(&self.clearcoat_gloss, rec);
+        let transmission: f64 = PrincipledMaterial::scalar(&self.transmission, rec);
+
+        // Hue of the base color without its brightness
+        let l: f64 = luminance(&base_color);
+        let tint: Vec3 = if l > 0.0 { base_color / l } else { Vec3::one() };
+        let sheen: Vec3 = (Vec3::one() * (1.0 - sheen_tint) + tint * sheen_tint)
+            * PrincipledMaterial::scalar(&self.sheen, rec);
+
+        let f0: f64 = (0.08 * specular).max(1e-4);
+        let ir: f64 = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+
+        let diffuse_weight: f64 = (1.0 - metallic) * (1.0 - transmission);
+        let glass_weight: f64 = (1.0 - metallic) * transmission;
+        let selection: [f64; 4] = [diffuse_weight, 1.0 - glass_weight, glass_weight, 0.25 * clearcoat];
+        let total: f64 = selection.iter().sum();
+
+        PrincipledLobes {
+            base_color,
+            sheen,
+            specular_color: Vec3::one() * (f0 * (1.0 - metallic)) + base_color * metallic,
+            roughness,
+            clearcoat,
+            diffuse_weight,
+            glass_weight,
+            specular: Ggx::ggx(roughness),
+            // Disney's clearcoat alpha runs from 0.1 down to 0.001
+            coat: Ggx::ggx((0.1 + (0.001 - 0.1) * clearcoat_gloss).sqrt().max(PRINCIPLED_MIN_ROUGHNESS)),
+            glass: RoughDielectricMaterial::rough_dielectric(ir, roughness),
+            selection: selection.map(|w| w / total),
+        }
+    }
+
+    fn schlick(f0: Vec3, cos_theta: f64) -> Vec3 {
+        f0 + (Vec3::one() - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+    }
+}
+
+impl Scatter for PrincipledMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let lobes: PrincipledLobes = self.lobes(rec);
+        let frame: Onb = Onb::onb(&rec.normal);
+        let wo: Vec3 = -Utils::unit_vector(&r_in.direction());
+        let wo_local: Vec3 = frame.world_to_local(&wo);
+        if wo_local.z() <= 0.0 {
+            return false;
+        }
+
+        // Reflections that end up below the surface are lost, pdf() only
+        // counts the ones above it
+        let reflect = |ggx: &Ggx| -> Option<Vec3> {
+            let m: Vec3 = ggx.sample_visible_normal(&wo_local, Utils::random_double(), Utils::random_double());
+            let wi: Vec3 = m * (2.0 * wo_local.dot(&m)) - wo_local;
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            Some(frame.local(wi.x(), wi.y(), wi.z()))
+        };
+
+        let u: f64 = Utils::random_double();
+        let [diffuse, specular, glass, _]: [f64; 4] = lobes.selection;
+        let sampled: Option<Vec3> = if u < diffuse {
+            let scatter_direction: Vec3 = rec.normal + Utils::random_unit_vector();
+            Some(if scatter_direction.near_zero() { rec.normal } else { Utils::unit_vector(&scatter_direction) })
+        } else if u < diffuse + specular {
+            reflect(&lobes.specular)
+        } else if u < diffuse + specular + glass {
+            let mut glass_srec: ScatterRecord = ScatterRecord::default();
+            lobes.glass.scatter(r_in, rec, &mut glass_srec).then_some(glass_srec.direction)
+        } else {
+            reflect(&lobes.coat)
+        };
+        let direction: Vec3 = match sampled {
+            Some(direction) => direction,
+            None => return false,
+        };
+
+        srec.direction = direction;
+        srec.f = self.eval(rec, &direction, &wo);
+        srec.pdf = self.pdf(rec, &direction, &wo);
+        srec.is_specular = false;
+        srec.pdf > 0.0
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        let lobes: PrincipledLobes = self.lobes(rec);
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi_local, wo_local): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        if wo_local.z() <= 0.0 {
+            return Vec3::zero();
+        }
+
+        let mut f: Vec3 = Vec3::zero();
+        if lobes.glass_weight > 0.0 {
+            let glass: Vec3 = lobes.glass.eval(rec, wi, wo) * lobes.glass_weight;
+            // Light passing through picks up the base color
+            f += if wi_local.z() < 0.0 { glass * lobes.base_color } else { glass };
+        }
+        if wi_local.z() <= 0.0 {
+            return f;
+        }
+
+        let h: Vec3 = Utils::unit_vector(&(wi_local + wo_local));
+        let cos_d: f64 = wi_local.dot(&h);
+
+        // Burley's diffuse brightens grazing angles on rough surfaces, and
+        // sheen adds a soft rim
+        let fd90: f64 = 0.5 + 2.0 * lobes.roughness * cos_d * cos_d;
+        let fd = |cos: f64| -> f64 { 1.0 + (fd90 - 1.0) * (1.0 - cos).powi(5) };
+        let diffuse: Vec3 = lobes.base_color * (fd(wi_local.z()) * fd(wo_local.z()) / Utils::pi())
+            + lobes.sheen * (1.0 - cos_d).powi(5);
+        f += diffuse * (lobes.diffuse_weight * wi_local.z());
+
+        // The cosine of wi cancels against the microfacet denominator
+        let specular: f64 = lobes.specular.d(&h) * lobes.specular.g(&wo_local, &wi_local) / (4.0 * wo_local.z());
+        f += PrincipledMaterial::schlick(lobes.specular_color, cos_d) * (specular * (1.0 - lobes.glass_weight));
+
+        if lobes.clearcoat > 0.0 {
+            let coat: f64 = lobes.coat.d(&h) * lobes.coat.g(&wo_local, &wi_local) / (4.0 * wo_local.z());
+            f += PrincipledMaterial::schlick(Vec3::one() * 0.04, cos_d) * (0.25 * lobes.clearcoat * coat);
+        }
+        f
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        let lobes: PrincipledLobes = self.lobes(rec);
+        let frame: Onb = Onb::onb(&rec.normal);
+        let (wi_local, wo_local): (Vec3, Vec3) = (frame.world_to_local(wi), frame.world_to_local(wo));
+        if wo_local.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let [diffuse, specular, glass, coat]: [f64; 4] = lobes.selection;
+        let mut pdf: f64 = 0.0;
+        if glass > 0.0 {
+            pdf += glass * lobes.glass.pdf(rec, wi, wo);
+        }
+        if wi_local.z() <= 0.0 {
+            return pdf;
+        }
+
+        let h: Vec3 = Utils::unit_vector(&(wi_local + wo_local));
+        let reflection = |ggx: &Ggx| -> f64 { ggx.visible_normal_pdf(&wo_local, &h) / (4.0 * wo_local.dot(&h)) };
+        pdf += diffuse * wi_local.z() / Utils::pi();
+        pdf += specular * reflection(&lobes.specular);
+        if coat > 0.0 {
+            pdf += coat * reflection(&lobes.coat);
+        }
+        pdf
+    }
+}
+// -----------------------------------------
+
+// -------- Diffuse light material ---------
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DiffuseLightMaterial {
+    emit: Vec3,
+}
+
+impl DiffuseLightMaterial {
+    pub fn diffuse_light(emit: Vec3) -> DiffuseLightMaterial {
+        DiffuseLightMaterial { emit }
+    }
+}
+
+// Emits the same radiance in every direction from both sides and absorbs
+// everything that hits it
+impl Scatter for DiffuseLightMaterial {
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        self.emit
     }
 }
 // -----------------------------------------
 
+// -------- Isotropic material ------------
+// Phase function of a participating medium: scatters equally in every
+// direction, with no surface or cosine term. Glowing media also emit at
+// the points where rays collide with them.
 #[derive(Clone, PartialEq, Debug)]
+pub struct IsotropicMaterial {
+    albedo: Texture,
+    emit: Vec3,
+}
+
+impl IsotropicMaterial {
+    pub fn isotropic(a: Texture) -> IsotropicMaterial {
+        IsotropicMaterial { albedo: a, emit: Vec3::zero() }
+    }
+
+    pub fn isotropic_emissive(a: Texture, emit: Vec3) -> IsotropicMaterial {
+        IsotropicMaterial { albedo: a, emit }
+    }
+}
+
+impl Scatter for IsotropicMaterial {
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+        self.emit
+    }
+
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.direction = Utils::random_unit_vector();
+        srec.f = self.eval(rec, &srec.direction, &Vec3::zero());
+        srec.pdf = self.pdf(rec, &srec.direction, &Vec3::zero());
+        srec.is_specular = false;
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(rec, wi, wo)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, _wi: &Vec3, _wo: &Vec3) -> f64 {
+        1.0 / (4.0 * Utils::pi())
+    }
+}
+// -----------------------------------------
+
+#[derive(Clone, PartialEq, Debug, Default)]
 pub enum Material {
     Lambertian { lambertian: LambertianMaterial },
     Metal { metal: MetalMaterial },
     Dielectric { dielectric: DielectricMaterial },
+    DiffuseLight { diffuse_light: DiffuseLightMaterial },
+    Isotropic { isotropic: IsotropicMaterial },
+    Conductor { conductor: ConductorMaterial },
+    RoughDielectric { rough_dielectric: RoughDielectricMaterial },
+    // Boxed since its nine textures would make every material as large
+    Principled { principled: Box<PrincipledMaterial> },
+    #[default]
     Default,
 }
 
-impl Default for Material {
-    fn default() -> Self {
-        Material::Default
-    }
-}
-
 impl Scatter for Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         // Material should not be default
         assert_ne!(*self, Material::Default);
 
         match self {
             Material::Lambertian { lambertian } => {
-                lambertian.scatter(r_in, rec, attenuation, scattered)
+                lambertian.scatter(r_in, rec, srec)
             },
             Material::Metal { metal } => {
-                metal.scatter(r_in, rec, attenuation, scattered)
+                metal.scatter(r_in, rec, srec)
             },
             Material::Dielectric { dielectric } => {
-                dielectric.scatter(r_in, rec, attenuation, scattered)
+                dielectric.scatter(r_in, rec, srec)
+            },
+            Material::DiffuseLight { diffuse_light } => {
+                diffuse_light.scatter(r_in, rec, srec)
+            },
+            Material::Isotropic { isotropic } => {
+                isotropic.scatter(r_in, rec, srec)
+            },
+            Material::Conductor { conductor } => {
+                conductor.scatter(r_in, rec, srec)
+            },
+            Material::RoughDielectric { rough_dielectric } => {
+                rough_dielectric.scatter(r_in, rec, srec)
+            },
+            Material::Principled { principled } => {
+                principled.scatter(r_in, rec, srec)
             },
             Material::Default => {
                 // Should be unreachable given the assert at the start of the function,
@@ -147,4 +762,216 @@ impl Scatter for Material {
             }
         }
     }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        match self {
+            Material::DiffuseLight { diffuse_light } => diffuse_light.emitted(r_in, rec),
+            Material::Isotropic { isotropic } => isotropic.emitted(r_in, rec),
+            _ => Vec3::zero(),
+        }
+    }
+
+    fn eval(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> Vec3 {
+        match self {
+            Material::Lambertian { lambertian } => lambertian.eval(rec, wi, wo),
+            Material::Metal { metal } => metal.eval(rec, wi, wo),
+            Material::Isotropic { isotropic } => isotropic.eval(rec, wi, wo),
+            Material::Conductor { conductor } => conductor.eval(rec, wi, wo),
+            Material::RoughDielectric { rough_dielectric } => rough_dielectric.eval(rec, wi, wo),
+            Material::Principled { principled } => principled.eval(rec, wi, wo),
+            _ => Vec3::zero(),
+        }
+    }
+
+    fn pdf(&self, rec: &HitRecord, wi: &Vec3, wo: &Vec3) -> f64 {
+        match self {
+            Material::Lambertian { lambertian } => lambertian.pdf(rec, wi, wo),
+            Material::Metal { metal } => metal.pdf(rec, wi, wo),
+            Material::Isotropic { isotropic } => isotropic.pdf(rec, wi, wo),
+            Material::Conductor { conductor } => conductor.pdf(rec, wi, wo),
+            Material::RoughDielectric { rough_dielectric } => rough_dielectric.pdf(rec, wi, wo),
+            Material::Principled { principled } => principled.pdf(rec, wi, wo),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Material {
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::DiffuseLight { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit_record() -> (Ray, HitRecord) {
+        let r: Ray = Ray::ray(Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0));
+        let mut rec: HitRecord = HitRecord::default();
+        rec.set_face_normal(&r, &Vec3::new(0.0, 1.0, 0.0));
+        (r, rec)
+    }
+
+    #[test]
+    fn test_material_lambertian_scatter_record() {
+        let (r, rec): (Ray, HitRecord) = hit_record();
+        let albedo: Vec3 = Vec3::new(0.2, 0.5, 0.8);
+        let material: Material = Material::Lambertian { lambertian: LambertianMaterial::lambertian(albedo.into()) };
+        let wo: Vec3 = -Utils::unit_vector(&r.direction());
+
+        // The record agrees with eval and pdf, and the f / pdf estimator of
+        // the reflected energy comes out at the albedo
+        let n: u32 = 10_000;
+        let mut sum: Vec3 = Vec3::zero();
+        for _ in 0..n {
+            let mut srec: ScatterRecord = ScatterRecord::default();
+            assert!(material.scatter(&r, &rec, &mut srec));
+            assert!(!srec.is_specular);
+            assert!((srec.direction.length() - 1.0).abs() < 1e-9);
+            assert!((srec.pdf - material.pdf(&rec, &srec.direction, &wo)).abs() < 1e-12);
+            assert_eq!(srec.f, material.eval(&rec, &srec.direction, &wo));
+            sum += srec.f / srec.pdf;
+        }
+        let mean: Vec3 = sum / n as f64;
+        assert!((mean - albedo).length() < 1e-9);
+
+        let below: Vec3 = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(material.pdf(&rec, &below, &wo), 0.0);
+        assert_eq!(material.eval(&rec, &below, &wo), Vec3::zero());
+    }
+
+    #[test]
+    fn test_material_specular_scatter_record() {
+        let (r, rec): (Ray, HitRecord) = hit_record();
+        let materials: [Material; 2] = [
+            Material::Metal { metal: MetalMaterial::metal(Vec3::new(0.9, 0.8, 0.7).into(), 0.0) },
+            Material::Dielectric { dielectric: DielectricMaterial::dielectric(1.5) },
+        ];
+
+        for material in materials.iter() {
+            let mut srec: ScatterRecord = ScatterRecord::default();
+            assert!(material.scatter(&r, &rec, &mut srec));
+            assert!(srec.is_specular);
+            assert_eq!(srec.pdf, 1.0);
+            assert_eq!(material.pdf(&rec, &srec.direction, &-r.direction()), 0.0);
+        }
+
+        // A perfect mirror sends the ray straight back out
+        let mut srec: ScatterRecord = ScatterRecord::default();
+        assert!(materials[0].scatter(&r, &rec, &mut srec));
+        assert!((srec.direction - Utils::unit_vector(&Vec3::new(-1.0, 1.0, 0.0))).length() < 1e-12);
+        assert_eq!(srec.f, Vec3::new(0.9, 0.8, 0.7));
+    }
+
+    // The f / pdf estimator of the scattered energy agrees with integrating
+    // eval over all directions, which only holds if scatter() draws
+    // directions with the density pdf() reports
+    fn assert_sampling_matches_pdf(material: &Material, r: &Ray, rec: &HitRecord) {
+        let wo: Vec3 = -Utils::unit_vector(&r.direction());
+        let n: u32 = 200_000;
+        let mut sampled: Vec3 = Vec3::zero();
+        let mut integrated: Vec3 = Vec3::zero();
+        let mut pdf_integral: f64 = 0.0;
+        for _ in 0..n {
+            let mut srec: ScatterRecord = ScatterRecord::default();
+            if material.scatter(r, rec, &mut srec) {
+                assert!(!srec.is_specular);
+                assert!((srec.pdf - material.pdf(rec, &srec.direction, &wo)).abs() <= 1e-9 * srec.pdf);
+                sampled += srec.f / srec.pdf;
+            }
+
+            // Uniform sphere directions, density 1 / 4 pi
+            let wi: Vec3 = Utils::random_unit_vector();
+            integrated += material.eval(rec, &wi, &wo) * 4.0 * Utils::pi();
+            pdf_integral += material.pdf(rec, &wi, &wo) * 4.0 * Utils::pi();
+        }
+        let (sampled, integrated): (Vec3, Vec3) = (sampled / n as f64, integrated / n as f64);
+        assert!((sampled - integrated).length() < 0.03 * integrated.length(), "{:?} vs {:?}", sampled, integrated);
+        assert!(pdf_integral / n as f64 <= 1.02);
+    }
+
+    // An oblique ray hitting a surface from outside and from inside
+    fn oblique_hits() -> (Ray, HitRecord, HitRecord) {
+        let r: Ray = Ray::ray(Vec3::new(0.6, 1.0, 0.2), Vec3::new(-0.6, -1.0, -0.2));
+        let mut front: HitRecord = HitRecord::default();
+        front.set_face_normal(&r, &Vec3::new(0.0, 1.0, 0.0));
+        let mut back: HitRecord = HitRecord::default();
+        back.set_face_normal(&r, &Vec3::new(0.0, -1.0, 0.0));
+        (r, front, back)
+    }
+
+    #[test]
+    fn test_material_fuzzy_metal_sampling_matches_pdf() {
+        // Glossy at any fuzz above zero, also when the ball around the
+        // mirror direction reaches past the hit point
+        let (r, front, _): (Ray, HitRecord, HitRecord) = oblique_hits();
+        for fuzz in [0.3, 1.5] {
+            let material: Material = Material::Metal { metal: MetalMaterial::metal(Vec3::new(0.9, 0.8, 0.7).into(), fuzz) };
+            assert_sampling_matches_pdf(&material, &r, &front);
+
+            // Over all directions, below the surface too, the density
+            // integrates to one
+            let wo: Vec3 = -Utils::unit_vector(&r.direction());
+            let n: u32 = 200_000;
+            let total: f64 = (0..n).map(|_| material.pdf(&front, &Utils::random_unit_vector(), &wo)).sum::<f64>()
+                * 4.0 * Utils::pi() / n as f64;
+            assert!((total - 1.0).abs() < 0.02, "fuzz {} gave {}", fuzz, total);
+        }
+    }
+
+    #[test]
+    fn test_material_microfacet_sampling_matches_pdf() {
+        let (r, front, back): (Ray, HitRecord, HitRecord) = oblique_hits();
+        let wo: Vec3 = -Utils::unit_vector(&r.direction());
+
+        let gold: Material = Material::Conductor { conductor: ConductorMaterial::preset("gold", 0.5).unwrap() };
+        let glass: Material = Material::RoughDielectric { rough_dielectric: RoughDielectricMaterial::rough_dielectric(1.5, 0.4) };
+        Utils::seed_random(21);
+
+        for (material, rec) in [(&gold, &front), (&glass, &front), (&glass, &back)] {
+            assert_sampling_matches_pdf(material, &r, rec);
+        }
+
+        // Without roughness the glass is a perfect delta lobe again
+        let smooth: Material = Material::RoughDielectric { rough_dielectric: RoughDielectricMaterial::rough_dielectric(1.5, 0.0) };
+        let mut srec: ScatterRecord = ScatterRecord::default();
+        assert!(smooth.scatter(&r, &front, &mut srec));
+        assert!(srec.is_specular);
+        assert_eq!(smooth.pdf(&front, &srec.direction, &wo), 0.0);
+    }
+
+    #[test]
+    fn test_material_principled() {
+        let (r, front, back): (Ray, HitRecord, HitRecord) = oblique_hits();
+        let wo: Vec3 = -Utils::unit_vector(&r.direction());
+        let principled = |metallic: f64, roughness: f64, sheen: f64, clearcoat: f64, transmission: f64| -> Material {
+            Material::Principled { principled: Box::new(PrincipledMaterial::principled(
+                Vec3::new(0.8, 0.4, 0.2).into(), metallic.into(), roughness.into(), 0.5.into(),
+                sheen.into(), 0.5.into(), clearcoat.into(), 0.8.into(), transmission.into())) }
+        };
+        Utils::seed_random(22);
+
+        // Every lobe on its own, then all of them layered
+        let plastic: Material = principled(0.0, 0.3, 0.0, 0.0, 0.0);
+        let metal: Material = principled(1.0, 0.4, 0.0, 0.0, 0.0);
+        let velvet: Material = principled(0.0, 0.8, 1.0, 0.0, 0.0);
+        let coated: Material = principled(0.3, 0.5, 0.0, 1.0, 0.0);
+        let layered: Material = principled(0.2, 0.4, 0.5, 0.5, 0.5);
+        for (material, rec) in [(&plastic, &front), (&metal, &front), (&velvet, &front), (&coated, &front),
+                                (&layered, &front), (&layered, &back)] {
+            assert_sampling_matches_pdf(material, &r, rec);
+        }
+
+        // Full transmission with the default specular is rough glass of
+        // index 1.5, tinted by the base color on the way through
+        let glass: Material = Material::RoughDielectric { rough_dielectric: RoughDielectricMaterial::rough_dielectric(1.5, 0.4) };
+        let clear: Material = principled(0.0, 0.4, 0.0, 0.0, 1.0);
+        for _ in 0..100 {
+            let wi: Vec3 = Utils::random_unit_vector();
+            let tint: Vec3 = if wi.y() < 0.0 { Vec3::new(0.8, 0.4, 0.2) } else { Vec3::one() };
+            assert!((clear.eval(&front, &wi, &wo) - glass.eval(&front, &wi, &wo) * tint).length() < 1e-9);
+            assert!((clear.pdf(&front, &wi, &wo) - glass.pdf(&front, &wi, &wo)).abs() < 1e-9);
+        }
+    }
 }
@@ -0,0 +1,434 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::*;
+use crate::texture::Texture;
+use crate::aabb::Aabb;
+use crate::voxel_grid::VoxelGrid;
+use std::sync::Arc;
+
+// Distance a ray travels through a medium of the given density before it
+// scatters, sampled with density proportional to the transmittance
+pub fn sample_free_flight(density: f64) -> f64 {
+    -(1.0 - Utils::random_double()).ln() / density
+}
+
+fn isotropic(albedo: Texture) -> Box<Material> {
+    Box::new(Material::Isotropic { isotropic: IsotropicMaterial::isotropic(albedo) })
+}
+
+// -------- Constant medium ----------------
+// Smoke-like volume of constant density filling a boundary. The boundary
+// must be convex: a ray is assumed to enter and leave it at most once.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f64,
+    phase_function: Box<Material>,
+}
+
+impl ConstantMedium {
+    pub fn constant_medium(boundary: Box<dyn Hittable>, density: f64, albedo: Texture) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function: isotropic(albedo),
+        }
+    }
+
+    // Where the whole line enters and leaves the boundary, clipped to the
+    // queried interval
+    fn inside(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut rec1: HitRecord = HitRecord::default();
+        let mut rec2: HitRecord = HitRecord::default();
+
+        if !self.boundary.hit(r, -Utils::infinity(), Utils::infinity(), &mut rec1) {
+            return None;
+        }
+        if !self.boundary.hit(r, rec1.t + 0.0001, Utils::infinity(), &mut rec2) {
+            return None;
+        }
+
+        let t_enter: f64 = rec1.t.max(t_min).max(0.0);
+        let t_exit: f64 = rec2.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+        Some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t_enter, t_exit): (f64, f64) = match self.inside(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let ray_length: f64 = r.direction().length();
+        let distance_inside_boundary: f64 = (t_exit - t_enter) * ray_length;
+        let hit_distance: f64 = sample_free_flight(self.density);
+
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = r.point_at_parameter(rec.t);
+
+        // Arbitrary, the phase function does not look at them
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.mat_ptr = self.phase_function.clone();
+
+        true
+    }
+
+    fn hit_surface(&self, _r: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.inside(r, t_min, t_max) {
+            Some((t_enter, t_exit)) => (-self.density * (t_exit - t_enter) * r.direction().length()).exp(),
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(output_box)
+    }
+}
+// -----------------------------------------
+
+// -------- Fog ----------------------------
+// Homogeneous medium filling the space between surfaces. Rays that leave
+// the scene reach the background unattenuated, as if the fog ended behind
+// the last surface.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Fog {
+    density: f64,
+    albedo: Vec3,
+}
+
+impl Fog {
+    pub fn fog(density: f64, albedo: Vec3) -> Fog {
+        Fog { density, albedo }
+    }
+
+    // Fraction of light that makes it across `distance`
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        (-self.density * distance).exp()
+    }
+
+    // Turns `rec` into a scattering event in the fog if the ray scatters
+    // before reaching the surface it hit, if any
+    pub fn scatter_before(&self, r: &Ray, hit_anything: bool, rec: &mut HitRecord) -> bool {
+        let ray_length: f64 = r.direction().length();
+        let distance: f64 = sample_free_flight(self.density);
+        if !hit_anything || distance >= rec.t * ray_length {
+            return false;
+        }
+
+        rec.t = distance / ray_length;
+        rec.p = r.point_at_parameter(rec.t);
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.mat_ptr = isotropic(self.albedo.into());
+
+        true
+    }
+}
+// -----------------------------------------
+
+// -------- Color maps ---------------------
+// Turns the values of a grid channel into colors
+#[derive(Clone, PartialEq, Debug)]
+pub enum ColorMap {
+    // Piecewise linear between (value, color) stops sorted by value, and
+    // constant past either end
+    Ramp { stops: Vec<(f64, Vec3)> },
+    // Values are temperatures in kelvin, see blackbody()
+    Blackbody,
+}
+
+impl ColorMap {
+    pub fn constant(color: Vec3) -> ColorMap {
+        ColorMap::Ramp { stops: vec![(0.0, color)] }
+    }
+
+    // None without stops or if they are out of order
+    pub fn ramp(stops: Vec<(f64, Vec3)>) -> Option<ColorMap> {
+        if stops.is_empty() || stops.windows(2).any(|w| w[1].0 < w[0].0) {
+            return None;
+        }
+        Some(ColorMap::Ramp { stops })
+    }
+
+    pub fn value(&self, x: f64) -> Vec3 {
+        match self {
+            ColorMap::Ramp { stops } => {
+                let next: usize = stops.partition_point(|(value, _)| *value <= x);
+                if next == 0 {
+                    return stops[0].1;
+                }
+                if next == stops.len() {
+                    return stops[next - 1].1;
+                }
+                let (x0, c0): (f64, Vec3) = stops[next - 1];
+                let (x1, c1): (f64, Vec3) = stops[next];
+                let s: f64 = (x - x0) / (x1 - x0);
+                c0 * (1.0 - s) + c1 * s
+            },
+            ColorMap::Blackbody => blackbody(x),
+        }
+    }
+}
+
+// Planck's law up to a constant factor, wavelength in nanometers
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+    let lambda: f64 = wavelength * 1e-3;
+    1.0 / (lambda.powi(5) * ((1.4388e4 / (lambda * temperature)).exp_m1()))
+}
+
+// Radiance of a black body at `temperature` kelvin, measured at one
+// wavelength per channel and relative to a body at 6500 K, which comes out
+// white. Brightness rises steeply with temperature, as it does in reality.
+pub fn blackbody(temperature: f64) -> Vec3 {
+    if temperature <= 0.0 {
+        return Vec3::zero();
+    }
+    let channel = |wavelength: f64| planck(wavelength, temperature) / planck(wavelength, 6500.0);
+    Vec3::new(channel(610.0), channel(550.0), channel(465.0))
+}
+// -----------------------------------------
+
+// -------- Grid medium --------------------
+// Glow of a grid medium: `map` turns the channel's values into radiance,
+// which is then multiplied by `scale`
+#[derive(Clone, PartialEq, Debug)]
+pub struct GridEmission {
+    grid: Arc<VoxelGrid>,
+    map: ColorMap,
+    scale: f64,
+}
+
+impl GridEmission {
+    pub fn grid_emission(grid: Arc<VoxelGrid>, map: ColorMap, scale: f64) -> GridEmission {
+        GridEmission { grid, map, scale }
+    }
+}
+
+// Heterogeneous medium such as a cloud or an explosion, with its density
+// given by a voxel grid stretched over `bounds`. Collisions are found by
+// delta tracking and shadow rays are dimmed by ratio tracking, both against
+// the largest density in the grid.
+//
+// The albedo is looked up from the density channel. Only the absorbed part
+// of the medium glows, so emissive media need an albedo below one.
+pub struct GridMedium {
+    density: Arc<VoxelGrid>,
+    density_scale: f64,
+    albedo: ColorMap,
+    emission: Option<GridEmission>,
+    bounds: Aabb,
+    majorant: f64,
+}
+
+impl GridMedium {
+    pub fn grid_medium(density: Arc<VoxelGrid>, density_scale: f64, albedo: ColorMap,
+                       emission: Option<GridEmission>, bounds: Aabb) -> Self {
+        let majorant: f64 = density.max_value() * density_scale;
+        GridMedium { density, density_scale, albedo, emission, bounds, majorant }
+    }
+
+    // Unit cube coordinates of a point inside the bounds
+    fn grid_point(&self, p: &Vec3) -> Vec3 {
+        let extent: Vec3 = self.bounds.extent();
+        let offset: Vec3 = *p - self.bounds.minimum;
+        Vec3::new(offset.x() / extent.x(), offset.y() / extent.y(), offset.z() / extent.z())
+    }
+
+    // Calls `collide` with the position and density value at every
+    // tentative collision inside the bounds, until it returns false
+    fn track(&self, r: &Ray, t_min: f64, t_max: f64, mut collide: impl FnMut(f64, &Vec3, f64) -> bool) {
+        if self.majorant <= 0.0 {
+            return;
+        }
+        let (mut t, t_exit): (f64, f64) = match self.bounds.intersect(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        let ray_length: f64 = r.direction().length();
+        loop {
+            t += sample_free_flight(self.majorant) / ray_length;
+            if t >= t_exit {
+                return;
+            }
+            let g: Vec3 = self.grid_point(&r.point_at_parameter(t));
+            if !collide(t, &g, self.density.sample(&g)) {
+                return;
+            }
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    // Delta tracking: a tentative collision is real with probability
+    // density / majorant, and is otherwise skipped
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut collision: Option<(f64, Vec3, f64)> = None;
+        self.track(r, t_min, t_max, |t, g, value| {
+            if Utils::random_double() * self.majorant < value * self.density_scale {
+                collision = Some((t, *g, value));
+                return false;
+            }
+            true
+        });
+
+        let (t, g, value): (f64, Vec3, f64) = match collision {
+            Some(collision) => collision,
+            None => return false,
+        };
+
+        let albedo: Vec3 = self.albedo.value(value);
+        let emit: Vec3 = match &self.emission {
+            Some(emission) => (Vec3::one() - albedo) * emission.map.value(emission.grid.sample(&g)) * emission.scale,
+            None => Vec3::zero(),
+        };
+
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        *rec.mat_ptr = Material::Isotropic {
+            isotropic: IsotropicMaterial::isotropic_emissive(albedo.into(), emit) };
+
+        true
+    }
+
+    fn hit_surface(&self, _r: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    // Ratio tracking: every tentative collision keeps the fraction of the
+    // majorant that is not real density
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance: f64 = 1.0;
+        self.track(r, t_min, t_max, |_, _, value| {
+            transmittance *= 1.0 - value * self.density_scale / self.majorant;
+            transmittance > 0.0
+        });
+        transmittance
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bounds;
+        true
+    }
+}
+// -----------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_constant_medium_transmittance() {
+        // The fraction of rays crossing a unit sphere unscattered matches
+        // exp(-density * 2)
+        let density: f64 = 0.7;
+        let medium: ConstantMedium = ConstantMedium::constant_medium(
+            Box::new(Sphere::sphere(Vec3::zero(), 1.0, Box::default())), density, Vec3::one().into());
+        let r: Ray = Ray::ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+
+        let n: u32 = 100_000;
+        let mut passed: u32 = 0;
+        let mut rec: HitRecord = HitRecord::default();
+        for _ in 0..n {
+            if medium.hit(&r, 0.001, Utils::infinity(), &mut rec) {
+                assert!(rec.p.length() <= 1.0 + 1e-9);
+                assert!(matches!(*rec.mat_ptr, Material::Isotropic { .. }));
+            } else {
+                passed += 1;
+            }
+        }
+        let expected: f64 = (-density * 2.0).exp();
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.01);
+
+        // From inside only the remaining distance counts
+        let inside: Ray = Ray::ray(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let n_inside: u32 = (0..n).filter(|_| !medium.hit(&inside, 0.001, Utils::infinity(), &mut rec)).count() as u32;
+        assert!((n_inside as f64 / n as f64 - (-density).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_grid_medium_tracking() {
+        // Density rises from 0 to 2 across the middle half of the box, so
+        // the optical depth along x is 1
+        let grid: Arc<VoxelGrid> = Arc::new(VoxelGrid::voxel_grid(2, 1, 1, vec![0.0, 1.0]));
+        let medium: GridMedium = GridMedium::grid_medium(
+            grid, 2.0, ColorMap::constant(Vec3::one()), None, Aabb::aabb(Vec3::zero(), Vec3::one()));
+        let r: Ray = Ray::ray(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let expected: f64 = (-1.0_f64).exp();
+
+        let n: u32 = 100_000;
+        let mut passed: u32 = 0;
+        let mut ratio: f64 = 0.0;
+        let mut rec: HitRecord = HitRecord::default();
+        for _ in 0..n {
+            if medium.hit(&r, 0.001, Utils::infinity(), &mut rec) {
+                assert!(rec.p.x() > 0.25 && rec.p.x() < 1.0);
+            } else {
+                passed += 1;
+            }
+            ratio += medium.transmittance(&r, 0.001, Utils::infinity());
+        }
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.01);
+        assert!((ratio / n as f64 - expected).abs() < 0.01);
+
+        let mut ignored: HitRecord = HitRecord::default();
+        assert!(!medium.hit_surface(&r, 0.001, Utils::infinity(), &mut ignored));
+        assert_eq!(medium.transmittance(&Ray::ray(Vec3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, 0.0, 0.0)), 0.001, 10.0), 1.0);
+    }
+
+    #[test]
+    fn test_color_maps() {
+        let ramp: ColorMap = ColorMap::ramp(vec![(1.0, Vec3::zero()), (3.0, Vec3::new(2.0, 4.0, 0.0))]).unwrap();
+        assert_eq!(ramp.value(0.0), Vec3::zero());
+        assert_eq!(ramp.value(2.0), Vec3::new(1.0, 2.0, 0.0));
+        assert_eq!(ramp.value(5.0), Vec3::new(2.0, 4.0, 0.0));
+        assert!(ColorMap::ramp(vec![(1.0, Vec3::zero()), (0.0, Vec3::one())]).is_none());
+
+        assert!((blackbody(6500.0) - Vec3::one()).length() < 1e-12);
+        let ember: Vec3 = blackbody(1500.0);
+        assert!(ember.r() > ember.g() && ember.g() > ember.b() && ember.r() < 1e-3);
+        assert_eq!(ColorMap::Blackbody.value(0.0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_fog_scatters_before_surfaces() {
+        let fog: Fog = Fog::fog(0.5, Vec3::one());
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+
+        let n: u32 = 100_000;
+        let mut scattered: u32 = 0;
+        for _ in 0..n {
+            let mut rec: HitRecord = HitRecord { t: 2.0, ..Default::default() };
+            if fog.scatter_before(&r, true, &mut rec) {
+                assert!(rec.t < 2.0);
+                scattered += 1;
+            }
+            assert!(!fog.scatter_before(&r, false, &mut rec));
+        }
+        assert!((1.0 - scattered as f64 / n as f64 - fog.transmittance(2.0)).abs() < 0.01);
+    }
+}
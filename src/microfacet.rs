@@ -0,0 +1,221 @@
+// GGX (Trowbridge-Reitz) microfacet distribution and Fresnel terms.
+//
+// Everything here works in a local shading frame with the macro surface
+// normal along +z.
+
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+
+// Below this alpha a surface is treated as perfectly smooth, since the
+// distribution is too peaked to evaluate reliably
+pub const SMOOTH_ALPHA: f64 = 1e-3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // `roughness` is perceptually linear, the width of the distribution
+    // is its square
+    pub fn ggx(roughness: f64) -> Ggx {
+        Ggx { alpha: (roughness * roughness).max(1e-4) }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    // Density of microfacet normals m, projected onto the macro surface
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let cos2: f64 = m.z() * m.z();
+        let tan2: f64 = (1.0 - cos2) / cos2;
+        let a2: f64 = self.alpha * self.alpha;
+        let t: f64 = 1.0 + tan2 / a2;
+        1.0 / (Utils::pi() * a2 * cos2 * cos2 * t * t)
+    }
+
+    // Smith auxiliary function
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2: f64 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2: f64 = (1.0 - cos2) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    // Fraction of microfacets visible from w
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated masking-shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals sample_visible_normal() returns for wo. Facets
+    // turned away from wo are never seen.
+    pub fn visible_normal_pdf(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        if wo.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / wo.z().abs() * self.d(m) * wo.dot(m).max(0.0)
+    }
+
+    // Samples a microfacet normal visible from wo, which must be above
+    // the surface (Heitz 2018, "Sampling the GGX Distribution of Visible
+    // Normals")
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view direction to the hemisphere configuration
+        let vh: Vec3 = Utils::unit_vector(&Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let lensq: f64 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1: Vec3 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2: Vec3 = vh.cross(&t1);
+
+        // Uniform point on the disk, warped to the visible half
+        let r: f64 = u1.sqrt();
+        let phi: f64 = 2.0 * Utils::pi() * u2;
+        let p1: f64 = r * phi.cos();
+        let s: f64 = 0.5 * (1.0 + vh.z());
+        let p2: f64 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh: Vec3 = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Utils::unit_vector(&Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)))
+    }
+}
+
+// Unpolarized reflectance of a dielectric interface for light arriving at
+// cos_theta_i on the side the normal points to. `eta` is the index on the
+// far side over the index on the near side.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta): (f64, f64) = if cos_theta_i < 0.0 {
+        (-cos_theta_i.max(-1.0), 1.0 / eta)
+    } else {
+        (cos_theta_i.min(1.0), eta)
+    };
+
+    let sin2_t: f64 = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t: f64 = (1.0 - sin2_t).sqrt();
+
+    let r_parallel: f64 = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular: f64 = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Reflectance of a conductor with complex index of refraction eta + i k,
+// per channel
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let cos2: f64 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2: f64 = 1.0 - cos2;
+
+    let channel = |eta: f64, k: f64| -> f64 {
+        let t0: f64 = eta * eta - k * k - sin2;
+        let a2_plus_b2: f64 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1: f64 = a2_plus_b2 + cos2;
+        let a: f64 = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2: f64 = 2.0 * cos_theta_i.clamp(0.0, 1.0) * a;
+        let rs: f64 = (t1 - t2) / (t1 + t2);
+
+        let t3: f64 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4: f64 = t2 * sin2;
+        let rp: f64 = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+
+    Vec3::new(channel(eta.x(), k.x()), channel(eta.y(), k.y()), channel(eta.z(), k.z()))
+}
+
+// Measured complex indices of refraction at red, green and blue wavelengths
+pub const CONDUCTOR_PRESETS: [(&str, [f64; 3], [f64; 3]); 4] = [
+    ("gold", [0.143119, 0.374957, 1.44248], [3.98316, 2.38572, 1.60322]),
+    ("copper", [0.200438, 0.924033, 1.10221], [3.91295, 2.45285, 2.14219]),
+    ("aluminum", [1.65746, 0.880369, 0.521229], [9.22387, 6.26952, 4.837]),
+    ("silver", [0.155265, 0.116723, 0.138342], [4.82835, 3.12225, 2.14696]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ggx_normalized() {
+        // Projected microfacet area adds up to the macro surface, and the
+        // visible normals of any direction integrate to one
+        let ggx: Ggx = Ggx::ggx(0.5);
+        let wo: Vec3 = Utils::unit_vector(&Vec3::new(0.9, 0.0, 0.3));
+        let n: u32 = 400_000;
+        let mut projected: f64 = 0.0;
+        let mut visible: f64 = 0.0;
+        Utils::seed_random(11);
+        for _ in 0..n {
+            // Uniform hemisphere directions, density 1 / 2 pi
+            let mut m: Vec3 = Utils::random_unit_vector();
+            if m.z() < 0.0 {
+                m = -m;
+            }
+            projected += ggx.d(&m) * m.z();
+            visible += ggx.visible_normal_pdf(&wo, &m);
+        }
+        let scale: f64 = 2.0 * Utils::pi() / n as f64;
+        assert!((projected * scale - 1.0).abs() < 0.02, "{}", projected * scale);
+        assert!((visible * scale - 1.0).abs() < 0.02, "{}", visible * scale);
+    }
+
+    #[test]
+    fn test_ggx_visible_normal_sampling() {
+        // Sampled normals face wo, and their mean cosine with the macro
+        // normal matches the one predicted by the density
+        let ggx: Ggx = Ggx::ggx(0.7);
+        let wo: Vec3 = Utils::unit_vector(&Vec3::new(-0.3, 0.5, 0.4));
+        let n: u32 = 200_000;
+        let mut sampled: f64 = 0.0;
+        let mut expected: f64 = 0.0;
+        Utils::seed_random(12);
+        for _ in 0..n {
+            let m: Vec3 = ggx.sample_visible_normal(&wo, Utils::random_double(), Utils::random_double());
+            assert!(m.z() > 0.0 && wo.dot(&m) >= -1e-9);
+            sampled += m.z();
+
+            let mut h: Vec3 = Utils::random_unit_vector();
+            if h.z() < 0.0 {
+                h = -h;
+            }
+            expected += ggx.visible_normal_pdf(&wo, &h) * h.z() * 2.0 * Utils::pi();
+        }
+        let (sampled, expected): (f64, f64) = (sampled / n as f64, expected / n as f64);
+        assert!((sampled - expected).abs() < 0.01, "{} vs {}", sampled, expected);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // Glass reflects 4% head on, everything at grazing angles, and
+        // totally reflects from inside past the critical angle
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(1e-9, 1.5) - 1.0).abs() < 1e-6);
+        assert_eq!(fresnel_dielectric(-0.3, 1.5), 1.0);
+        assert!((fresnel_dielectric(-1.0, 1.5) - 0.04).abs() < 1e-12);
+
+        // Without absorption the conductor formula is the dielectric one
+        let eta: Vec3 = Vec3::one() * 1.5;
+        let f: Vec3 = fresnel_conductor(0.6, &eta, &Vec3::zero());
+        assert!((f.x() - fresnel_dielectric(0.6, 1.5)).abs() < 1e-12);
+
+        // Gold is yellow
+        let (_, eta, k) = CONDUCTOR_PRESETS[0];
+        let gold: Vec3 = fresnel_conductor(1.0, &Vec3::new(eta[0], eta[1], eta[2]), &Vec3::new(k[0], k[1], k[2]));
+        assert!(gold.x() > 0.9 && gold.y() > 0.7 && gold.z() < 0.5);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::sphere::*;
+use crate::material::Material;
+use crate::aabb::Aabb;
+
+// Sphere moving in a straight line from center0 at time0 to center1 at
+// time1. Outside that interval it rests at the nearer end.
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl MovingSphere {
+    pub fn moving_sphere(cen0: Vec3, cen1: Vec3, time0: f64, time1: f64, r: f64, m: Box<Material>) -> Self {
+        MovingSphere { center0: cen0, center1: cen1, time0, time1, radius: r, mat_ptr: m }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+
+        let s: f64 = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + (self.center1 - self.center0) * s
+    }
+}
+
+// Moving spheres are not registered as lights: sampling one would need the
+// time of the shadow ray. Emitters among them are still found by BSDF
+// sampling.
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !sphere_hit(&self.center(r.time()), self.radius, r, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    // The path is a straight line, so the boxes around both ends cover it
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::surrounding_box(
+            &sphere_bounding_box(&self.center0, self.radius),
+            &sphere_bounding_box(&self.center1, self.radius));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Utils;
+
+    #[test]
+    fn test_moving_sphere_hit_follows_time() {
+        let sphere: MovingSphere = MovingSphere::moving_sphere(
+            Vec3::new(0.0, 0.0, -2.0), Vec3::new(2.0, 0.0, -2.0), 0.0, 1.0, 0.5, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        assert_eq!(sphere.center(0.5), Vec3::new(1.0, 0.0, -2.0));
+        assert_eq!(sphere.center(2.0), Vec3::new(2.0, 0.0, -2.0));
+
+        let direction: Vec3 = Vec3::new(0.0, 0.0, -1.0);
+        assert!(sphere.hit(&Ray::ray_with_time(Vec3::zero(), direction, 0.0), 0.001, Utils::infinity(), &mut rec));
+        assert!(!sphere.hit(&Ray::ray_with_time(Vec3::zero(), direction, 1.0), 0.001, Utils::infinity(), &mut rec));
+        let later: Ray = Ray::ray_with_time(Vec3::new(2.0, 0.0, 0.0), direction, 1.0);
+        assert!(sphere.hit(&later, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.5).abs() < 1e-12);
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(sphere.bounding_box(&mut bbox));
+        assert_eq!(bbox, Aabb::aabb(Vec3::new(-0.5, -0.5, -2.5), Vec3::new(2.5, 0.5, -1.5)));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::vec3::Vec3;
+use crate::material::*;
+use crate::hittable_list::HittableList;
+use crate::triangle_mesh::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Error while reading an OBJ or MTL file. `line` is 1-based, 0 when the
+// error is not tied to a line (e.g. the file could not be opened).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl ObjError {
+    fn obj_error(file: &str, line: usize, message: String) -> ObjError {
+        ObjError { file: file.to_string(), line, message }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Used for faces that come before any usemtl statement
+fn default_material() -> Material {
+    Material::Lambertian { lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.8, 0.8).into()) }
+}
+
+fn parse_floats(file: &str, line: usize, keyword: &str, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjError> {
+    if args.len() < min || args.len() > max {
+        let expected: String = if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        };
+        return Err(ObjError::obj_error(file, line,
+            format!("'{}' expects {} numbers, found {}", keyword, expected, args.len())));
+    }
+
+    args.iter()
+        .map(|a| a.parse::<f64>().map_err(|_|
+            ObjError::obj_error(file, line, format!("invalid number '{}' in '{}'", a, keyword))))
+        .collect()
+}
+
+fn parse_vec3(file: &str, line: usize, keyword: &str, args: &[&str]) -> Result<Vec3, ObjError> {
+    let values: Vec<f64> = parse_floats(file, line, keyword, args, 3, 3)?;
+    Ok(Vec3::new(values[0], values[1], values[2]))
+}
+
+// Maps the Wavefront illumination model onto the closest Material
+#[derive(Default)]
+struct MtlEntry {
+    kd: Option<Vec3>,
+    ks: Option<Vec3>,
+    ns: Option<f64>,
+    ni: Option<f64>,
+    d: Option<f64>,
+    illum: Option<u32>,
+}
+
+impl MtlEntry {
+    fn to_material(&self) -> Material {
+        let kd: Vec3 = self.kd.unwrap_or(Vec3::new(0.8, 0.8, 0.8));
+        let ks: Vec3 = self.ks.unwrap_or(Vec3::zero());
+        let illum: u32 = self.illum.unwrap_or(2);
+
+        // Refraction models, or anything not fully opaque, become glass
+        let transparent: bool = self.d.map(|d| d < 1.0).unwrap_or(false);
+        if transparent || matches!(illum, 4 | 6 | 7 | 9) {
+            let ir: f64 = self.ni.filter(|&ni| ni >= 1.0).unwrap_or(1.5);
+            return Material::Dielectric { dielectric: DielectricMaterial::dielectric(ir) };
+        }
+
+        // Ray traced reflection models with a specular color become metal,
+        // with the Phong exponent turned into a fuzz radius
+        let reflective: bool = matches!(illum, 3 | 5 | 8);
+        if reflective && ks.length_squared() > 0.0 {
+            let ns: f64 = self.ns.unwrap_or(0.0).max(0.0);
+            let fuzz: f64 = (2.0 / (ns + 2.0)).sqrt().min(1.0);
+            return Material::Metal { metal: MetalMaterial::metal(ks.into(), fuzz) };
+        }
+
+        Material::Lambertian { lambertian: LambertianMaterial::lambertian(kd.into()) }
+    }
+}
+
+// Parses the contents of an MTL file into named materials
+pub fn parse_mtl(source: &str, file: &str) -> Result<HashMap<String, Material>, ObjError> {
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line: usize = index + 1;
+        let content: &str = raw_line.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let mut tokens = content.split_whitespace();
+        let keyword: &str = tokens.next().unwrap();
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(ObjError::obj_error(file, line, "'newmtl' expects a single name".to_string()));
+            }
+            entries.push((args[0].to_string(), MtlEntry::default()));
+            continue;
+        }
+
+        let entry: &mut MtlEntry = match entries.last_mut() {
+            Some((_, entry)) => entry,
+            None => return Err(ObjError::obj_error(file, line,
+                format!("'{}' before any 'newmtl'", keyword))),
+        };
+
+        match keyword {
+            "Kd" => entry.kd = Some(parse_vec3(file, line, keyword, &args)?),
+            "Ks" => entry.ks = Some(parse_vec3(file, line, keyword, &args)?),
+            "Ns" => entry.ns = Some(parse_floats(file, line, keyword, &args, 1, 1)?[0]),
+            "Ni" => entry.ni = Some(parse_floats(file, line, keyword, &args, 1, 1)?[0]),
+            "d" => entry.d = Some(parse_floats(file, line, keyword, &args, 1, 1)?[0]),
+            "Tr" => entry.d = Some(1.0 - parse_floats(file, line, keyword, &args, 1, 1)?[0]),
+            "illum" => {
+                let illum: u32 = args.first()
+                    .filter(|_| args.len() == 1)
+                    .and_then(|a| a.parse::<u32>().ok())
+                    .ok_or_else(|| ObjError::obj_error(file, line,
+                        "'illum' expects a single integer".to_string()))?;
+                entry.illum = Some(illum);
+            },
+            // Ka, Ke, texture maps and vendor extensions are not used
+            _ => {},
+        }
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.to_material()))
+        .collect())
+}
+
+// Collects the faces of one (group, material) pair and remaps the global
+// OBJ indices into compact per-mesh buffers
+struct MeshBuilder {
+    material: Material,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<MeshFace>,
+    position_map: HashMap<usize, usize>,
+    normal_map: HashMap<usize, usize>,
+    uv_map: HashMap<usize, usize>,
+}
+
+impl MeshBuilder {
+    fn mesh_builder(material: Material) -> MeshBuilder {
+        MeshBuilder {
+            material,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            position_map: HashMap::new(),
+            normal_map: HashMap::new(),
+            uv_map: HashMap::new(),
+        }
+    }
+
+    fn remap<T: Copy>(map: &mut HashMap<usize, usize>, local: &mut Vec<T>, global: &[T], index: usize) -> usize {
+        *map.entry(index).or_insert_with(|| {
+            local.push(global[index]);
+            local.len() - 1
+        })
+    }
+
+    fn into_mesh(self) -> TriangleMesh {
+        TriangleMesh::triangle_mesh(
+            self.positions, self.normals, self.uvs, self.faces, Box::new(self.material))
+    }
+}
+
+#[derive(Copy, Clone)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Resolves a 1-based (or negative, relative) OBJ index into a 0-based one
+fn resolve_index(file: &str, line: usize, token: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+    let index: i64 = token.parse::<i64>().map_err(|_|
+        ObjError::obj_error(file, line, format!("invalid {} index '{}'", what, token)))?;
+
+    let resolved: i64 = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::obj_error(file, line,
+            format!("{} index {} out of range ({} defined)", what, index, count)));
+    }
+
+    Ok(resolved as usize)
+}
+
+struct ObjData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+}
+
+fn parse_face_vertex(file: &str, line: usize, token: &str, data: &ObjData) -> Result<FaceVertex, ObjError> {
+    let parts: Vec<&str> = token.split('/').collect();
+    if parts.len() > 3 || parts[0].is_empty() {
+        return Err(ObjError::obj_error(file, line, format!("malformed face vertex '{}'", token)));
+    }
+
+    let position: usize = resolve_index(file, line, parts[0], data.positions.len(), "vertex")?;
+    let uv: Option<usize> = match parts.get(1) {
+        Some(p) if !p.is_empty() => Some(resolve_index(file, line, p, data.uvs.len(), "texture coordinate")?),
+        _ => None,
+    };
+    let normal: Option<usize> = match parts.get(2) {
+        Some(p) if !p.is_empty() => Some(resolve_index(file, line, p, data.normals.len(), "normal")?),
+        _ => None,
+    };
+
+    Ok(FaceVertex { position, uv, normal })
+}
+
+// Parses the contents of an OBJ file. `mtl_dir` is where mtllib
+// references are looked up; without it they are an error.
+pub fn parse_obj(source: &str, file: &str, mtl_dir: Option<&Path>) -> Result<HittableList, ObjError> {
+    let mut data: ObjData = ObjData { positions: Vec::new(), normals: Vec::new(), uvs: Vec::new() };
+    let mut materials: HashMap<String, Material> = HashMap::new();
+
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut builder_index: HashMap<(String, String), usize> = HashMap::new();
+    let mut group: String = String::new();
+    let mut material_name: String = String::new();
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line: usize = index + 1;
+        let content: &str = raw_line.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let mut tokens = content.split_whitespace();
+        let keyword: &str = tokens.next().unwrap();
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                // An optional w component is ignored
+                let values: Vec<f64> = parse_floats(file, line, keyword, &args, 3, 4)?;
+                data.positions.push(Vec3::new(values[0], values[1], values[2]));
+            },
+            "vn" => data.normals.push(parse_vec3(file, line, keyword, &args)?),
+            "vt" => {
+                let values: Vec<f64> = parse_floats(file, line, keyword, &args, 1, 3)?;
+                data.uvs.push((values[0], values.get(1).copied().unwrap_or(0.0)));
+            },
+            "g" | "o" => group = args.join(" "),
+            "usemtl" => {
+                if args.len() != 1 {
+                    return Err(ObjError::obj_error(file, line, "'usemtl' expects a single name".to_string()));
+                }
+                if !materials.contains_key(args[0]) {
+                    return Err(ObjError::obj_error(file, line, format!("unknown material '{}'", args[0])));
+                }
+                material_name = args[0].to_string();
+            },
+            "mtllib" => {
+                let dir: &Path = mtl_dir.ok_or_else(|| ObjError::obj_error(file, line,
+                    "'mtllib' is not supported without a base directory".to_string()))?;
+                for name in &args {
+                    let mtl_path = dir.join(name);
+                    let mtl_file: String = mtl_path.display().to_string();
+                    let mtl_source: String = fs::read_to_string(&mtl_path).map_err(|e|
+                        ObjError::obj_error(file, line, format!("cannot read '{}': {}", mtl_file, e)))?;
+                    materials.extend(parse_mtl(&mtl_source, &mtl_file)?);
+                }
+            },
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::obj_error(file, line,
+                        format!("face needs at least 3 vertices, found {}", args.len())));
+                }
+
+                let vertices: Vec<FaceVertex> = args
+                    .iter()
+                    .map(|token| parse_face_vertex(file, line, token, &data))
+                    .collect::<Result<_, _>>()?;
+
+                let key: (String, String) = (group.clone(), material_name.clone());
+                let slot: usize = *builder_index.entry(key).or_insert_with(|| {
+                    let material: Material = materials
+                        .get(&material_name)
+                        .cloned()
+                        .unwrap_or_else(default_material);
+                    builders.push(MeshBuilder::mesh_builder(material));
+                    builders.len() - 1
+                });
+                let builder: &mut MeshBuilder = &mut builders[slot];
+
+                // Fan triangulation around the first vertex
+                for k in 1..vertices.len() - 1 {
+                    let tri: [FaceVertex; 3] = [vertices[0], vertices[k], vertices[k + 1]];
+
+                    let mut positions: [usize; 3] = [0; 3];
+                    for (out, v) in positions.iter_mut().zip(tri.iter()) {
+                        *out = MeshBuilder::remap(
+                            &mut builder.position_map, &mut builder.positions, &data.positions, v.position);
+                    }
+
+                    // Normals and UVs are only used when all three vertices have them
+                    let normals: Option<[usize; 3]> = if tri.iter().all(|v| v.normal.is_some()) {
+                        let mut idx: [usize; 3] = [0; 3];
+                        for (out, v) in idx.iter_mut().zip(tri.iter()) {
+                            *out = MeshBuilder::remap(
+                                &mut builder.normal_map, &mut builder.normals, &data.normals, v.normal.unwrap());
+                        }
+                        Some(idx)
+                    } else {
+                        None
+                    };
+
+                    let uvs: Option<[usize; 3]> = if tri.iter().all(|v| v.uv.is_some()) {
+                        let mut idx: [usize; 3] = [0; 3];
+                        for (out, v) in idx.iter_mut().zip(tri.iter()) {
+                            *out = MeshBuilder::remap(
+                                &mut builder.uv_map, &mut builder.uvs, &data.uvs, v.uv.unwrap());
+                        }
+                        Some(idx)
+                    } else {
+                        None
+                    };
+
+                    builder.faces.push(MeshFace { positions, normals, uvs });
+                }
+            },
+            // Smoothing groups, lines, points and free-form geometry are skipped
+            _ => {},
+        }
+    }
+
+    let mut world: HittableList = HittableList::default();
+    for builder in builders {
+        world.add(Box::new(builder.into_mesh()));
+    }
+
+    Ok(world)
+}
+
+// Loads an OBJ file and its material libraries. Every group/material
+// combination becomes one TriangleMesh in the returned list.
+pub fn load_obj(path: &Path) -> Result<HittableList, ObjError> {
+    let file: String = path.display().to_string();
+    let source: String = fs::read_to_string(path).map_err(|e|
+        ObjError::obj_error(&file, 0, format!("cannot read file: {}", e)))?;
+
+    parse_obj(&source, &file, Some(path.parent().unwrap_or_else(|| Path::new("."))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::*;
+    use crate::ray::Ray;
+    use crate::aabb::Aabb;
+    use crate::utils::Utils;
+
+    #[test]
+    fn test_parse_mtl_materials() {
+        let mtl: &str = "
+            newmtl red
+            Kd 0.8 0.1 0.1
+            illum 2
+
+            newmtl chrome  # reflective
+            Ks 0.9 0.9 0.9
+            Ns 6
+            illum 3
+
+            newmtl glass
+            Ni 1.45
+            d 0.2
+        ";
+        let materials = parse_mtl(mtl, "test.mtl").unwrap();
+
+        assert_eq!(materials["red"],
+            Material::Lambertian { lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.1, 0.1).into()) });
+        assert_eq!(materials["chrome"],
+            Material::Metal { metal: MetalMaterial::metal(Vec3::new(0.9, 0.9, 0.9).into(), 0.5) });
+        assert_eq!(materials["glass"],
+            Material::Dielectric { dielectric: DielectricMaterial::dielectric(1.45) });
+    }
+
+    #[test]
+    fn test_parse_obj_quad_with_groups() {
+        let obj: &str = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            vn 0 0 1
+            g front
+            f 1/1/1 2/2/1 3/3/1 4/4/1
+            g back
+            f -1 -2 -3
+        ";
+        let world: HittableList = parse_obj(obj, "quad.obj", None).unwrap();
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(world.bounding_box(&mut bbox));
+        assert_eq!(bbox, Aabb::aabb(Vec3::zero(), Vec3::new(1.0, 1.0, 0.0)));
+
+        let r: Ray = Ray::ray(Vec3::new(0.75, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert_eq!(rec.t, 1.0);
+        assert!((rec.u - 0.75).abs() < 1e-12);
+        assert!((rec.v - 0.2).abs() < 1e-12);
+    }
+
+    fn parse_error(source: &str) -> ObjError {
+        match parse_obj(source, "bad.obj", None) {
+            Ok(_) => panic!("expected a parse error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_parse_obj_errors_have_line_numbers() {
+        let bad_number = parse_error("v 0 0 0\nv 1 x 0\n");
+        assert_eq!(bad_number.line, 2);
+        assert_eq!(bad_number.to_string(), "bad.obj:2: invalid number 'x' in 'v'");
+
+        let bad_index = parse_error("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n");
+        assert_eq!(bad_index.line, 4);
+
+        let bad_material = parse_error("usemtl missing\n");
+        assert_eq!(bad_material.line, 1);
+
+        let short_face = parse_error("v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert_eq!(short_face.line, 3);
+    }
+
+    #[test]
+    fn test_load_obj_with_mtllib() {
+        let dir = std::env::temp_dir().join(format!("ray_tracer_obj_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("tri.mtl"), "newmtl gold\nKs 1 0.8 0.3\nNs 1000\nillum 3\n").unwrap();
+        fs::write(dir.join("tri.obj"), "mtllib tri.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl gold\nf 1 2 3\n").unwrap();
+
+        let world = load_obj(&dir.join("tri.obj"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let r: Ray = Ray::ray(Vec3::new(0.2, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(world.unwrap().hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!(matches!(*rec.mat_ptr, Material::Metal { .. }));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+
+// Orthonormal basis whose w axis points along a given direction
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    // Branchless construction from Duff et al. 2017, "Building an
+    // Orthonormal Basis, Revisited"
+    pub fn onb(n: &Vec3) -> Onb {
+        let w: Vec3 = Utils::unit_vector(n);
+        let sign: f64 = 1.0_f64.copysign(w.z());
+        let a: f64 = -1.0 / (sign + w.z());
+        let b: f64 = w.x() * w.y() * a;
+
+        Onb {
+            u: Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()),
+            v: Vec3::new(b, sign + w.y() * w.y() * a, -w.y()),
+            w,
+        }
+    }
+
+    // Converts coordinates in this basis to a world space vector
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        self.u * a + self.v * b + self.w * c
+    }
+
+    // Coordinates of a world space vector in this basis
+    pub fn world_to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.u.dot(v), self.v.dot(v), self.w.dot(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onb_orthonormal() {
+        for n in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 2.0, -3.0), Vec3::new(-0.3, 0.1, 0.0)] {
+            let uvw: Onb = Onb::onb(&n);
+            for (a, b) in [(uvw.u, uvw.v), (uvw.v, uvw.w), (uvw.w, uvw.u)] {
+                assert!(a.dot(&b).abs() < 1e-12);
+                assert!((a.length() - 1.0).abs() < 1e-12);
+            }
+            // Right-handed, with w along n
+            assert!((uvw.u.cross(&uvw.v) - uvw.w).length() < 1e-12);
+            assert!((uvw.w - Utils::unit_vector(&n)).length() < 1e-12);
+
+            let v: Vec3 = Vec3::new(0.3, -2.0, 1.5);
+            let local: Vec3 = uvw.world_to_local(&v);
+            assert!((uvw.local(local.x(), local.y(), local.z()) - v).length() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise over a lattice of random unit vectors
+#[derive(Clone, PartialEq, Debug)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn perlin() -> Perlin {
+        let ranvec: Vec<Vec3> = (0..POINT_COUNT)
+            .map(|_| Utils::unit_vector(&Utils::random_vec3_min_max(-1.0, 1.0)))
+            .collect();
+
+        Perlin {
+            ranvec,
+            perm_x: Perlin::generate_perm(),
+            perm_y: Perlin::generate_perm(),
+            perm_z: Perlin::generate_perm(),
+        }
+    }
+
+    fn generate_perm() -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+
+        // Fisher-Yates shuffle
+        for i in (1..POINT_COUNT).rev() {
+            let target: usize = ((Utils::random_double() * (i + 1) as f64) as usize).min(i);
+            p.swap(i, target);
+        }
+
+        p
+    }
+
+    // Smooth noise in roughly [-1, 1]
+    pub fn noise(&self, p: &Vec3) -> f64 {
+        let u: f64 = p.x() - p.x().floor();
+        let v: f64 = p.y() - p.y().floor();
+        let w: f64 = p.z() - p.z().floor();
+
+        let i: i64 = p.x().floor() as i64;
+        let j: i64 = p.y().floor() as i64;
+        let k: i64 = p.z().floor() as i64;
+
+        let mut c: [[[Vec3; 2]; 2]; 2] = [[[Vec3::zero(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.ranvec[
+                        self.perm_x[((i + di as i64) & 255) as usize] ^
+                        self.perm_y[((j + dj as i64) & 255) as usize] ^
+                        self.perm_z[((k + dk as i64) & 255) as usize]];
+                }
+            }
+        }
+
+        Perlin::perlin_interp(&c, u, v, w)
+    }
+
+    // Trilinear blend of the corner gradients, with Hermite smoothing to
+    // hide the lattice
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu: f64 = u * u * (3.0 - 2.0 * u);
+        let vv: f64 = v * v * (3.0 - 2.0 * v);
+        let ww: f64 = w * w * (3.0 - 2.0 * w);
+        let mut accum: f64 = 0.0;
+
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk): (f64, f64, f64) = (i as f64, j as f64, k as f64);
+                    let weight_v: Vec3 = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * corner.dot(&weight_v);
+                }
+            }
+        }
+
+        accum
+    }
+
+    // Sum of `depth` octaves of noise, each at twice the frequency and half
+    // the weight of the previous one
+    pub fn turbulence(&self, p: &Vec3, depth: u32) -> f64 {
+        let mut accum: f64 = 0.0;
+        let mut temp_p: Vec3 = *p;
+        let mut weight: f64 = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perlin_noise() {
+        let perlin: Perlin = Perlin::perlin();
+
+        // Zero on the lattice, continuous and bounded in between
+        assert!(perlin.noise(&Vec3::new(3.0, -2.0, 7.0)).abs() < 1e-12);
+        let p: Vec3 = Vec3::new(0.3, 1.7, -2.2);
+        let nearby: Vec3 = p + Vec3::new(1e-6, 0.0, 0.0);
+        assert!((perlin.noise(&p) - perlin.noise(&nearby)).abs() < 1e-4);
+
+        for i in 0..1000 {
+            let p: Vec3 = Vec3::new(i as f64 * 0.137, i as f64 * 0.071, i as f64 * -0.053);
+            assert!(perlin.noise(&p).abs() <= 1.0);
+            assert!(perlin.turbulence(&p, 7) >= 0.0);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use std::sync::Arc;
+
+// Distance along the ray to the plane through `point` with unit `normal`,
+// None when the ray runs parallel to it
+fn plane_hit_t(point: &Vec3, normal: &Vec3, r: &Ray) -> Option<f64> {
+    let denom: f64 = normal.dot(&r.direction());
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    Some(normal.dot(&(*point - r.origin())) / denom)
+}
+
+// Smallest box around a disk: along each axis it reaches as far as the
+// disk's rim, radius * sin of the angle between the axis and the normal
+pub fn disk_bounding_box(center: &Vec3, normal: &Vec3, radius: f64) -> Aabb {
+    let n: Vec3 = Utils::unit_vector(normal);
+    let reach: Vec3 = Vec3::new(
+        (1.0 - n.x() * n.x()).max(0.0).sqrt(),
+        (1.0 - n.y() * n.y()).max(0.0).sqrt(),
+        (1.0 - n.z() * n.z()).max(0.0).sqrt()) * radius;
+    Aabb::aabb(*center - reach, *center + reach)
+}
+
+// -------- Plane --------------------------
+// Infinite plane. It has no bounding box, so it stays outside the BVH.
+pub struct Plane {
+    point: Vec3,
+    // Surface coordinates are distances along uvw.u and uvw.v from `point`
+    uvw: Onb,
+    mat_ptr: Box<Material>,
+}
+
+impl Plane {
+    pub fn plane(point: Vec3, normal: Vec3, m: Box<Material>) -> Self {
+        Plane { point, uvw: Onb::onb(&normal), mat_ptr: m }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let t: f64 = match plane_hit_t(&self.point, &self.uvw.w, r) {
+            Some(t) if t_min <= t && t <= t_max => t,
+            _ => return false,
+        };
+
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        let planar: Vec3 = self.uvw.world_to_local(&(rec.p - self.point));
+        rec.u = planar.x();
+        rec.v = planar.y();
+        rec.set_face_normal(r, &self.uvw.w);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+}
+// -----------------------------------------
+
+// -------- Disk ---------------------------
+#[derive(Clone)]
+pub struct Disk {
+    center: Vec3,
+    radius: f64,
+    uvw: Onb,
+    mat_ptr: Box<Material>,
+}
+
+impl Disk {
+    pub fn disk(center: Vec3, normal: Vec3, radius: f64, m: Box<Material>) -> Self {
+        Disk { center, radius, uvw: Onb::onb(&normal), mat_ptr: m }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let t: f64 = match plane_hit_t(&self.center, &self.uvw.w, r) {
+            Some(t) if t_min <= t && t <= t_max => t,
+            _ => return false,
+        };
+
+        let p: Vec3 = r.point_at_parameter(t);
+        let planar: Vec3 = self.uvw.world_to_local(&(p - self.center));
+        let distance: f64 = (planar.x() * planar.x() + planar.y() * planar.y()).sqrt();
+        if distance > self.radius {
+            return false;
+        }
+
+        // Polar coordinates: u is the angle around the normal, v the
+        // distance from the center as a fraction of the radius
+        rec.t = t;
+        rec.p = p;
+        rec.u = (planar.y().atan2(planar.x()) / (2.0 * Utils::pi())).rem_euclid(1.0);
+        rec.v = distance / self.radius;
+        rec.set_face_normal(r, &self.uvw.w);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = disk_bounding_box(&self.center, &self.uvw.w, self.radius);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let mut rec: HitRecord = HitRecord::default();
+        if !self.hit(&Ray::ray(*origin, *direction), 0.001, Utils::infinity(), &mut rec) {
+            return 0.0;
+        }
+
+        let area: f64 = Utils::pi() * self.radius * self.radius;
+        let distance_squared: f64 = rec.t * rec.t * direction.length_squared();
+        let cosine: f64 = direction.dot(&self.uvw.w).abs() / direction.length();
+        distance_squared / (cosine * area)
+    }
+
+    // Uniform over the area of the disk
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let r: f64 = self.radius * Utils::random_double().sqrt();
+        let phi: f64 = 2.0 * Utils::pi() * Utils::random_double();
+        self.center + self.uvw.local(r * phi.cos(), r * phi.sin(), 0.0) - *origin
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+// -----------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plane_hit() {
+        let plane: Plane = Plane::plane(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+        let mut bbox: Aabb = Aabb::default();
+        assert!(!plane.bounding_box(&mut bbox));
+
+        // Far away from the origin, and from below
+        let r: Ray = Ray::ray(Vec3::new(1000.0, 1.0, -500.0), Vec3::new(0.0, -0.5, 0.0));
+        assert!(plane.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-12 && rec.front_face);
+        let planar: Vec3 = plane.uvw.world_to_local(&Vec3::new(1000.0, 0.0, -500.0));
+        assert!((rec.u - planar.x()).abs() < 1e-9 && (rec.v - planar.y()).abs() < 1e-9);
+
+        let below: Ray = Ray::ray(Vec3::new(0.0, -3.0, 0.0), Vec3::new(0.1, 1.0, 0.0));
+        assert!(plane.hit(&below, 0.001, Utils::infinity(), &mut rec));
+        assert!(!rec.front_face && rec.normal == Vec3::new(0.0, -1.0, 0.0));
+        let parallel: Ray = Ray::ray(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        assert!(!plane.hit(&parallel, 0.001, Utils::infinity(), &mut rec));
+    }
+
+    #[test]
+    fn test_disk_hit_and_sampling() {
+        let normal: Vec3 = Vec3::new(1.0, 1.0, 0.0);
+        let disk: Disk = Disk::disk(Vec3::new(0.0, 0.0, -3.0), normal, 2.0, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        let r: Ray = Ray::ray(Vec3::new(1.0, 1.0, -3.0), -normal);
+        assert!(disk.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!(rec.front_face && rec.v.abs() < 1e-12);
+        let rim: Ray = Ray::ray(Vec3::new(1.0, 1.0, -4.9), -normal);
+        assert!(disk.hit(&rim, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.v - 0.95).abs() < 1e-12);
+        let outside: Ray = Ray::ray(Vec3::new(1.0, 1.0, -5.1), -normal);
+        assert!(!disk.hit(&outside, 0.001, Utils::infinity(), &mut rec));
+
+        // The box touches the rim: 2 / sqrt(2) along x and y, 2 along z
+        let mut bbox: Aabb = Aabb::default();
+        assert!(disk.bounding_box(&mut bbox));
+        assert!((bbox.maximum - Vec3::new(2f64.sqrt(), 2f64.sqrt(), -1.0)).length() < 1e-12);
+
+        // Sampled directions hit the disk, and the density integrates to
+        // one over the directions that do
+        Utils::seed_random(3);
+        let origin: Vec3 = Vec3::new(2.0, 1.0, 0.0);
+        for _ in 0..100 {
+            assert!(disk.pdf_value(&origin, &disk.random(&origin)) > 0.0);
+        }
+        let n: usize = 200_000;
+        let integral: f64 = (0..n)
+            .map(|_| disk.pdf_value(&origin, &Utils::random_unit_vector()))
+            .sum::<f64>() * 4.0 * Utils::pi() / n as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use std::sync::Arc;
+
+// Parallelogram with corners q, q+u, q+u+v and q+v
+#[derive(Clone)]
+pub struct Quad {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    // n / (n . n) for the unnormalized normal n, used to find the hit
+    // point's (u, v) coordinates
+    w: Vec3,
+    normal: Vec3,
+    // Plane equation normal . p = d
+    d: f64,
+    area: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl Quad {
+    pub fn quad(q: Vec3, u: Vec3, v: Vec3, m: Box<Material>) -> Self {
+        let n: Vec3 = Utils::cross(&u, &v);
+        let normal: Vec3 = Utils::unit_vector(&n);
+
+        Quad {
+            q,
+            u,
+            v,
+            w: n / n.length_squared(),
+            normal,
+            d: normal.dot(&q),
+            area: n.length(),
+            mat_ptr: m,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom: f64 = self.normal.dot(&r.direction());
+
+        // Parallel to the plane
+        if denom.abs() < 1e-12 {
+            return false;
+        }
+
+        let t: f64 = (self.d - self.normal.dot(&r.origin())) / denom;
+        if t < t_min || t_max < t {
+            return false;
+        }
+
+        let p: Vec3 = r.point_at_parameter(t);
+        let planar_hit: Vec3 = p - self.q;
+        let alpha: f64 = self.w.dot(&Utils::cross(&planar_hit, &self.v));
+        let beta: f64 = self.w.dot(&Utils::cross(&self.u, &planar_hit));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::aabb(self.q, self.q + self.u + self.v)
+            .include_point(&(self.q + self.u))
+            .include_point(&(self.q + self.v));
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let mut rec: HitRecord = HitRecord::default();
+        if !self.hit(&Ray::ray(*origin, *direction), 0.001, Utils::infinity(), &mut rec) {
+            return 0.0;
+        }
+
+        // Area density converted to solid angle
+        let distance_squared: f64 = rec.t * rec.t * direction.length_squared();
+        let cosine: f64 = direction.dot(&self.normal).abs() / direction.length();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let p: Vec3 = self.q + self.u * Utils::random_double() + self.v * Utils::random_double();
+        p - *origin
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quad_hit() {
+        let quad: Quad = Quad::quad(
+            Vec3::new(-1.0, 0.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 4.0), Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        let r: Ray = Ray::ray(Vec3::new(0.5, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(quad.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        // u x v points down, so the ray comes in from the back
+        assert!(!rec.front_face);
+
+        let outside: Ray = Ray::ray(Vec3::new(1.5, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(!quad.hit(&outside, 0.001, Utils::infinity(), &mut rec));
+        let parallel: Ray = Ray::ray(Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!quad.hit(&parallel, 0.001, Utils::infinity(), &mut rec));
+    }
+}
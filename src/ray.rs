@@ -4,11 +4,17 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub _origin: Vec3,
     pub _direction: Vec3,
+    // Instant within the camera shutter interval the ray exists at
+    pub _time: f64,
 }
 
 impl Ray {
     pub fn ray(origin:Vec3, direction:Vec3) -> Ray {
-        Ray {_origin:origin, _direction:direction}
+        Ray {_origin:origin, _direction:direction, _time:0.0}
+    }
+
+    pub fn ray_with_time(origin:Vec3, direction:Vec3, time:f64) -> Ray {
+        Ray {_origin:origin, _direction:direction, _time:time}
     }
 
     pub fn origin(self) -> Vec3 {
@@ -19,6 +25,10 @@ impl Ray {
         self._direction
     }
 
+    pub fn time(self) -> f64 {
+        self._time
+    }
+
     pub fn point_at_parameter(self, t: f64) -> Vec3 {
         self._origin + self._direction * t
     }
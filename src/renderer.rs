@@ -0,0 +1,503 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::hittable::*;
+use crate::camera::Camera;
+use crate::material::{Scatter, ScatterRecord};
+use crate::framebuffer::Framebuffer;
+use crate::background::Background;
+use crate::lights::LightList;
+use crate::medium::Fog;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// How direct light from emitters is found
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightSampling {
+    // Only when a scattered ray happens to hit an emitter
+    None,
+    // Next-event estimation, combined with BSDF sampling through multiple
+    // importance sampling with the balance or the power heuristic
+    Balance,
+    Power,
+}
+
+impl LightSampling {
+    pub fn from_name(name: &str) -> Option<LightSampling> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(LightSampling::None),
+            "balance" => Some(LightSampling::Balance),
+            "power" => Some(LightSampling::Power),
+            _ => None,
+        }
+    }
+
+    // MIS weight of a sample drawn with density pdf_a that the other
+    // strategy would have drawn with density pdf_b
+    fn weight(&self, pdf_a: f64, pdf_b: f64) -> f64 {
+        match self {
+            LightSampling::None => 1.0,
+            LightSampling::Balance => pdf_a / (pdf_a + pdf_b),
+            LightSampling::Power => pdf_a * pdf_a / (pdf_a * pdf_a + pdf_b * pdf_b),
+        }
+    }
+}
+
+// Direct light at a non-specular hit from one sample towards the lights
+fn sample_light(r_in: &Ray, rec: &HitRecord, background: &Background, fog: Option<Fog>, world: &dyn Hittable,
+                lights: &LightList, light_sampling: LightSampling) -> Vec3 {
+    let direction: Vec3 = lights.random(&rec.p);
+    let light_pdf: f64 = lights.pdf_value(&rec.p, &direction);
+    if light_pdf <= 0.0 {
+        return Vec3::zero();
+    }
+
+    let wi: Vec3 = Utils::unit_vector(&direction);
+    let wo: Vec3 = -Utils::unit_vector(&r_in.direction());
+    let f: Vec3 = rec.mat_ptr.eval(rec, &wi, &wo);
+    if f == Vec3::zero() {
+        return Vec3::zero();
+    }
+
+    // Shadow ray; whatever surface it hits first is what lights the point,
+    // or the environment if it leaves the scene
+    let shadow_ray: Ray = Ray::ray_with_time(rec.p, direction, r_in.time());
+    let mut light_rec: HitRecord = HitRecord::default();
+    let mut emitted: Vec3 = if world.hit_surface(&shadow_ray, 0.001, Utils::infinity(), &mut light_rec) {
+        light_rec.mat_ptr.emitted(&shadow_ray, &light_rec)
+    } else if lights.samples_environment() {
+        light_rec.t = Utils::infinity();
+        background.value(&shadow_ray)
+    } else {
+        return Vec3::zero();
+    };
+    if emitted == Vec3::zero() {
+        return Vec3::zero();
+    }
+
+    // Media and fog dim the light on the way instead of blocking it at random.
+    // Fog ends behind the last surface, so light from the environment
+    // reaches the point undimmed, as it does for rays in ray_color.
+    emitted *= world.transmittance(&shadow_ray, 0.001, light_rec.t);
+    if let Some(fog) = fog.filter(|_| light_rec.t < Utils::infinity()) {
+        emitted *= fog.transmittance(light_rec.t * direction.length());
+    }
+    let bsdf_pdf: f64 = rec.mat_ptr.pdf(rec, &wi, &wo);
+    f * emitted * (light_sampling.weight(light_pdf, bsdf_pdf) / light_pdf)
+}
+
+// Radiance arriving along `r`. With an empty light list this is plain
+// path tracing; otherwise every non-specular bounce also samples a light.
+// Media scatter at free-flight distances sampled along each segment.
+pub fn ray_color(r: &Ray, background: &Background, fog: Option<Fog>, world: &dyn Hittable,
+                 lights: &LightList, light_sampling: LightSampling, depth: u32) -> Vec3 {
+    let mut radiance: Vec3 = Vec3::zero();
+    let mut throughput: Vec3 = Vec3::one();
+    let mut ray: Ray = *r;
+    // Density of the BSDF sample that produced `ray`. None for camera rays
+    // and specular bounces, which can only find emitters by hitting them.
+    let mut bsdf_pdf: Option<f64> = None;
+
+    for bounce in 0..depth {
+        // Object intersection
+        let mut rec: HitRecord = HitRecord::default();
+
+        let hit_anything: bool = world.hit(&ray, 0.001, Utils::infinity(), &mut rec);
+
+        // Fog has the same phase function as a constant medium, it only
+        // fills the space between surfaces instead of a boundary
+        let in_fog: bool = match fog {
+            Some(fog) => fog.scatter_before(&ray, hit_anything, &mut rec),
+            None => false,
+        };
+
+        if !hit_anything && !in_fog {
+            // Environment, which light sampling may have found as well
+            let weight: f64 = match bsdf_pdf {
+                Some(pdf) if lights.samples_environment() =>
+                    light_sampling.weight(pdf, lights.pdf_value(&ray.origin(), &ray.direction())),
+                _ => 1.0,
+            };
+            radiance += throughput * background.value(&ray) * weight;
+            break;
+        }
+
+        // Only lights could also have been reached by light sampling, glowing
+        // media are found by following the path alone
+        let emitted: Vec3 = rec.mat_ptr.emitted(&ray, &rec);
+        let weight: f64 = match bsdf_pdf {
+            Some(pdf) if rec.mat_ptr.is_emissive() =>
+                light_sampling.weight(pdf, lights.pdf_value(&ray.origin(), &ray.direction())),
+            _ => 1.0,
+        };
+        radiance += throughput * emitted * weight;
+
+        let mut srec: ScatterRecord = ScatterRecord::default();
+
+        if !rec.mat_ptr.scatter(&ray, &rec, &mut srec) {
+            break;
+        }
+
+        bsdf_pdf = None;
+
+        if !srec.is_specular && !lights.is_empty() {
+            // Like emitters found by the next bounce, light samples only
+            // count while that bounce is still allowed
+            if bounce + 1 < depth {
+                radiance += throughput * sample_light(&ray, &rec, background, fog, world, lights, light_sampling);
+            }
+            bsdf_pdf = Some(srec.pdf);
+        }
+
+        throughput = throughput * srec.f / srec.pdf;
+        ray = Ray::ray_with_time(rec.p, srec.direction, ray.time());
+    }
+
+    radiance
+}
+
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    // Edge length of the square tiles handed out to worker threads
+    pub tile_size: u32,
+    // 0 uses one thread per core
+    pub threads: usize,
+    pub seed: u64,
+    pub light_sampling: LightSampling,
+}
+
+impl RenderSettings {
+    pub fn render_settings(image_width: u32, image_height: u32, samples_per_pixel: u32, max_depth: u32) -> RenderSettings {
+        RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel,
+            max_depth,
+            tile_size: 32,
+            threads: 0,
+            seed: 0,
+            light_sampling: LightSampling::Power,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+fn make_tiles(settings: &RenderSettings) -> Vec<Tile> {
+    let size: u32 = settings.tile_size.max(1);
+    let mut tiles: Vec<Tile> = Vec::new();
+
+    for y0 in (0..settings.image_height).step_by(size as usize) {
+        for x0 in (0..settings.image_width).step_by(size as usize) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + size).min(settings.image_width),
+                y1: (y0 + size).min(settings.image_height),
+            });
+        }
+    }
+
+    tiles
+}
+
+fn render_tile(world: &dyn Hittable, lights: &LightList, background: &Background, fog: Option<Fog>,
+               cam: &Camera, settings: &RenderSettings, tile: &Tile) -> Vec<Vec3> {
+    let mut pixels: Vec<Vec3> =
+        Vec::with_capacity(((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize);
+
+    for y in tile.y0..tile.y1 {
+        // y counts output rows from the top, j is the camera scanline
+        let j: u32 = settings.image_height - 1 - y;
+
+        for i in tile.x0..tile.x1 {
+            // Seed from the pixel itself so the result does not depend on
+            // which thread renders it or in which order
+            let pixel_index: u64 = j as u64 * settings.image_width as u64 + i as u64;
+            Utils::seed_random(
+                settings.seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ pixel_index);
+
+            let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
+
+            for _ in 0..settings.samples_per_pixel {
+                let u: f64 =
+                    (i as f64 + Utils::random_double()) / settings.image_width as f64;
+                let v: f64 =
+                    (j as f64 + Utils::random_double()) / settings.image_height as f64;
+
+                let r: Ray = cam.get_ray(u, v);
+                pixel_color += ray_color(&r, background, fog, world, lights, settings.light_sampling, settings.max_depth);
+            }
+
+            pixels.push(pixel_color / settings.samples_per_pixel as f64);
+        }
+    }
+
+    pixels
+}
+
+// Renders the image tile by tile on a thread pool. The framebuffer holds
+// the averaged linear color of every pixel.
+pub fn render(world: &dyn Hittable, background: &Background, fog: Option<Fog>, cam: &Camera,
+              settings: &RenderSettings) -> Framebuffer {
+    let tiles: Vec<Tile> = make_tiles(settings);
+    let mut lights: LightList = LightList::default();
+    if settings.light_sampling != LightSampling::None {
+        lights = LightList::light_list(world);
+        lights.add_environment(background);
+    }
+    let remaining = AtomicUsize::new(tiles.len());
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(settings.threads)
+        .build()
+        .expect("failed to build render thread pool");
+
+    let rendered: Vec<Vec<Vec3>> = pool.install(|| {
+        tiles
+            .par_iter()
+            .map(|tile| {
+                let pixels = render_tile(world, &lights, background, fog, cam, settings, tile);
+                let left = remaining.fetch_sub(1, Ordering::Relaxed) - 1;
+                if left % 10 == 0 {
+                    eprintln!("\rTiles remaining: {}", left);
+                }
+                pixels
+            })
+            .collect()
+    });
+
+    // Stitch the tiles back into scanline order
+    let mut framebuffer: Framebuffer =
+        Framebuffer::framebuffer(settings.image_width, settings.image_height);
+
+    for (tile, pixels) in tiles.iter().zip(rendered) {
+        framebuffer.set_block(tile.x0, tile.y0, tile.x1 - tile.x0, &pixels);
+    }
+
+    framebuffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::*;
+    use crate::sphere::Sphere;
+    use crate::quad::Quad;
+    use crate::background::EnvironmentMap;
+    use crate::image_reader::Image;
+    use std::sync::Arc;
+
+    fn small_world() -> HittableList {
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(0.0, -100.5, -1.0), 100.0,
+                    Box::new(Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.8, 0.0).into())}))));
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(0.0, 0.0, -1.0), 0.5,
+                    Box::new(Material::Dielectric{
+                        dielectric: DielectricMaterial::dielectric(1.5)}))));
+        world
+    }
+
+    fn small_camera() -> Camera {
+        Camera::camera(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.1,
+            2.0,
+            0.0,
+            1.0)
+    }
+
+    #[test]
+    fn test_render_independent_of_threads_and_tiles() {
+        let world = small_world();
+        let cam = small_camera();
+
+        let mut settings = RenderSettings::render_settings(23, 11, 4, 5);
+        settings.seed = 7;
+        settings.threads = 1;
+        settings.tile_size = 64;
+        let single = render(&world, &Background::sky(), None, &cam, &settings);
+
+        settings.threads = 4;
+        settings.tile_size = 5;
+        let tiled = render(&world, &Background::sky(), None, &cam, &settings);
+
+        assert_eq!((single.width(), single.height()), (23, 11));
+        assert_eq!(single, tiled);
+    }
+
+    #[test]
+    fn test_render_first_row_is_top() {
+        // Black below the horizon and white above it, so the rows written
+        // first must come out brighter than the last ones
+        let world: HittableList = HittableList::default();
+        let cam = small_camera();
+        let background: Background = Background::Gradient { bottom: Vec3::zero(), top: Vec3::one() };
+
+        let settings = RenderSettings::render_settings(8, 6, 2, 5);
+        let fb = render(&world, &background, None, &cam, &settings);
+        assert!(fb.pixels()[0].y() > fb.pixels()[8 * 5].y());
+    }
+
+    #[test]
+    fn test_ray_color_emission_and_background() {
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(0.0, 0.0, -2.0), 0.5,
+                    Box::new(Material::DiffuseLight{
+                        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(4.0, 2.0, 1.0))}))));
+        let background: Background = Background::Color(Vec3::new(0.1, 0.2, 0.3));
+
+        let lights: LightList = LightList::light_list(&world);
+        let sampling: LightSampling = LightSampling::Power;
+
+        let towards: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray_color(&towards, &background, None, &world, &lights, sampling, 5), Vec3::new(4.0, 2.0, 1.0));
+
+        let away: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(ray_color(&away, &background, None, &world, &lights, sampling, 5), Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(ray_color(&away, &background, None, &world, &lights, sampling, 0), Vec3::zero());
+    }
+
+    #[test]
+    fn test_fog_attenuates_emitters() {
+        // Black fog only absorbs, so on average the light reaching the
+        // camera is dimmed by the transmittance over the 1.5 units to the
+        // emitter
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(0.0, 0.0, -2.0), 0.5,
+                    Box::new(Material::DiffuseLight{
+                        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(4.0, 2.0, 1.0))}))));
+        let background: Background = Background::Color(Vec3::zero());
+        let lights: LightList = LightList::light_list(&world);
+        let fog: Fog = Fog::fog(0.4, Vec3::zero());
+
+        Utils::seed_random(3);
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let n: u32 = 100_000;
+        let mut sum: f64 = 0.0;
+        for _ in 0..n {
+            sum += ray_color(&r, &background, Some(fog), &world, &lights, LightSampling::Power, 5).r();
+        }
+        let expected: f64 = 4.0 * fog.transmittance(1.5);
+        assert!((sum / n as f64 - expected).abs() < 0.02 * expected, "{} vs {}", sum / n as f64, expected);
+    }
+
+    // Mean and variance of the red channel over many camera rays
+    fn estimate(world: &HittableList, background: &Background, fog: Option<Fog>, sampling: LightSampling,
+                samples: usize) -> (f64, f64) {
+        let mut lights: LightList = LightList::default();
+        if sampling != LightSampling::None {
+            lights = LightList::light_list(world);
+            lights.add_environment(background);
+        }
+        let r: Ray = Ray::ray(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        let values: Vec<f64> = (0..samples)
+            .map(|_| ray_color(&r, background, fog, world, &lights, sampling, 3).r())
+            .collect();
+        let mean: f64 = values.iter().sum::<f64>() / samples as f64;
+        let variance: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / samples as f64;
+        (mean, variance)
+    }
+
+    // Every light sampling mode converges to the same answer, with much less
+    // noise when lights are sampled
+    #[test]
+    fn test_light_sampling_unbiased_and_less_noisy() {
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Quad::quad(
+                    Vec3::new(-5.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 0.0),
+                    Box::new(Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.5, 0.5, 0.5).into())}))));
+        world.add(
+            Box::new(
+                Quad::quad(
+                    Vec3::new(1.0, 2.0, -0.5), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0),
+                    Box::new(Material::DiffuseLight{
+                        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(10.0, 10.0, 10.0))}))));
+
+        let background: Background = Background::Color(Vec3::zero());
+        Utils::seed_random(5);
+        let (plain_mean, plain_variance) = estimate(&world, &background, None, LightSampling::None, 400_000);
+        let (balance_mean, balance_variance) = estimate(&world, &background, None, LightSampling::Balance, 40_000);
+        let (power_mean, power_variance) = estimate(&world, &background, None, LightSampling::Power, 40_000);
+
+        assert!((balance_mean - plain_mean).abs() < 0.03 * plain_mean, "{} vs {}", balance_mean, plain_mean);
+        assert!((power_mean - plain_mean).abs() < 0.03 * plain_mean, "{} vs {}", power_mean, plain_mean);
+        assert!(balance_variance * 10.0 < plain_variance);
+        assert!(power_variance * 10.0 < plain_variance);
+    }
+
+    // Same for an environment map whose light comes from one bright pixel
+    #[test]
+    fn test_environment_sampling_unbiased_and_less_noisy() {
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Quad::quad(
+                    Vec3::new(-5.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 0.0),
+                    Box::new(Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.5, 0.5, 0.5).into())}))));
+        let pixels: Vec<Vec3> = (0..32)
+            .map(|i| if i == 8 + 5 { Vec3::one() * 100.0 } else { Vec3::one() * 0.01 })
+            .collect();
+        let background: Background = Background::Image {
+            map: Arc::new(EnvironmentMap::environment_map(Image::image(8, 4, pixels), 0.0, 1.0)) };
+
+        Utils::seed_random(6);
+        let (plain_mean, plain_variance) = estimate(&world, &background, None, LightSampling::None, 400_000);
+        let (power_mean, power_variance) = estimate(&world, &background, None, LightSampling::Power, 40_000);
+
+        assert!((power_mean - plain_mean).abs() < 0.03 * plain_mean, "{} vs {}", power_mean, plain_mean);
+        assert!(power_variance * 10.0 < plain_variance);
+    }
+    // Fog dims the light of surfaces but not the environment behind them,
+    // whichever strategy finds it
+    #[test]
+    fn test_environment_sampling_unbiased_in_fog() {
+        let mut world: HittableList = HittableList::default();
+        world.add(
+            Box::new(
+                Quad::quad(
+                    Vec3::new(-5.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 0.0),
+                    Box::new(Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.5, 0.5, 0.5).into())}))));
+        let background: Background = Background::Image {
+            map: Arc::new(EnvironmentMap::environment_map(Image::image(8, 4, vec![Vec3::one(); 32]), 0.0, 1.0)) };
+        let fog: Option<Fog> = Some(Fog::fog(0.01, Vec3::new(0.5, 0.5, 0.5)));
+
+        Utils::seed_random(7);
+        let (plain_mean, _) = estimate(&world, &background, fog, LightSampling::None, 200_000);
+        let (power_mean, _) = estimate(&world, &background, fog, LightSampling::Power, 100_000);
+        assert!((power_mean - plain_mean).abs() < 0.03 * plain_mean, "{} vs {}", power_mean, plain_mean);
+    }
+}
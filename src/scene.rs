@@ -0,0 +1,980 @@
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+use crate::sphere::Sphere;
+use crate::moving_sphere::MovingSphere;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::camera::CameraSettings;
+use crate::material::*;
+use crate::renderer;
+use crate::renderer::RenderSettings;
+use crate::framebuffer::Framebuffer;
+use crate::triangle::Triangle;
+use crate::quad::Quad;
+use crate::plane::Plane;
+use crate::cuboid::Cuboid;
+use crate::csg::{Csg, CsgOperation};
+use crate::fractal::{Fractal, FractalHittable};
+use crate::triangle_mesh::*;
+use crate::aabb::Aabb;
+use crate::obj_loader;
+use crate::background::{Background, EnvironmentMap};
+use crate::sky::PhysicalSky;
+use crate::image_reader::Image;
+use crate::texture::*;
+use crate::mat4::Transform;
+use crate::transformed::Transformed;
+use crate::medium::*;
+use crate::voxel_grid::VoxelGrid;
+use crate::perlin::Perlin;
+use std::sync::Arc;
+use std::path::Path;
+
+// A world ready to render, with the camera and the default render settings
+pub struct Scene {
+    pub world: HittableList,
+    pub background: Background,
+    // Homogeneous medium between all surfaces, None for clear air
+    pub fog: Option<Fog>,
+    pub camera: CameraSettings,
+    pub settings: RenderSettings,
+}
+
+impl Scene {
+    // The camera's aspect ratio follows the output resolution, which may
+    // have been changed after the scene was built
+    pub fn render(&self) -> Framebuffer {
+        let aspect_ratio: f64 = self.settings.image_width as f64 / self.settings.image_height as f64;
+        renderer::render(&self.world, &self.background, self.fog, &self.camera.camera(aspect_ratio), &self.settings)
+    }
+}
+
+// Scenes that can be rendered by name from the command line
+pub const BUILTIN_SCENES: [(&str, &str); 14] = [
+    ("final", "Random spheres from the cover of Ray Tracing in One Weekend"),
+    ("bouncing-spheres", "The same spheres with the small diffuse ones motion blurred"),
+    ("three-spheres", "Diffuse, glass and metal spheres on a yellow ground"),
+    ("mesh", "Smooth-shaded octahedron mesh and a metal triangle"),
+    ("cornell-box", "Cornell box lit only by its ceiling light"),
+    ("cornell-smoke", "Cornell box with its two blocks made of dark and light smoke"),
+    ("textures", "Checkered ground with Perlin noise, turbulence and marble spheres"),
+    ("instances", "Hundreds of scaled and rotated copies of one shared mesh"),
+    ("volumes", "A noisy cloud next to a glowing fireball, both voxel grids"),
+    ("microfacets", "Gold, copper, aluminum and silver of rising roughness next to frosted glass"),
+    ("principled", "Plastic, metal, clearcoat, velvet, glass and a textured mix from one principled material"),
+    ("environment", "Spheres lit only by an HDR environment map with a small, bright sun"),
+    ("physical-sky", "The same spheres under an analytic late afternoon sky and sun"),
+    ("fractals", "A Mandelbulb and a quaternion Julia set colored by orbit traps, and a Menger sponge"),
+];
+
+pub fn builtin_scene(name: &str) -> Option<Scene> {
+    match name {
+        "final" => Some(final_scene(false)),
+        "bouncing-spheres" => Some(final_scene(true)),
+        "three-spheres" => Some(three_spheres_scene()),
+        "mesh" => Some(mesh_scene()),
+        "cornell-box" => Some(cornell_box_scene(false)),
+        "cornell-smoke" => Some(cornell_box_scene(true)),
+        "textures" => Some(textures_scene()),
+        "instances" => Some(instances_scene()),
+        "volumes" => Some(volumes_scene()),
+        "microfacets" => Some(microfacets_scene()),
+        "principled" => Some(principled_scene()),
+        "environment" => Some(lit_spheres_scene(Background::Image {
+            map: Arc::new(EnvironmentMap::environment_map(sunny_sky_image(512, 256), 0.0, 1.0)) })),
+        "physical-sky" => Some(lit_spheres_scene(Background::PhysicalSky {
+            sky: Arc::new(PhysicalSky::physical_sky(Vec3::new(-1.0, 0.35, 0.4), 3.0, Vec3::one() * 0.3)) })),
+        "fractals" => Some(fractals_scene()),
+        _ => None,
+    }
+}
+
+fn three_spheres_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 10;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let material_ground: Box<Material>
+        = Box::new(
+            Material::Lambertian{
+                lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.8, 0.0).into())});
+    let material_center: Box<Material>
+        = Box::new(
+            Material::Lambertian{
+                lambertian: LambertianMaterial::lambertian(Vec3::new(0.1, 0.2, 0.5).into())});
+    let material_left: Box<Material>
+        = Box::new(
+            Material::Dielectric{
+                dielectric: DielectricMaterial::dielectric(1.5)});
+    let material_left_2: Box<Material>
+        = Box::new(
+            Material::Dielectric{
+                dielectric: DielectricMaterial::dielectric(1.5)});
+    let material_right: Box<Material>
+        = Box::new(
+            Material::Metal{
+                metal: MetalMaterial::metal(Vec3::new(0.8, 0.6, 0.2).into(), 0.0)});
+
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    // Hollow glass ball
+    world.add(
+        Box::new(
+            Csg::csg(
+                CsgOperation::Difference,
+                Box::new(Sphere::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left)),
+                Box::new(Sphere::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.45, material_left_2)))));
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(3.0, 3.0, 2.0);
+    let lookat : Vec3 = Vec3::new(0.0, 0.0, -1.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let aperture: f64 = 2.0;
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        60.0,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world, background: Background::sky(), fog: None, camera, settings }
+}
+
+fn mesh_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 10;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -100.5, -1.0), 100.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.8, 0.0).into())}))));
+
+    // Octahedron with per-vertex normals, shaded smooth
+    let positions: Vec<Vec3> = vec![
+        Vec3::new(0.5, 0.0, -1.0),
+        Vec3::new(-0.5, 0.0, -1.0),
+        Vec3::new(0.0, 0.5, -1.0),
+        Vec3::new(0.0, -0.5, -1.0),
+        Vec3::new(0.0, 0.0, -0.5),
+        Vec3::new(0.0, 0.0, -1.5),
+    ];
+    let center: Vec3 = Vec3::new(0.0, 0.0, -1.0);
+    let normals: Vec<Vec3> = positions
+        .iter()
+        .map(|p| Utils::unit_vector(&(*p - center)))
+        .collect();
+    let faces: Vec<MeshFace> = [
+        [0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+        [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5],
+    ]
+        .iter()
+        .map(|&idx| MeshFace { positions: idx, normals: Some(idx), uvs: None })
+        .collect();
+    world.add(
+        Box::new(
+            TriangleMesh::triangle_mesh(
+                positions, normals, Vec::new(), faces,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.1, 0.2, 0.5).into())}))));
+
+    world.add(
+        Box::new(
+            Triangle::triangle(
+                Vec3::new(0.7, -0.5, -1.5),
+                Vec3::new(1.7, -0.5, -1.5),
+                Vec3::new(1.2, 0.5, -1.5),
+                Box::new(
+                    Material::Metal{
+                        metal: MetalMaterial::metal(Vec3::new(0.8, 0.6, 0.2).into(), 0.0)}))));
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 1.0, 2.0);
+    let lookat : Vec3 = Vec3::new(0.0, 0.0, -1.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        40.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings }
+}
+
+// Frames an OBJ model with a camera and puts it on a ground plane
+pub fn obj_scene(path: &Path) -> Result<Scene, String> {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 10;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = obj_loader::load_obj(path).map_err(|e| e.to_string())?;
+
+    let mut bbox: Aabb = Aabb::default();
+    if !world.bounding_box(&mut bbox) {
+        return Err(format!("{}: no faces to render", path.display()));
+    }
+    let center: Vec3 = bbox.centroid();
+    let size: f64 = bbox.extent().length().max(0.001);
+
+    // Ground just below the model
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(center.x(), bbox.minimum.y() - 1000.0 * size, center.z()), 1000.0 * size,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian((Vec3::one() / 2.0).into())}))));
+
+    // Camera framing the bounding box
+    let lookfrom : Vec3 = center + Vec3::new(0.6, 0.4, 1.2) * size;
+    let lookat : Vec3 = center;
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        40.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Ok(Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings })
+}
+
+// With `bouncing` the small diffuse spheres move upwards while the shutter
+// is open
+fn random_world(bouncing: bool) -> HittableList {
+    let mut world: HittableList = HittableList::default();
+
+    let ground_material: Box<Material>
+        = Box::new(
+            Material::Lambertian{
+                lambertian: LambertianMaterial::lambertian((Vec3::one() / 2.0).into())});
+    world.add(
+        Box::new(
+            Plane::plane(
+                Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), ground_material)));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat: f64 = Utils::random_double();
+            let center: Vec3 = Vec3::new(a as f64 + 0.9*Utils::random_double(), 0.2, b as f64 + 0.9 * Utils::random_double());
+
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Box<Material>;
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo: Vec3 = Utils::random_vec3() * Utils::random_vec3();
+                    sphere_material = Box::new(
+                        Material::Lambertian{
+                            lambertian: LambertianMaterial::lambertian(albedo.into())});
+                    if bouncing {
+                        let center2: Vec3 = center + Vec3::new(0.0, Utils::random_double_min_max(0.0, 0.5), 0.0);
+                        world.add(
+                            Box::new(
+                                MovingSphere::moving_sphere(
+                                    center, center2, 0.0, 1.0, 0.2, sphere_material)));
+                    } else {
+                        world.add(
+                            Box::new(
+                                Sphere::sphere(
+                                    center, 0.2, sphere_material)));
+                    }
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo: Vec3 = Utils::random_vec3_min_max(0.5, 1.0);
+                    let fuzz: f64 = Utils::random_double_min_max(0.0, 0.5);
+                    sphere_material = Box::new(
+                        Material::Metal{
+                            metal: MetalMaterial::metal(albedo.into(), fuzz)});
+                    world.add(
+                        Box::new(
+                            Sphere::sphere(
+                                center, 0.2, sphere_material)));
+                } else {
+                    sphere_material = Box::new(
+                        Material::Dielectric{
+                            dielectric: DielectricMaterial::dielectric(1.5)});
+                    world.add(
+                        Box::new(
+                            Sphere::sphere(
+                                center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+
+    let material1: Box<Material>
+        = Box::new(
+            Material::Dielectric{
+                dielectric: DielectricMaterial::dielectric(1.5)});
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, 1.0, 0.0), 1.0, material1)));
+
+    let material2: Box<Material>
+        = Box::new(
+            Material::Lambertian{
+                lambertian: LambertianMaterial::lambertian(
+                    Vec3::new(0.4, 0.2, 0.1).into())});
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(-4.0, 1.0, 0.0), 1.0, material2)));
+
+    let material3: Box<Material>
+        = Box::new(
+            Material::Metal{
+                metal: MetalMaterial::metal(
+                    Vec3::new(0.7, 0.6, 0.5).into(), 0.0)});
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(4.0, 1.0, 0.0), 1.0, material3)));
+
+    // return the random world
+    world
+}
+
+fn final_scene(bouncing: bool) -> Scene {
+    // Image
+    let aspect_ratio : f64 = 3.0 / 2.0;
+    let image_witdh : u32 = 1200;
+    let samples_per_pixel : u32 = 500;
+    let max_depth = 50;
+
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    Utils::seed_random(0);
+    let world = random_world(bouncing).into_bvh();
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(13.0, 2.0, 3.0);
+    let lookat : Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = 10.0;
+    let aperture: f64 = 0.1;
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        20.0,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world, background: Background::sky(), fog: None, camera, settings }
+}
+
+fn add_quad(world: &mut HittableList, q: Vec3, u: Vec3, v: Vec3, material: &Material) {
+    world.add(Box::new(Quad::quad(q, u, v, Box::new(material.clone()))));
+}
+
+// Box from the origin to `size`, turned by `angle` degrees about the y axis
+// and then moved by `offset`
+fn add_box(world: &mut HittableList, size: Vec3, angle: f64, offset: Vec3, material: &Material) {
+    let (sin_theta, cos_theta): (f64, f64) = Utils::degree_to_radians(angle).sin_cos();
+    let dx: Vec3 = Vec3::new(cos_theta, 0.0, -sin_theta) * size.x();
+    let dy: Vec3 = Vec3::new(0.0, size.y(), 0.0);
+    let dz: Vec3 = Vec3::new(sin_theta, 0.0, cos_theta) * size.z();
+
+    world.add(Box::new(Cuboid::oriented(offset, dx, dy, dz, Box::new(material.clone()))));
+}
+
+fn cornell_box_scene(smoke: bool) -> Scene {
+    // Image
+    let image_witdh : u32 = 600;
+    let samples_per_pixel : u32 = 200;
+    let max_depth = 50;
+
+    // World
+    let red: Material = Material::Lambertian{
+        lambertian: LambertianMaterial::lambertian(Vec3::new(0.65, 0.05, 0.05).into())};
+    let white: Material = Material::Lambertian{
+        lambertian: LambertianMaterial::lambertian(Vec3::new(0.73, 0.73, 0.73).into())};
+    let green: Material = Material::Lambertian{
+        lambertian: LambertianMaterial::lambertian(Vec3::new(0.12, 0.45, 0.15).into())};
+    let light: Material = Material::DiffuseLight{
+        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(15.0, 15.0, 15.0))};
+
+    let mut world: HittableList = HittableList::default();
+    add_quad(&mut world, Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), &green);
+    add_quad(&mut world, Vec3::zero(), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0), &red);
+    add_quad(&mut world, Vec3::new(343.0, 554.0, 332.0), Vec3::new(-130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -105.0), &light);
+    add_quad(&mut world, Vec3::zero(), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0), &white);
+    add_quad(&mut world, Vec3::one() * 555.0, Vec3::new(-555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -555.0), &white);
+    add_quad(&mut world, Vec3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), &white);
+
+    if smoke {
+        // The blocks only bound the smoke, their material is never seen
+        let mut tall: HittableList = HittableList::default();
+        add_box(&mut tall, Vec3::new(165.0, 330.0, 165.0), 15.0, Vec3::new(265.0, 0.0, 295.0), &white);
+        let mut short: HittableList = HittableList::default();
+        add_box(&mut short, Vec3::new(165.0, 165.0, 165.0), -18.0, Vec3::new(130.0, 0.0, 65.0), &white);
+
+        world.add(Box::new(ConstantMedium::constant_medium(Box::new(tall), 0.01, Vec3::zero().into())));
+        world.add(Box::new(ConstantMedium::constant_medium(Box::new(short), 0.01, Vec3::one().into())));
+    } else {
+        add_box(&mut world, Vec3::new(165.0, 330.0, 165.0), 15.0, Vec3::new(265.0, 0.0, 295.0), &white);
+        add_box(&mut world, Vec3::new(165.0, 165.0, 165.0), -18.0, Vec3::new(130.0, 0.0, 65.0), &white);
+    }
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(278.0, 278.0, -800.0);
+    let lookat : Vec3 = Vec3::new(278.0, 278.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        40.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_witdh, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::Color(Vec3::zero()), fog: None, camera, settings }
+}
+
+fn textures_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 10;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+
+    let checker: Texture = Texture::Checker{
+        checker: CheckerTexture::checker(1.0, Vec3::new(0.2, 0.3, 0.1).into(), Vec3::new(0.9, 0.9, 0.9).into())};
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(checker)}))));
+
+    let kinds: [(NoiseKind, f64); 3] = [(NoiseKind::Perlin, 4.0), (NoiseKind::Turbulence, 4.0), (NoiseKind::Marble, 4.0)];
+    for (i, (kind, scale)) in kinds.iter().enumerate() {
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(2.2 * (i as f64 - 1.0), 1.0, 0.0), 1.0,
+                    Box::new(
+                        Material::Lambertian{
+                            lambertian: LambertianMaterial::lambertian(
+                                Texture::Noise{ noise: NoiseTexture::noise(*kind, *scale) })}))));
+    }
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 3.0, 9.0);
+    let lookat : Vec3 = Vec3::new(0.0, 0.8, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        40.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings }
+}
+
+fn instances_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 10;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian((Vec3::one() / 2.0).into())}))));
+
+    // Unit octahedron around the origin, flat shaded, built once
+    let positions: Vec<Vec3> = vec![
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(0.0, 0.0, -1.0),
+    ];
+    let faces: Vec<MeshFace> = [
+        [0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+        [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5],
+    ]
+        .iter()
+        .map(|&idx| MeshFace { positions: idx, normals: None, uvs: None })
+        .collect();
+    let mesh: Arc<dyn Hittable> = Arc::new(
+        TriangleMesh::triangle_mesh(
+            positions, Vec::new(), Vec::new(), faces,
+            Box::new(
+                Material::Metal{
+                    metal: MetalMaterial::metal(Vec3::new(0.8, 0.6, 0.2).into(), 0.2)})));
+
+    // Every instance only stores its transform
+    Utils::seed_random(0);
+    for a in -12..12 {
+        for b in -12..12 {
+            let size: f64 = Utils::random_double_min_max(0.15, 0.35);
+            let transform: Transform = Transform::rotate(&Utils::random_unit_vector(), Utils::random_double() * 360.0)
+                .then(&Transform::scale(&(Vec3::one() * size)).unwrap())
+                .then(&Transform::translate(&Vec3::new(
+                    a as f64 + 0.8 * Utils::random_double(), size, b as f64 + 0.8 * Utils::random_double())));
+            world.add(Box::new(Transformed::transformed(mesh.clone(), transform)));
+        }
+    }
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(13.0, 3.0, 3.0);
+    let lookat : Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        30.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings }
+}
+
+// Grid of n^3 voxels filled by `f` from the distance to the center, where
+// the inscribed sphere has radius 1, and from turbulence at the voxel
+fn noise_ball(n: usize, perlin: &Perlin, f: impl Fn(f64, f64) -> f64) -> VoxelGrid {
+    let mut values: Vec<f32> = Vec::with_capacity(n * n * n);
+    for z in 0..n {
+        for y in 0..n {
+            for x in 0..n {
+                let p: Vec3 = (Vec3::new(x as f64, y as f64, z as f64) + Vec3::one() * 0.5) / n as f64;
+                let radius: f64 = ((p - Vec3::one() * 0.5) * 2.0).length();
+                values.push(f(radius, perlin.turbulence(&(p * 4.0), 5)) as f32);
+            }
+        }
+    }
+    VoxelGrid::voxel_grid(n, n, n, values)
+}
+
+fn volumes_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 20;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.4, 0.4, 0.4).into())}))));
+
+    let perlin: Perlin = Perlin::perlin();
+    let cloud: VoxelGrid = noise_ball(48, &perlin, |radius, turbulence|
+        (2.0 * (1.0 - radius) + 2.0 * turbulence - 0.6).clamp(0.0, 1.0));
+    world.add(Box::new(GridMedium::grid_medium(
+        Arc::new(cloud), 8.0, ColorMap::constant(Vec3::new(0.95, 0.95, 0.95)), None,
+        Aabb::aabb(Vec3::new(-3.6, 0.2, -1.5), Vec3::new(-0.4, 3.4, 1.5)))));
+
+    // Hot in the middle and cooling towards the smoky edge
+    let fireball_density: VoxelGrid = noise_ball(48, &perlin, |radius, turbulence|
+        (2.0 * (1.0 - radius) + 2.0 * turbulence - 0.4).clamp(0.0, 1.0));
+    let fireball_temperature: VoxelGrid = noise_ball(48, &perlin, |radius, turbulence|
+        (3200.0 * (1.0 - radius) + 1500.0 * turbulence).clamp(0.0, 3500.0));
+    let smoke: ColorMap = ColorMap::ramp(vec![(0.0, Vec3::new(0.6, 0.6, 0.6)), (1.0, Vec3::new(0.2, 0.2, 0.2))]).unwrap();
+    world.add(Box::new(GridMedium::grid_medium(
+        Arc::new(fireball_density), 6.0, smoke,
+        Some(GridEmission::grid_emission(Arc::new(fireball_temperature), ColorMap::Blackbody, 2000.0)),
+        Aabb::aabb(Vec3::new(0.6, 0.0, -1.5), Vec3::new(3.6, 3.0, 1.5)))));
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 2.0, 10.0);
+    let lookat : Vec3 = Vec3::new(0.0, 1.6, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        40.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings }
+}
+
+fn microfacets_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 20;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    let checker: Texture = Texture::Checker{
+        checker: CheckerTexture::checker(1.0, Vec3::new(0.1, 0.1, 0.1).into(), Vec3::new(0.8, 0.8, 0.8).into())};
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(checker)}))));
+
+    let metals: [(&str, f64); 4] = [("gold", 0.1), ("copper", 0.25), ("aluminum", 0.4), ("silver", 0.6)];
+    for (i, (name, roughness)) in metals.iter().enumerate() {
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(2.2 * (i as f64 - 2.0), 1.0, 0.0), 1.0,
+                    Box::new(
+                        Material::Conductor{
+                            conductor: ConductorMaterial::preset(name, *roughness).unwrap()}))));
+    }
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(4.4, 1.0, 0.0), 1.0,
+                Box::new(
+                    Material::RoughDielectric{
+                        rough_dielectric: RoughDielectricMaterial::rough_dielectric(1.5, 0.3)}))));
+
+    let light: Material = Material::DiffuseLight{
+        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(6.0, 6.0, 6.0))};
+    add_quad(&mut world, Vec3::new(-3.0, 6.0, -1.0), Vec3::new(6.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 3.0), &light);
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 3.0, 11.0);
+    let lookat : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        45.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::Gradient { bottom: Vec3::one() * 0.2, top: Vec3::new(0.3, 0.4, 0.6) }, fog: None, camera, settings }
+}
+
+fn principled_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 100;
+    let max_depth = 20;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.5, 0.5, 0.5).into())}))));
+
+    // base color, metallic, roughness, sheen, clearcoat, transmission
+    let checker: Texture = Texture::Checker{
+        checker: CheckerTexture::checker(0.3, Vec3::one().into(), Vec3::zero().into())};
+    let marble: Texture = Texture::Noise{ noise: NoiseTexture::noise(NoiseKind::Marble, 3.0) };
+    let spheres: [(Vec3, Texture, Texture, f64, f64, f64); 6] = [
+        (Vec3::new(0.8, 0.1, 0.1), 0.0.into(), 0.2.into(), 0.0, 0.0, 0.0),
+        (Vec3::new(1.0, 0.78, 0.34), 1.0.into(), 0.3.into(), 0.0, 0.0, 0.0),
+        (Vec3::new(0.1, 0.2, 0.7), 0.0.into(), 0.8.into(), 0.0, 1.0, 0.0),
+        (Vec3::new(0.25, 0.05, 0.3), 0.0.into(), 1.0.into(), 1.0, 0.0, 0.0),
+        (Vec3::new(0.85, 1.0, 0.9), 0.0.into(), 0.1.into(), 0.0, 0.0, 1.0),
+        (Vec3::new(0.9, 0.6, 0.4), checker, marble, 0.0, 0.0, 0.0),
+    ];
+    for (i, (base_color, metallic, roughness, sheen, clearcoat, transmission)) in spheres.into_iter().enumerate() {
+        world.add(
+            Box::new(
+                Sphere::sphere(
+                    Vec3::new(2.2 * (i as f64 - 2.5), 1.0, 0.0), 1.0,
+                    Box::new(
+                        Material::Principled{
+                            principled: Box::new(PrincipledMaterial::principled(
+                                base_color.into(), metallic, roughness, 0.5.into(), sheen.into(), 0.5.into(),
+                                clearcoat.into(), 0.9.into(), transmission.into()))}))));
+    }
+
+    let light: Material = Material::DiffuseLight{
+        diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::new(6.0, 6.0, 6.0))};
+    add_quad(&mut world, Vec3::new(-3.0, 6.0, -1.0), Vec3::new(6.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 3.0), &light);
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 2.0, 14.0);
+    let lookat : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        38.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::Gradient { bottom: Vec3::one() * 0.2, top: Vec3::new(0.3, 0.4, 0.6) }, fog: None, camera, settings }
+}
+
+// Equirectangular sky with a sun over a thousand times brighter, the kind
+// of map that needs importance sampling to converge
+fn sunny_sky_image(width: u32, height: u32) -> Image {
+    let sun: Vec3 = Utils::unit_vector(&Vec3::new(1.0, 0.7, 0.5));
+    let mut pixels: Vec<Vec3> = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let theta: f64 = Utils::pi() * (y as f64 + 0.5) / height as f64;
+        for x in 0..width {
+            let phi: f64 = 2.0 * Utils::pi() * ((x as f64 + 0.5) / width as f64 - 0.5);
+            let direction: Vec3 = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+
+            let pixel: Vec3 = if direction.dot(&sun) > Utils::degree_to_radians(1.5).cos() {
+                Vec3::new(1500.0, 1350.0, 1100.0)
+            } else if direction.y() > 0.0 {
+                let t: f64 = direction.y().sqrt();
+                Vec3::new(0.9, 0.95, 1.0) * (1.0 - t) + Vec3::new(0.25, 0.45, 0.9) * t
+            } else {
+                Vec3::new(0.3, 0.27, 0.25)
+            };
+            pixels.push(pixel);
+        }
+    }
+    Image::image(width, height, pixels)
+}
+
+// Spheres of a few materials on a ground plane, lit only by the background
+fn lit_spheres_scene(background: Background) -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 64;
+    let max_depth = 20;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    let checker: Texture = Texture::Checker{
+        checker: CheckerTexture::checker(1.0, Vec3::new(0.1, 0.1, 0.1).into(), Vec3::new(0.4, 0.4, 0.4).into())};
+    world.add(
+        Box::new(
+            Sphere::sphere(
+                Vec3::new(0.0, -1000.0, 0.0), 1000.0,
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(checker)}))));
+
+    let materials: [Material; 4] = [
+        Material::Lambertian{ lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.8, 0.8).into()) },
+        Material::Conductor{ conductor: ConductorMaterial::preset("gold", 0.2).unwrap() },
+        Material::Dielectric{ dielectric: DielectricMaterial::dielectric(1.5) },
+        Material::Principled{ principled: Box::new(PrincipledMaterial::principled(
+            Vec3::new(0.7, 0.1, 0.1).into(), 0.0.into(), 0.4.into(), 0.5.into(), 0.0.into(), 0.5.into(),
+            1.0.into(), 1.0.into(), 0.0.into())) },
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(
+            Box::new(
+                Sphere::sphere(Vec3::new(2.2 * (i as f64 - 1.5), 1.0, 0.0), 1.0, Box::new(material))));
+    }
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 2.0, 12.0);
+    let lookat : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        35.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background, fog: None, camera, settings }
+}
+
+fn fractals_scene() -> Scene {
+    // Image
+    let aspect_ratio : f64 = 16.0 / 9.0;
+    let image_witdh : u32 = 400;
+    let samples_per_pixel : u32 = 64;
+    let max_depth = 20;
+    let image_heigth : u32 = (image_witdh as f64 / aspect_ratio) as u32;
+
+    // World
+    let mut world: HittableList = HittableList::default();
+    world.add(
+        Box::new(
+            Plane::plane(
+                Vec3::zero(), Vec3::new(0.0, 1.0, 0.0),
+                Box::new(
+                    Material::Lambertian{
+                        lambertian: LambertianMaterial::lambertian(Vec3::new(0.5, 0.5, 0.5).into())}))));
+
+    // Colors come from how close the orbits came to the origin
+    let fire: ColorMap = ColorMap::ramp(vec![
+        (0.2, Vec3::new(0.1, 0.02, 0.0)), (0.5, Vec3::new(0.8, 0.3, 0.05)), (0.8, Vec3::new(0.95, 0.85, 0.5))]).unwrap();
+    let sea: ColorMap = ColorMap::ramp(vec![
+        (0.1, Vec3::new(0.9, 0.9, 0.95)), (0.4, Vec3::new(0.1, 0.4, 0.6)), (0.8, Vec3::new(0.02, 0.05, 0.2))]).unwrap();
+    let fractals: [(Fractal, u32, Material, f64, Vec3); 3] = [
+        (Fractal::Mandelbulb { power: 8.0 }, 10,
+         Material::Lambertian{
+             lambertian: LambertianMaterial::lambertian(
+                 Texture::Ramp { ramp: RampTexture::ramp(fire, TextureCoordinate::U) })},
+         1.3, Vec3::new(0.0, 1.3, 0.0)),
+        (Fractal::Julia { c: [-0.2, 0.8, 0.0, 0.0] }, 12,
+         Material::Metal{
+             metal: MetalMaterial::metal(Texture::Ramp { ramp: RampTexture::ramp(sea, TextureCoordinate::U) }, 0.3)},
+         1.0, Vec3::new(3.0, 1.2, -0.5)),
+        (Fractal::Menger, 4,
+         Material::Lambertian{ lambertian: LambertianMaterial::lambertian(Vec3::new(0.7, 0.7, 0.65).into()) },
+         0.9, Vec3::new(-3.0, 0.9, -0.5)),
+    ];
+    for (fractal, iterations, material, size, center) in fractals {
+        let transform: Transform = Transform::scale(&(Vec3::one() * size)).unwrap()
+            .then(&Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), 30.0))
+            .then(&Transform::translate(&center));
+        world.add(
+            Box::new(
+                Transformed::transformed(
+                    Arc::new(FractalHittable::fractal_hittable(fractal, iterations, Box::new(material))),
+                    transform)));
+    }
+
+    // Camera
+    let lookfrom : Vec3 = Vec3::new(0.0, 3.0, 11.0);
+    let lookat : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let vup : Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus: f64 = (lookfrom - lookat).length();
+    let camera: CameraSettings = CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        35.0,
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0);
+
+    let settings: RenderSettings = RenderSettings::render_settings(
+        image_witdh, image_heigth, samples_per_pixel, max_depth);
+    Scene { world: world.into_bvh(), background: Background::sky(), fog: None, camera, settings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_scenes() {
+        for (name, _) in BUILTIN_SCENES {
+            let scene: Scene = builtin_scene(name).unwrap();
+            assert!(scene.settings.image_width > 0 && scene.settings.image_height > 0);
+        }
+        assert!(builtin_scene("missing").is_none());
+    }
+}
@@ -0,0 +1,994 @@
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+use crate::camera::CameraSettings;
+use crate::material::*;
+use crate::hittable_list::HittableList;
+use crate::hittable::Hittable;
+use crate::renderer::RenderSettings;
+use crate::scene::Scene;
+use crate::background::{Background, EnvironmentMap};
+use crate::sky::PhysicalSky;
+use crate::medium::*;
+use crate::texture::*;
+use crate::image_reader;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::{Table, Value};
+
+mod objects;
+use objects::parse_object;
+
+// Error while reading a scene file. `key` is the dotted path of the offending
+// entry, e.g. "objects[2].material", and empty when the error is not tied to
+// a key (e.g. a TOML syntax error).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub file: String,
+    pub key: String,
+    pub message: String,
+}
+
+impl SceneError {
+    fn scene_error(file: &str, key: &str, message: String) -> SceneError {
+        SceneError { file: file.to_string(), key: key.to_string(), message }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.file, self.key, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+fn value_to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(f) => Some(*f),
+        Value::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+fn value_to_vec3(value: &Value) -> Option<Vec3> {
+    let array: &Vec<Value> = value.as_array()?;
+    if array.len() != 3 {
+        return None;
+    }
+    Some(Vec3::new(value_to_f64(&array[0])?, value_to_f64(&array[1])?, value_to_f64(&array[2])?))
+}
+
+// A table together with its key path, so that errors can name the key
+struct Section<'a> {
+    file: &'a str,
+    path: String,
+    table: &'a Table,
+}
+
+impl<'a> Section<'a> {
+    fn key_path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn error(&self, key: &str, message: &str) -> SceneError {
+        SceneError::scene_error(self.file, &self.key_path(key), message.to_string())
+    }
+
+    // Catches typos, which would otherwise be silently ignored
+    fn check_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        match self.table.keys().find(|key| !allowed.contains(&key.as_str())) {
+            Some(key) => Err(self.error(key, "unknown key")),
+            None => Ok(()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Value, SceneError> {
+        self.table.get(key).ok_or_else(|| self.error(key, "missing required key"))
+    }
+
+    // Runs `read` only if the key is present
+    fn optional<T>(&self, key: &str, read: impl Fn(&Self, &str) -> Result<T, SceneError>) -> Result<Option<T>, SceneError> {
+        if self.table.contains_key(key) {
+            Ok(Some(read(self, key)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn number(&self, key: &str) -> Result<f64, SceneError> {
+        value_to_f64(self.get(key)?).ok_or_else(|| self.error(key, "expected a number"))
+    }
+
+    fn count(&self, key: &str) -> Result<u32, SceneError> {
+        self.get(key)?
+            .as_integer()
+            .filter(|&i| i > 0 && i <= u32::MAX as i64)
+            .map(|i| i as u32)
+            .ok_or_else(|| self.error(key, "expected a positive integer"))
+    }
+
+    fn string(&self, key: &str) -> Result<&'a str, SceneError> {
+        self.get(key)?.as_str().ok_or_else(|| self.error(key, "expected a string"))
+    }
+
+    fn vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+        value_to_vec3(self.get(key)?).ok_or_else(|| self.error(key, "expected an array of 3 numbers"))
+    }
+
+    fn section(&self, key: &str) -> Result<Section<'a>, SceneError> {
+        let table: &Table = self.get(key)?.as_table().ok_or_else(|| self.error(key, "expected a table"))?;
+        Ok(Section { file: self.file, path: self.key_path(key), table })
+    }
+}
+
+fn parse_settings(image: &Section) -> Result<RenderSettings, SceneError> {
+    image.check_keys(&["width", "height", "aspect_ratio", "samples_per_pixel", "max_depth"])?;
+
+    let width: u32 = image.count("width")?;
+    let height: u32 = match (image.optional("height", Section::count)?, image.optional("aspect_ratio", Section::number)?) {
+        (Some(_), Some(_)) => return Err(image.error("aspect_ratio", "set either height or aspect_ratio, not both")),
+        (Some(height), None) => height,
+        (None, Some(aspect_ratio)) if aspect_ratio <= 0.0 => {
+            return Err(image.error("aspect_ratio", "must be positive"));
+        },
+        (None, aspect_ratio) => ((width as f64 / aspect_ratio.unwrap_or(16.0 / 9.0)) as u32).max(1),
+    };
+
+    Ok(RenderSettings::render_settings(
+        width,
+        height,
+        image.optional("samples_per_pixel", Section::count)?.unwrap_or(100),
+        image.optional("max_depth", Section::count)?.unwrap_or(50)))
+}
+
+fn parse_camera(camera: &Section) -> Result<CameraSettings, SceneError> {
+    camera.check_keys(&["lookfrom", "lookat", "vup", "vfov", "aperture", "focus_dist", "shutter_open", "shutter_close"])?;
+
+    let lookfrom: Vec3 = camera.vec3("lookfrom")?;
+    let lookat: Vec3 = camera.vec3("lookat")?;
+    if (lookfrom - lookat).near_zero() {
+        return Err(camera.error("lookat", "must differ from lookfrom"));
+    }
+
+    // The camera basis is built from their cross product
+    let vup: Vec3 = camera.optional("vup", Section::vec3)?.unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+    if vup.near_zero() || Utils::cross(&Utils::unit_vector(&vup), &Utils::unit_vector(&(lookfrom - lookat))).near_zero() {
+        return Err(camera.error("vup", "must not be parallel to the view direction"));
+    }
+
+    let vfov: f64 = camera.number("vfov")?;
+    if vfov <= 0.0 || vfov >= 180.0 {
+        return Err(camera.error("vfov", "must be between 0 and 180 degrees"));
+    }
+
+    // Moving objects default to the same [0, 1] interval
+    let shutter_open: f64 = camera.optional("shutter_open", Section::number)?.unwrap_or(0.0);
+    let shutter_close: f64 = camera.optional("shutter_close", Section::number)?.unwrap_or(1.0);
+    if shutter_close < shutter_open {
+        return Err(camera.error("shutter_close", "must not be before shutter_open"));
+    }
+
+    Ok(CameraSettings::camera_settings(
+        lookfrom,
+        lookat,
+        vup,
+        vfov,
+        camera.optional("aperture", Section::number)?.unwrap_or(0.0),
+        camera.optional("focus_dist", Section::number)?.unwrap_or((lookfrom - lookat).length()),
+        shutter_open,
+        shutter_close))
+}
+
+// Relative paths in a scene file are relative to the file itself
+fn resolve_path(relative: &str, base_dir: Option<&Path>) -> PathBuf {
+    match base_dir {
+        Some(dir) => dir.join(relative),
+        None => PathBuf::from(relative),
+    }
+}
+
+// Where a texture is expected, either a plain color or the name of an entry
+// in [textures]
+enum TextureRef<'a> {
+    Color(Vec3),
+    Name(&'a str),
+}
+
+fn texture_ref<'a>(section: &Section<'a>, key: &str) -> Result<TextureRef<'a>, SceneError> {
+    let value: &Value = section.get(key)?;
+    if let Some(name) = value.as_str() {
+        return Ok(TextureRef::Name(name));
+    }
+    value_to_vec3(value)
+        .map(TextureRef::Color)
+        .ok_or_else(|| section.error(key, "expected an array of 3 numbers or a texture name"))
+}
+
+// Textures may refer to each other by name, so each one is parsed when it is
+// first needed. `pending` holds the chain of textures being parsed, which
+// catches cycles.
+struct TextureParser<'a> {
+    textures: Section<'a>,
+    base_dir: Option<&'a Path>,
+    parsed: HashMap<String, Texture>,
+    pending: Vec<String>,
+}
+
+impl<'a> TextureParser<'a> {
+    fn named(&mut self, name: &str) -> Result<Texture, SceneError> {
+        if let Some(texture) = self.parsed.get(name) {
+            return Ok(texture.clone());
+        }
+
+        self.pending.push(name.to_string());
+        let texture: Texture = self.parse(&self.textures.section(name)?)?;
+        self.pending.pop();
+        self.parsed.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn texture(&mut self, section: &Section, key: &str) -> Result<Texture, SceneError> {
+        let name: &str = match texture_ref(section, key)? {
+            TextureRef::Color(color) => return Ok(color.into()),
+            TextureRef::Name(name) => name,
+        };
+        if !self.textures.table.contains_key(name) {
+            return Err(section.error(key, &format!("unknown texture '{}'", name)));
+        }
+        if self.pending.iter().any(|pending| pending == name) {
+            return Err(section.error(key, &format!("texture '{}' refers back to itself", name)));
+        }
+        self.named(name)
+    }
+
+    fn parse(&mut self, texture: &Section) -> Result<Texture, SceneError> {
+        let scale = |key: &str| -> Result<f64, SceneError> {
+            match texture.optional(key, Section::number)? {
+                Some(scale) if scale <= 0.0 => Err(texture.error(key, "must be positive")),
+                scale => Ok(scale.unwrap_or(1.0)),
+            }
+        };
+
+        match texture.string("type")? {
+            "solid" => {
+                texture.check_keys(&["type", "color"])?;
+                Ok(texture.vec3("color")?.into())
+            },
+            "checker" => {
+                texture.check_keys(&["type", "scale", "even", "odd"])?;
+                let even: Texture = self.texture(texture, "even")?;
+                let odd: Texture = self.texture(texture, "odd")?;
+                Ok(Texture::Checker { checker: CheckerTexture::checker(scale("scale")?, even, odd) })
+            },
+            "image" => {
+                texture.check_keys(&["type", "path", "wrap"])?;
+                let wrap: WrapMode = match texture.optional("wrap", Section::string)? {
+                    Some(name) => WrapMode::from_name(name).ok_or_else(|| texture.error("wrap", &format!(
+                        "unknown wrap mode '{}', expected repeat, clamp or mirror", name)))?,
+                    None => WrapMode::Repeat,
+                };
+                let image: image_reader::Image = image_reader::load_image(
+                    &resolve_path(texture.string("path")?, self.base_dir))
+                    .map_err(|e| texture.error("path", &e.to_string()))?;
+                Ok(Texture::Image { image: ImageTexture::image(Arc::new(image), wrap) })
+            },
+            "noise" => {
+                texture.check_keys(&["type", "kind", "scale"])?;
+                let kind: NoiseKind = match texture.optional("kind", Section::string)? {
+                    Some(name) => NoiseKind::from_name(name).ok_or_else(|| texture.error("kind", &format!(
+                        "unknown noise kind '{}', expected perlin, turbulence or marble", name)))?,
+                    None => NoiseKind::Perlin,
+                };
+                Ok(Texture::Noise { noise: NoiseTexture::noise(kind, scale("scale")?) })
+            },
+            "ramp" => {
+                // Colors u or v, such as the orbit traps of fractals
+                texture.check_keys(&["type", "map", "coordinate"])?;
+                let coordinate: TextureCoordinate = match texture.optional("coordinate", Section::string)? {
+                    Some(name) => TextureCoordinate::from_name(name).ok_or_else(|| texture.error("coordinate", &format!(
+                        "unknown coordinate '{}', expected u or v", name)))?,
+                    None => TextureCoordinate::U,
+                };
+                Ok(Texture::Ramp { ramp: RampTexture::ramp(parse_color_map(texture, "map")?, coordinate) })
+            },
+            other => Err(texture.error("type", &format!(
+                "unknown texture type '{}', expected solid, checker, image, noise or ramp", other))),
+        }
+    }
+}
+
+fn parse_textures(textures: Section, base_dir: Option<&Path>) -> Result<HashMap<String, Texture>, SceneError> {
+    let names: Vec<String> = textures.table.keys().cloned().collect();
+    let mut parser: TextureParser = TextureParser { textures, base_dir, parsed: HashMap::new(), pending: Vec::new() };
+    for name in names {
+        parser.named(&name)?;
+    }
+    Ok(parser.parsed)
+}
+
+fn lookup_texture(material: &Section, key: &str, textures: &HashMap<String, Texture>) -> Result<Texture, SceneError> {
+    match texture_ref(material, key)? {
+        TextureRef::Color(color) => Ok(color.into()),
+        TextureRef::Name(name) => textures.get(name)
+            .cloned()
+            .ok_or_else(|| material.error(key, &format!("unknown texture '{}'", name))),
+    }
+}
+
+fn parse_material(material: &Section, textures: &HashMap<String, Texture>) -> Result<Material, SceneError> {
+    match material.string("type")? {
+        "lambertian" => {
+            material.check_keys(&["type", "albedo"])?;
+            Ok(Material::Lambertian {
+                lambertian: LambertianMaterial::lambertian(lookup_texture(material, "albedo", textures)?) })
+        },
+        "metal" => {
+            material.check_keys(&["type", "albedo", "fuzz"])?;
+            let fuzz: f64 = material.optional("fuzz", Section::number)?.unwrap_or(0.0);
+            if !(0.0..=1.0).contains(&fuzz) {
+                return Err(material.error("fuzz", "must be between 0 and 1"));
+            }
+            Ok(Material::Metal { metal: MetalMaterial::metal(lookup_texture(material, "albedo", textures)?, fuzz) })
+        },
+        "dielectric" => {
+            material.check_keys(&["type", "ir"])?;
+            Ok(Material::Dielectric { dielectric: DielectricMaterial::dielectric(parse_ir(material)?) })
+        },
+        "diffuse_light" => {
+            material.check_keys(&["type", "emit"])?;
+            Ok(Material::DiffuseLight { diffuse_light: DiffuseLightMaterial::diffuse_light(material.vec3("emit")?) })
+        },
+        "conductor" => {
+            // Either a named metal or its complex index of refraction
+            material.check_keys(&["type", "preset", "eta", "k", "roughness"])?;
+            let roughness: f64 = parse_roughness(material)?;
+            let conductor: ConductorMaterial = match material.optional("preset", Section::string)? {
+                Some(name) => {
+                    if material.table.contains_key("eta") || material.table.contains_key("k") {
+                        return Err(material.error("preset", "cannot be combined with eta and k"));
+                    }
+                    ConductorMaterial::preset(name, roughness).ok_or_else(|| material.error("preset", &format!(
+                        "unknown conductor '{}', expected gold, copper, aluminum or silver", name)))?
+                },
+                None => ConductorMaterial::conductor(material.vec3("eta")?, material.vec3("k")?, roughness),
+            };
+            Ok(Material::Conductor { conductor })
+        },
+        "rough_dielectric" => {
+            material.check_keys(&["type", "ir", "roughness"])?;
+            Ok(Material::RoughDielectric {
+                rough_dielectric: RoughDielectricMaterial::rough_dielectric(parse_ir(material)?, parse_roughness(material)?) })
+        },
+        "principled" => {
+            material.check_keys(&["type", "base_color", "metallic", "roughness", "specular", "sheen", "sheen_tint",
+                                  "clearcoat", "clearcoat_gloss", "transmission"])?;
+            let parameter = |key: &str, default: f64| lookup_parameter(material, key, textures, default);
+            Ok(Material::Principled { principled: Box::new(PrincipledMaterial::principled(
+                lookup_texture(material, "base_color", textures)?,
+                parameter("metallic", 0.0)?,
+                parameter("roughness", 0.5)?,
+                parameter("specular", 0.5)?,
+                parameter("sheen", 0.0)?,
+                parameter("sheen_tint", 0.5)?,
+                parameter("clearcoat", 0.0)?,
+                parameter("clearcoat_gloss", 1.0)?,
+                parameter("transmission", 0.0)?)) })
+        },
+        other => Err(material.error("type", &format!(
+            "unknown material type '{}', expected lambertian, metal, dielectric, diffuse_light, conductor, \
+             rough_dielectric or principled",
+            other))),
+    }
+}
+
+// A number between 0 and 1, or a texture where the value varies over the
+// surface
+fn lookup_parameter(material: &Section, key: &str, textures: &HashMap<String, Texture>, default: f64) -> Result<Texture, SceneError> {
+    let value: Option<f64> = match material.table.get(key) {
+        None => Some(default),
+        Some(value) => value_to_f64(value),
+    };
+    match value {
+        Some(value) if !(0.0..=1.0).contains(&value) => Err(material.error(key, "must be between 0 and 1")),
+        Some(value) => Ok(value.into()),
+        None => lookup_texture(material, key, textures),
+    }
+}
+
+fn parse_ir(material: &Section) -> Result<f64, SceneError> {
+    let ir: f64 = material.number("ir")?;
+    if ir <= 0.0 {
+        return Err(material.error("ir", "must be positive"));
+    }
+    Ok(ir)
+}
+
+fn parse_roughness(material: &Section) -> Result<f64, SceneError> {
+    let roughness: f64 = material.optional("roughness", Section::number)?.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&roughness) {
+        return Err(material.error("roughness", "must be between 0 and 1"));
+    }
+    Ok(roughness)
+}
+
+fn parse_background(background: &Section, base_dir: Option<&Path>) -> Result<Background, SceneError> {
+    match background.string("type")? {
+        "color" => {
+            background.check_keys(&["type", "color", "fog"])?;
+            Ok(Background::Color(background.vec3("color")?))
+        },
+        "gradient" => {
+            background.check_keys(&["type", "bottom", "top", "fog"])?;
+            Ok(Background::Gradient { bottom: background.vec3("bottom")?, top: background.vec3("top")? })
+        },
+        "sky" => {
+            background.check_keys(&["type", "fog"])?;
+            Ok(Background::sky())
+        },
+        "image" => {
+            // Equirectangular, usually a .hdr
+            background.check_keys(&["type", "path", "rotation", "intensity", "fog"])?;
+            let image: image_reader::Image = image_reader::load_image(
+                &resolve_path(background.string("path")?, base_dir))
+                .map_err(|e| background.error("path", &e.to_string()))?;
+            let intensity: f64 = background.optional("intensity", Section::number)?.unwrap_or(1.0);
+            if intensity < 0.0 {
+                return Err(background.error("intensity", "must not be negative"));
+            }
+            let rotation: f64 = background.optional("rotation", Section::number)?.unwrap_or(0.0);
+            Ok(Background::Image { map: Arc::new(EnvironmentMap::environment_map(image, rotation, intensity)) })
+        },
+        "physical_sky" => {
+            background.check_keys(&["type", "sun_elevation", "sun_azimuth", "turbidity", "ground_albedo", "fog"])?;
+            // Degrees above the horizon, and clockwise from -z seen from above
+            let elevation: f64 = background.number("sun_elevation")?;
+            if !(0.0..=90.0).contains(&elevation) {
+                return Err(background.error("sun_elevation", "must be between 0 and 90 degrees"));
+            }
+            let azimuth: f64 = background.optional("sun_azimuth", Section::number)?.unwrap_or(0.0);
+            let (e, a): (f64, f64) = (Utils::degree_to_radians(elevation), Utils::degree_to_radians(azimuth));
+            let sun: Vec3 = Vec3::new(e.cos() * a.sin(), e.sin(), -e.cos() * a.cos());
+
+            // The range the model was fitted for
+            let turbidity: f64 = background.optional("turbidity", Section::number)?.unwrap_or(3.0);
+            if !(1.7..=10.0).contains(&turbidity) {
+                return Err(background.error("turbidity", "must be between 1.7 and 10"));
+            }
+            let ground_albedo: Vec3 = background.optional("ground_albedo", Section::vec3)?.unwrap_or(Vec3::one() * 0.3);
+            Ok(Background::PhysicalSky { sky: Arc::new(PhysicalSky::physical_sky(sun, turbidity, ground_albedo)) })
+        },
+        other => Err(background.error("type", &format!(
+            "unknown background type '{}', expected color, gradient, sky, image or physical_sky", other))),
+    }
+}
+
+// Fog goes with the background since both stand for the space around the
+// objects
+fn parse_fog(fog: &Section) -> Result<Fog, SceneError> {
+    fog.check_keys(&["density", "albedo"])?;
+    let density: f64 = fog.number("density")?;
+    if density <= 0.0 {
+        return Err(fog.error("density", "must be positive"));
+    }
+    Ok(Fog::fog(density, fog.vec3("albedo")?))
+}
+
+fn parse_materials(materials: &Section, textures: &HashMap<String, Texture>) -> Result<HashMap<String, Material>, SceneError> {
+    let mut result: HashMap<String, Material> = HashMap::new();
+    for name in materials.table.keys() {
+        result.insert(name.clone(), parse_material(&materials.section(name)?, textures)?);
+    }
+    Ok(result)
+}
+
+// A color, "blackbody" for temperatures in kelvin, or a ramp of
+// [value, [r, g, b]] stops
+fn parse_color_map(object: &Section, key: &str) -> Result<ColorMap, SceneError> {
+    let value: &Value = object.get(key)?;
+    if let Some(color) = value_to_vec3(value) {
+        return Ok(ColorMap::constant(color));
+    }
+    if value.as_str() == Some("blackbody") {
+        return Ok(ColorMap::Blackbody);
+    }
+
+    let expected: &str = "expected a color, \"blackbody\" or an array of [value, [r, g, b]] stops";
+    let stops: Vec<(f64, Vec3)> = value.as_array()
+        .ok_or_else(|| object.error(key, expected))?
+        .iter()
+        .map(|stop| match stop.as_array().map(|s| s.as_slice()) {
+            Some([v, color]) => Some((value_to_f64(v)?, value_to_vec3(color)?)),
+            _ => None,
+        })
+        .collect::<Option<Vec<(f64, Vec3)>>>()
+        .ok_or_else(|| object.error(key, expected))?;
+    ColorMap::ramp(stops).ok_or_else(|| object.error(key, "stops must be sorted by value"))
+}
+
+// Parses the contents of a TOML scene file. Relative OBJ and image paths are
+// resolved against `base_dir`.
+pub fn parse_scene(source: &str, file: &str, base_dir: Option<&Path>) -> Result<Scene, SceneError> {
+    let table: Table = source.parse::<Table>()
+        .map_err(|e| SceneError::scene_error(file, "", e.to_string().trim_end().to_string()))?;
+    let root: Section = Section { file, path: String::new(), table: &table };
+    root.check_keys(&["image", "camera", "background", "textures", "materials", "objects"])?;
+
+    let settings: RenderSettings = parse_settings(&root.section("image")?)?;
+    let camera: CameraSettings = parse_camera(&root.section("camera")?)?;
+    let (background, fog): (Background, Option<Fog>) = match root.optional("background", Section::section)? {
+        Some(section) => {
+            let fog: Option<Fog> = match section.optional("fog", Section::section)? {
+                Some(fog) => Some(parse_fog(&fog)?),
+                None => None,
+            };
+            (parse_background(&section, base_dir)?, fog)
+        },
+        None => (Background::sky(), None),
+    };
+
+    let textures: HashMap<String, Texture> = match root.optional("textures", Section::section)? {
+        Some(section) => parse_textures(section, base_dir)?,
+        None => HashMap::new(),
+    };
+    let materials: HashMap<String, Material> = match root.optional("materials", Section::section)? {
+        Some(section) => parse_materials(&section, &textures)?,
+        None => HashMap::new(),
+    };
+
+    let mut world: HittableList = HittableList::default();
+    let mut meshes: HashMap<PathBuf, Arc<dyn Hittable>> = HashMap::new();
+    let objects: &Vec<Value> = match root.get("objects")?.as_array() {
+        Some(objects) => objects,
+        None => return Err(root.error("objects", "expected an array of tables, e.g. [[objects]]")),
+    };
+    for (i, value) in objects.iter().enumerate() {
+        let key: String = format!("objects[{}]", i);
+        let object: Section = match value.as_table() {
+            Some(table) => Section { file, path: key, table },
+            None => return Err(root.error(&key, "expected a table")),
+        };
+        parse_object(&object, Some(&materials), base_dir, &mut meshes, &mut world)?;
+    }
+
+    Ok(Scene { world: world.into_bvh(), background, fog, camera, settings })
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let file: String = path.display().to_string();
+    let source: String = fs::read_to_string(path)
+        .map_err(|e| SceneError::scene_error(&file, "", format!("cannot read scene file: {}", e)))?;
+    parse_scene(&source, &file, path.parent())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::hittable::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::image_writer::ImageWriter;
+    use crate::hdr_writer::RadianceHdrWriter;
+
+    // Image and camera sections that the test scenes start with
+    const HEADER: &str = r#"
+        [image]
+        width = 40
+        aspect_ratio = 2.0
+        samples_per_pixel = 4
+
+        [camera]
+        lookfrom = [0, 0, 1]
+        lookat = [0, 0, 0]
+        vfov = 90
+    "#;
+
+    pub(super) const RED: &str = r#"
+        [materials.red]
+        type = "lambertian"
+        albedo = [0.8, 0.1, 0.1]
+    "#;
+
+    // Ball straight ahead of the camera, for scenes that only need
+    // something in them
+    const RED_BALL: &str = r#"
+        [materials.red]
+        type = "lambertian"
+        albedo = [0.8, 0.1, 0.1]
+
+        [[objects]]
+        type = "sphere"
+        center = [0, 0, -1]
+        radius = 0.5
+        material = "red"
+    "#;
+
+    // Parses `body` after the image and camera sections
+    pub(super) fn parse_body(body: &str, base_dir: Option<&Path>) -> Result<Scene, SceneError> {
+        parse_scene(&(HEADER.to_string() + body), "test.toml", base_dir)
+    }
+
+    pub(super) fn parse_error(source: &str) -> SceneError {
+        match parse_scene(source, "test.toml", None) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    pub(super) fn body_error(body: &str) -> SceneError {
+        parse_error(&(HEADER.to_string() + body))
+    }
+
+    // What a ball straight ahead is made of, when `materials` defines the
+    // material named "ball"
+    fn ball_material(materials: &str) -> Result<Material, SceneError> {
+        let scene: Scene = parse_body(&(materials.to_string() + r#"
+        [[objects]]
+        type = "sphere"
+        center = [0, 0, -1]
+        radius = 0.5
+        material = "ball"
+        "#), None)?;
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        Ok(*rec.mat_ptr)
+    }
+
+    fn ball_material_error(materials: &str) -> SceneError {
+        match ball_material(materials) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_scene_file_parse() {
+        let scene: Scene = parse_body(&(RED_BALL.to_string() + r#"
+        [background]
+        type = "color"
+        color = [0, 0, 0.1]
+        "#), None).unwrap();
+        assert_eq!((scene.settings.image_width, scene.settings.image_height), (40, 20));
+        assert_eq!((scene.settings.samples_per_pixel, scene.settings.max_depth), (4, 50));
+        assert_eq!(scene.background, Background::Color(Vec3::new(0.0, 0.0, 0.1)));
+        assert_eq!((scene.camera.shutter_open, scene.camera.shutter_close), (0.0, 1.0));
+
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.5).abs() < 1e-9);
+        assert_eq!(*rec.mat_ptr,
+            Material::Lambertian { lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.1, 0.1).into()) });
+
+        // Textures may refer to each other by name
+        let tiled: Material = ball_material(r#"
+        [textures.tiles]
+        type = "checker"
+        scale = 0.5
+        even = [1, 1, 1]
+        odd = "stone"
+
+        [textures.stone]
+        type = "noise"
+        kind = "marble"
+        scale = 4
+
+        [materials.ball]
+        type = "metal"
+        albedo = "tiles"
+        "#).unwrap();
+        assert!(matches!(tiled, Material::Metal { .. }));
+    }
+
+    #[test]
+    fn test_scene_file_errors_name_the_key() {
+        let e: SceneError = body_error(r#"
+        [[objects]]
+        type = "sphere"
+        center = [0, 0, -1]
+        radius = 0.5
+        material = "steel"
+        "#);
+        assert_eq!(e.key, "objects[0].material");
+        assert_eq!(e.to_string(), "test.toml: objects[0].material: unknown material 'steel'");
+
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "dielectric"
+        ir = "1.5"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.ir", "expected a number"));
+
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "rough_dielectric"
+        ir = 0
+        roughness = 0.2
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.ir", "must be positive"));
+
+        let e: SceneError = parse_error(r#"
+        [image]
+        width = 40
+
+        [camera]
+        lookfrom = [0, 0, 1]
+        lookat = [0, 0, 0]
+        fov = 90
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("camera.fov", "unknown key"));
+
+        // Looking straight down with the default vup
+        let e: SceneError = parse_error(r#"
+        [image]
+        width = 40
+
+        [camera]
+        lookfrom = [0, 5, 0]
+        lookat = [0, 0, 0]
+        vfov = 90
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("camera.vup", "must not be parallel to the view direction"));
+
+        let e: SceneError = parse_error(r#"
+        [image]
+        height = 20
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("image.width", "missing required key"));
+
+        let e: SceneError = ball_material_error(r#"
+        [textures.tiles]
+        type = "checker"
+        scale = 1
+        even = [1, 1, 1]
+        odd = "tiles"
+
+        [materials.ball]
+        type = "lambertian"
+        albedo = "tiles"
+        "#);
+        assert_eq!(e.to_string(), "test.toml: textures.tiles.odd: texture 'tiles' refers back to itself");
+
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "lambertian"
+        albedo = "wood"
+        "#);
+        assert_eq!(e.to_string(), "test.toml: materials.ball.albedo: unknown texture 'wood'");
+
+        let e: SceneError = body_error(r#"
+        [textures.stone]
+        type = "noise"
+        kind = "wood"
+        "#);
+        assert_eq!(e.key, "textures.stone.kind");
+
+        let e: SceneError = body_error(r#"
+        [textures.trap]
+        type = "ramp"
+        map = "blackbody"
+        coordinate = "w"
+        "#);
+        assert_eq!(e.key, "textures.trap.coordinate");
+
+        let e: SceneError = body_error(r#"
+        [background]
+        type = "stars"
+        "#);
+        assert_eq!(e.key, "background.type");
+
+        let e: SceneError = parse_error("[image\nwidth = 1");
+        assert_eq!(e.key, "");
+        assert!(e.message.contains("line 1"));
+    }
+
+    #[test]
+    fn test_scene_file_microfacet_materials() {
+        let copper: Material = ball_material(r#"
+        [materials.ball]
+        type = "conductor"
+        preset = "copper"
+        roughness = 0.2
+        "#).unwrap();
+        assert_eq!(copper, Material::Conductor { conductor: ConductorMaterial::preset("copper", 0.2).unwrap() });
+        let glass: Material = ball_material(r#"
+        [materials.ball]
+        type = "rough_dielectric"
+        ir = 1.5
+        roughness = 0.3
+        "#).unwrap();
+        assert_eq!(glass, Material::RoughDielectric { rough_dielectric: RoughDielectricMaterial::rough_dielectric(1.5, 0.3) });
+
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "conductor"
+        preset = "brass"
+        "#);
+        assert_eq!(e.key, "materials.ball.preset");
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "conductor"
+        eta = [1, 1, 1]
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.k", "missing required key"));
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "rough_dielectric"
+        ir = 1.5
+        roughness = 3
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.roughness", "must be between 0 and 1"));
+    }
+
+    #[test]
+    fn test_scene_file_principled_material() {
+        let material: Material = ball_material(r#"
+        [materials.ball]
+        type = "principled"
+        base_color = [0.8, 0.1, 0.1]
+        metallic = 1
+        roughness = [0.2, 0.4, 0.6]
+        "#).unwrap();
+        assert_eq!(material, Material::Principled { principled: Box::new(PrincipledMaterial::principled(
+            Vec3::new(0.8, 0.1, 0.1).into(), 1.0.into(), Vec3::new(0.2, 0.4, 0.6).into(), 0.5.into(), 0.0.into(), 0.5.into(), 0.0.into(),
+            1.0.into(), 0.0.into())) });
+
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "principled"
+        base_color = [0.8, 0.1, 0.1]
+        metallic = 2
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.metallic", "must be between 0 and 1"));
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "principled"
+        base_color = [0.8, 0.1, 0.1]
+        metalic = 1
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.metalic", "unknown key"));
+        let e: SceneError = ball_material_error(r#"
+        [materials.ball]
+        type = "principled"
+        base_color = [0.8, 0.1, 0.1]
+        roughness = "wood"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("materials.ball.roughness", "unknown texture 'wood'"));
+    }
+
+    #[test]
+    fn test_scene_file_fog() {
+        let scene: Scene = parse_body(&(RED_BALL.to_string() + r#"
+        [background]
+        type = "color"
+        color = [0, 0, 0.1]
+        fog = { density = 0.05, albedo = [0.9, 0.9, 0.9] }
+        "#), None).unwrap();
+        assert_eq!(scene.fog, Some(Fog::fog(0.05, Vec3::new(0.9, 0.9, 0.9))));
+
+        let e: SceneError = body_error(r#"
+        [background]
+        type = "color"
+        color = [0, 0, 0.1]
+        fog = { density = 0, albedo = [0.9, 0.9, 0.9] }
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("background.fog.density", "must be positive"));
+    }
+
+    #[test]
+    fn test_scene_file_environment_map() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("scene_file_environment_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut fb: Framebuffer = Framebuffer::framebuffer(2, 1);
+        fb.set_block(0, 0, 2, &[Vec3::new(4.0, 2.0, 1.0), Vec3::new(0.5, 0.5, 0.5)]);
+        let mut hdr_data: Vec<u8> = Vec::new();
+        RadianceHdrWriter {}.write(&fb, &mut hdr_data).unwrap();
+        fs::write(dir.join("studio.hdr"), &hdr_data).unwrap();
+
+        let background = |path: &str, intensity: f64| -> Result<Scene, SceneError> {
+            parse_body(&format!(r#"{}
+        [background]
+        type = "image"
+        path = "{}"
+        rotation = 180
+        intensity = {}
+        "#, RED_BALL, path, intensity), Some(&dir))
+        };
+        let scene: Result<Scene, SceneError> = background("studio.hdr", 2.0);
+        let negative: Result<Scene, SceneError> = background("studio.hdr", -2.0);
+        let missing: Result<Scene, SceneError> = background("attic.hdr", 2.0);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Turned half way round, straight ahead is the left half of the map
+        let background: Background = scene.unwrap().background;
+        assert!(background.is_sampled());
+        let ahead: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(background.value(&ahead), Vec3::new(8.0, 4.0, 2.0));
+
+        match (negative, missing) {
+            (Err(negative), Err(missing)) => {
+                assert_eq!((negative.key.as_str(), negative.message.as_str()), ("background.intensity", "must not be negative"));
+                assert_eq!(missing.key, "background.path");
+            },
+            _ => panic!("expected errors"),
+        }
+    }
+
+    #[test]
+    fn test_scene_file_physical_sky() {
+        let background: Background = parse_body(&(RED_BALL.to_string() + r#"
+        [background]
+        type = "physical_sky"
+        sun_elevation = 30
+        sun_azimuth = 90
+        turbidity = 4
+        "#), None).unwrap().background;
+        let (e, a): (f64, f64) = (Utils::degree_to_radians(30.0), Utils::degree_to_radians(90.0));
+        let sun: Vec3 = Vec3::new(e.cos() * a.sin(), e.sin(), -e.cos() * a.cos());
+        assert_eq!(background, Background::PhysicalSky {
+            sky: Arc::new(PhysicalSky::physical_sky(sun, 4.0, Vec3::one() * 0.3)) });
+        // Looking at the sun to the right
+        let r: Ray = Ray::ray(Vec3::zero(), sun);
+        assert!(background.value(&r).r() > 1e4);
+
+        let e: SceneError = body_error(r#"
+        [background]
+        type = "physical_sky"
+        sun_elevation = -5
+        sun_azimuth = 90
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("background.sun_elevation", "must be between 0 and 90 degrees"));
+        let e: SceneError = body_error(r#"
+        [background]
+        type = "physical_sky"
+        sun_elevation = 30
+        sun_azimuth = 90
+        turbidity = 40
+        "#);
+        assert_eq!(e.key, "background.turbidity");
+    }
+
+    #[test]
+    fn test_scene_file_image_texture() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("scene_file_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("red.ppm"), "P3 1 1 255\n255 0 0\n").unwrap();
+
+        let source: String = RED_BALL.to_string() + r#"
+        [textures.photo]
+        type = "image"
+        path = "red.ppm"
+        wrap = "clamp"
+        "#;
+        let scene: Result<Scene, SceneError> = parse_body(&source, Some(&dir));
+        let e: SceneError = body_error(&source);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(scene.is_ok());
+        assert_eq!(e.key, "textures.photo.path");
+    }
+
+    #[test]
+    fn test_scene_file_examples_load() {
+        let dir: &Path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes"));
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|e| e == "toml").unwrap_or(false) {
+                if let Err(e) = load_scene(&path) {
+                    panic!("{}", e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,884 @@
+// Objects of a scene file: primitives, CSG and distance field trees,
+// fractals, media and OBJ models, each placed by the optional transform keys
+use super::{Section, SceneError, value_to_f64, value_to_vec3, resolve_path, parse_color_map};
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::hittable_list::HittableList;
+use crate::sphere::Sphere;
+use crate::moving_sphere::MovingSphere;
+use crate::hittable::Hittable;
+use crate::mat4::Transform;
+use crate::transformed::Transformed;
+use crate::triangle::Triangle;
+use crate::quad::Quad;
+use crate::plane::{Plane, Disk};
+use crate::cuboid::Cuboid;
+use crate::cylinder::Cylinder;
+use crate::torus::Torus;
+use crate::csg::{Csg, CsgOperation};
+use crate::sdf::{Sdf, SdfHittable};
+use crate::fractal::{Fractal, FractalHittable};
+use crate::obj_loader;
+use crate::medium::*;
+use crate::voxel_grid::{self, VoxelGrid};
+use crate::aabb::Aabb;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Without a material table the object only bounds a medium and its
+// surface is never shaded, so it may leave out the material
+fn lookup_material(object: &Section, materials: Option<&HashMap<String, Material>>) -> Result<Box<Material>, SceneError> {
+    let materials: &HashMap<String, Material> = match materials {
+        Some(materials) => materials,
+        None => return Ok(Box::default()),
+    };
+    let name: &str = object.string("material")?;
+    match materials.get(name) {
+        Some(material) => Ok(Box::new(material.clone())),
+        None => Err(object.error("material", &format!("unknown material '{}'", name))),
+    }
+}
+
+fn parse_grid_medium(object: &Section, base_dir: Option<&Path>) -> Result<GridMedium, SceneError> {
+    let path: PathBuf = resolve_path(object.string("path")?, base_dir);
+    let grids: HashMap<String, Arc<VoxelGrid>> = voxel_grid::load_voxels(&path)
+        .map_err(|e| object.error("path", &e.to_string()))?
+        .into_iter()
+        .map(|(name, grid)| (name, Arc::new(grid)))
+        .collect();
+    let channel = |key: &str, name: &str| -> Result<Arc<VoxelGrid>, SceneError> {
+        grids.get(name)
+            .cloned()
+            .ok_or_else(|| object.error(key, &format!("no channel '{}' in the voxel file", name)))
+    };
+
+    let density: Arc<VoxelGrid> = channel("density", object.optional("density", Section::string)?.unwrap_or("density"))?;
+    let density_scale: f64 = object.optional("density_scale", Section::number)?.unwrap_or(1.0);
+    if density_scale < 0.0 {
+        return Err(object.error("density_scale", "must not be negative"));
+    }
+    let albedo: ColorMap = match object.optional("albedo", parse_color_map)? {
+        Some(albedo) => albedo,
+        None => ColorMap::constant(Vec3::one()),
+    };
+
+    let emission: Option<GridEmission> = match object.optional("emission", Section::string)? {
+        Some(name) => Some(GridEmission::grid_emission(
+            channel("emission", name)?,
+            object.optional("emission_map", parse_color_map)?.unwrap_or(ColorMap::Blackbody),
+            object.optional("emission_scale", Section::number)?.unwrap_or(1.0))),
+        None => None,
+    };
+
+    let bounds: Aabb = Aabb::aabb(object.vec3("min")?, object.vec3("max")?);
+    let extent: Vec3 = bounds.extent();
+    if extent.x() <= 0.0 || extent.y() <= 0.0 || extent.z() <= 0.0 {
+        return Err(object.error("max", "must differ from min along every axis"));
+    }
+
+    Ok(GridMedium::grid_medium(density, density_scale, albedo, emission, bounds))
+}
+
+// Any object can also be placed with a transform
+const TRANSFORM_KEYS: [&str; 3] = ["scale", "rotate", "translate"];
+
+fn check_object_keys(object: &Section, keys: &[&str]) -> Result<(), SceneError> {
+    object.check_keys(&[keys, &TRANSFORM_KEYS].concat())
+}
+
+// Scales first, then rotates about x, y and z in turn (in degrees), then
+// translates. None when the object has none of the transform keys.
+fn parse_transform(object: &Section) -> Result<Option<Transform>, SceneError> {
+    if !TRANSFORM_KEYS.iter().any(|key| object.table.contains_key(*key)) {
+        return Ok(None);
+    }
+
+    let mut transform: Transform = Transform::identity();
+    if let Some(value) = object.table.get("scale") {
+        let factors: Vec3 = match value_to_f64(value) {
+            Some(factor) => Vec3::one() * factor,
+            None => value_to_vec3(value)
+                .ok_or_else(|| object.error("scale", "expected a number or an array of 3 numbers"))?,
+        };
+        transform = Transform::scale(&factors).ok_or_else(|| object.error("scale", "must not be zero"))?;
+    }
+    if let Some(angles) = object.optional("rotate", Section::vec3)? {
+        let axes: [Vec3; 3] = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+        for (axis, angle) in axes.iter().zip(angles.e) {
+            transform = transform.then(&Transform::rotate(axis, angle));
+        }
+    }
+    if let Some(offset) = object.optional("translate", Section::vec3)? {
+        transform = transform.then(&Transform::translate(&offset));
+    }
+    Ok(Some(transform))
+}
+
+// A vector that only gives a direction, so must not be zero
+fn parse_direction(object: &Section, key: &str) -> Result<Vec3, SceneError> {
+    let direction: Vec3 = object.vec3(key)?;
+    if direction.near_zero() {
+        return Err(object.error(key, "must not be zero"));
+    }
+    Ok(direction)
+}
+
+// Centers of the base and top of a cylinder or cone
+fn parse_axis(object: &Section) -> Result<(Vec3, Vec3), SceneError> {
+    let (base, top): (Vec3, Vec3) = (object.vec3("base")?, object.vec3("top")?);
+    if (top - base).near_zero() {
+        return Err(object.error("top", "must differ from base"));
+    }
+    Ok((base, top))
+}
+
+// Positive number under `key`
+fn parse_size(node: &Section, key: &str) -> Result<f64, SceneError> {
+    let size: f64 = node.number(key)?;
+    if size <= 0.0 {
+        return Err(node.error(key, "must be positive"));
+    }
+    Ok(size)
+}
+
+// Distance function tree, each node a table with its own type. Any node can
+// be placed like an object, but only scaled uniformly, since stretching
+// would break the distances. Twist and bend rates are in degrees per unit.
+fn parse_sdf(node: &Section) -> Result<Sdf, SceneError> {
+    let child = |key: &str| -> Result<Box<Sdf>, SceneError> { Ok(Box::new(parse_sdf(&node.section(key)?)?)) };
+    let sdf: Sdf = match node.string("type")? {
+        "sphere" => {
+            check_object_keys(node, &["type", "radius"])?;
+            Sdf::Sphere { radius: parse_size(node, "radius")? }
+        },
+        "box" => {
+            check_object_keys(node, &["type", "half_size"])?;
+            let half_size: Vec3 = node.vec3("half_size")?;
+            if half_size.x() <= 0.0 || half_size.y() <= 0.0 || half_size.z() <= 0.0 {
+                return Err(node.error("half_size", "must be positive along every axis"));
+            }
+            Sdf::Box { half_size }
+        },
+        "torus" => {
+            check_object_keys(node, &["type", "major_radius", "minor_radius"])?;
+            Sdf::Torus { major_radius: parse_size(node, "major_radius")?, minor_radius: parse_size(node, "minor_radius")? }
+        },
+        "cylinder" => {
+            check_object_keys(node, &["type", "radius", "half_height"])?;
+            Sdf::Cylinder { radius: parse_size(node, "radius")?, half_height: parse_size(node, "half_height")? }
+        },
+        "capsule" => {
+            check_object_keys(node, &["type", "a", "b", "radius"])?;
+            Sdf::Capsule { a: node.vec3("a")?, b: node.vec3("b")?, radius: parse_size(node, "radius")? }
+        },
+        name @ ("union" | "intersection" | "difference") => {
+            // `smooth` is the distance over which the two shapes blend
+            check_object_keys(node, &["type", "a", "b", "smooth"])?;
+            let k: f64 = node.optional("smooth", Section::number)?.unwrap_or(0.0);
+            if k < 0.0 {
+                return Err(node.error("smooth", "must not be negative"));
+            }
+            let (a, b): (Box<Sdf>, Box<Sdf>) = (child("a")?, child("b")?);
+            match name {
+                "union" => Sdf::Union { a, b, k },
+                "intersection" => Sdf::Intersection { a, b, k },
+                _ => Sdf::Difference { a, b, k },
+            }
+        },
+        "round" => {
+            check_object_keys(node, &["type", "shape", "radius"])?;
+            Sdf::Round { sdf: child("shape")?, radius: parse_size(node, "radius")? }
+        },
+        "repeat" => {
+            check_object_keys(node, &["type", "shape", "period"])?;
+            let period: Vec3 = node.vec3("period")?;
+            if period.x() < 0.0 || period.y() < 0.0 || period.z() < 0.0 || period.near_zero() {
+                return Err(node.error("period", "must not be negative, and not zero along every axis"));
+            }
+            Sdf::Repeat { sdf: child("shape")?, period }
+        },
+        "twist" => {
+            check_object_keys(node, &["type", "shape", "rate"])?;
+            Sdf::Twist { sdf: child("shape")?, rate: node.number("rate")?.to_radians() }
+        },
+        "bend" => {
+            check_object_keys(node, &["type", "shape", "rate"])?;
+            Sdf::Bend { sdf: child("shape")?, rate: node.number("rate")?.to_radians() }
+        },
+        other => return Err(node.error("type", &format!(
+            "unknown shape type '{}', expected sphere, box, torus, cylinder, capsule, union, intersection, difference, \
+             round, repeat, twist or bend", other))),
+    };
+
+    let mut sdf: Sdf = sdf;
+    if let Some(factor) = node.optional("scale", Section::number)? {
+        if factor <= 0.0 {
+            return Err(node.error("scale", "must be positive"));
+        }
+        sdf = Sdf::Scale { sdf: Box::new(sdf), factor };
+    }
+    if let Some(angles) = node.optional("rotate", Section::vec3)? {
+        let axes: [Vec3; 3] = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+        let rotation: Transform = axes.iter().zip(angles.e)
+            .fold(Transform::identity(), |rotation, (axis, angle)| rotation.then(&Transform::rotate(axis, angle)));
+        sdf = Sdf::Rotate { sdf: Box::new(sdf), to_local: Box::new(rotation.inverse()) };
+    }
+    if let Some(offset) = node.optional("translate", Section::vec3)? {
+        sdf = Sdf::Translate { sdf: Box::new(sdf), offset };
+    }
+    Ok(sdf)
+}
+
+// Transformed OBJ models are loaded once per path and shared by every object
+// that uses them
+pub(super) fn parse_object(object: &Section, materials: Option<&HashMap<String, Material>>, base_dir: Option<&Path>,
+                           meshes: &mut HashMap<PathBuf, Arc<dyn Hittable>>, world: &mut HittableList) -> Result<(), SceneError> {
+    let transform: Option<Transform> = parse_transform(object)?;
+    let shape: Box<dyn Hittable> = match object.string("type")? {
+        "sphere" => {
+            check_object_keys(object, &["type", "center", "radius", "material"])?;
+            let radius: f64 = object.number("radius")?;
+            if radius == 0.0 {
+                return Err(object.error("radius", "must not be zero"));
+            }
+            Box::new(Sphere::sphere(object.vec3("center")?, radius, lookup_material(object, materials)?))
+        },
+        "moving_sphere" => {
+            check_object_keys(object, &["type", "center0", "center1", "time0", "time1", "radius", "material"])?;
+            let radius: f64 = object.number("radius")?;
+            if radius == 0.0 {
+                return Err(object.error("radius", "must not be zero"));
+            }
+            let time0: f64 = object.optional("time0", Section::number)?.unwrap_or(0.0);
+            let time1: f64 = object.optional("time1", Section::number)?.unwrap_or(1.0);
+            if time1 <= time0 {
+                return Err(object.error("time1", "must be after time0"));
+            }
+            Box::new(MovingSphere::moving_sphere(
+                object.vec3("center0")?, object.vec3("center1")?, time0, time1, radius,
+                lookup_material(object, materials)?))
+        },
+        "triangle" => {
+            check_object_keys(object, &["type", "vertices", "material"])?;
+            let vertices: Vec<Vec3> = match object.get("vertices")?.as_array() {
+                Some(array) if array.len() == 3 => array.iter()
+                    .enumerate()
+                    .map(|(i, v)| value_to_vec3(v).ok_or_else(||
+                        object.error(&format!("vertices[{}]", i), "expected an array of 3 numbers")))
+                    .collect::<Result<Vec<Vec3>, SceneError>>()?,
+                _ => return Err(object.error("vertices", "expected an array of 3 points")),
+            };
+            Box::new(Triangle::triangle(
+                vertices[0], vertices[1], vertices[2], lookup_material(object, materials)?))
+        },
+        "quad" => {
+            check_object_keys(object, &["type", "q", "u", "v", "material"])?;
+            let u: Vec3 = object.vec3("u")?;
+            let v: Vec3 = object.vec3("v")?;
+            if Utils::cross(&u, &v).near_zero() {
+                return Err(object.error("v", "must not be parallel to u"));
+            }
+            Box::new(Quad::quad(object.vec3("q")?, u, v, lookup_material(object, materials)?))
+        },
+        "plane" => {
+            check_object_keys(object, &["type", "point", "normal", "material"])?;
+            Box::new(Plane::plane(object.vec3("point")?, parse_direction(object, "normal")?,
+                                  lookup_material(object, materials)?))
+        },
+        "disk" => {
+            check_object_keys(object, &["type", "center", "normal", "radius", "material"])?;
+            let radius: f64 = object.number("radius")?;
+            if radius <= 0.0 {
+                return Err(object.error("radius", "must be positive"));
+            }
+            Box::new(Disk::disk(object.vec3("center")?, parse_direction(object, "normal")?, radius,
+                                lookup_material(object, materials)?))
+        },
+        "box" => {
+            // Oriented boxes are turned into place with `rotate`
+            check_object_keys(object, &["type", "min", "max", "material"])?;
+            let (min, max): (Vec3, Vec3) = (object.vec3("min")?, object.vec3("max")?);
+            if min.x() >= max.x() || min.y() >= max.y() || min.z() >= max.z() {
+                return Err(object.error("max", "must be greater than min along every axis"));
+            }
+            Box::new(Cuboid::cuboid(min, max, lookup_material(object, materials)?))
+        },
+        "cylinder" => {
+            check_object_keys(object, &["type", "base", "top", "radius", "material"])?;
+            let radius: f64 = object.number("radius")?;
+            if radius <= 0.0 {
+                return Err(object.error("radius", "must be positive"));
+            }
+            let (base, top): (Vec3, Vec3) = parse_axis(object)?;
+            Box::new(Cylinder::cylinder(base, top, radius, lookup_material(object, materials)?))
+        },
+        "cone" => {
+            // A top radius above zero cuts the cone off flat
+            check_object_keys(object, &["type", "base", "top", "base_radius", "top_radius", "material"])?;
+            let base_radius: f64 = object.number("base_radius")?;
+            let top_radius: f64 = object.optional("top_radius", Section::number)?.unwrap_or(0.0);
+            if base_radius < 0.0 || top_radius < 0.0 || base_radius + top_radius == 0.0 {
+                return Err(object.error("base_radius", "radii must not be negative, and not both zero"));
+            }
+            let (base, top): (Vec3, Vec3) = parse_axis(object)?;
+            Box::new(Cylinder::cone(base, top, base_radius, top_radius, lookup_material(object, materials)?))
+        },
+        "torus" => {
+            check_object_keys(object, &["type", "center", "axis", "major_radius", "minor_radius", "material"])?;
+            let axis: Vec3 = object.optional("axis", parse_direction)?.unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+            let major_radius: f64 = object.number("major_radius")?;
+            let minor_radius: f64 = object.number("minor_radius")?;
+            if minor_radius <= 0.0 || minor_radius >= major_radius {
+                return Err(object.error("minor_radius", "must be positive and less than major_radius"));
+            }
+            Box::new(Torus::torus(object.vec3("center")?, axis, major_radius, minor_radius,
+                                  lookup_material(object, materials)?))
+        },
+        "csg" => {
+            // Both sides are closed objects, written inline with their own
+            // materials
+            check_object_keys(object, &["type", "operation", "left", "right"])?;
+            let name: &str = object.string("operation")?;
+            let operation: CsgOperation = CsgOperation::from_name(name).ok_or_else(|| object.error("operation", &format!(
+                "unknown operation '{}', expected union, intersection or difference", name)))?;
+            let mut left: HittableList = HittableList::default();
+            parse_object(&object.section("left")?, materials, base_dir, meshes, &mut left)?;
+            let mut right: HittableList = HittableList::default();
+            parse_object(&object.section("right")?, materials, base_dir, meshes, &mut right)?;
+            Box::new(Csg::csg(operation, Box::new(left), Box::new(right)))
+        },
+        "sdf" => {
+            // Endless repetitions need `min` and `max` to bound the space
+            // that is marched through, and any shape can be clipped by them
+            check_object_keys(object, &["type", "shape", "min", "max", "material"])?;
+            let sdf: Sdf = parse_sdf(&object.section("shape")?)?;
+            let bounds: Aabb = match (object.optional("min", Section::vec3)?, object.optional("max", Section::vec3)?) {
+                (Some(min), Some(max)) => {
+                    if min.x() >= max.x() || min.y() >= max.y() || min.z() >= max.z() {
+                        return Err(object.error("max", "must be greater than min along every axis"));
+                    }
+                    Aabb::aabb(min, max)
+                },
+                (None, None) => sdf.bounding_box()
+                    .ok_or_else(|| object.error("shape", "repeats endlessly, give min and max to bound it"))?,
+                (Some(_), None) => return Err(object.error("max", "missing required key")),
+                (None, Some(_)) => return Err(object.error("min", "missing required key")),
+            };
+            Box::new(SdfHittable::sdf_hittable(sdf, bounds, lookup_material(object, materials)?))
+        },
+        name @ ("mandelbulb" | "julia" | "menger") => {
+            // Fractals sit at the origin and are placed with the transform
+            // keys. More iterations bring out finer detail.
+            let fractal: Fractal = match name {
+                "mandelbulb" => {
+                    check_object_keys(object, &["type", "power", "iterations", "material"])?;
+                    let power: f64 = object.optional("power", Section::number)?.unwrap_or(8.0);
+                    if power < 2.0 {
+                        return Err(object.error("power", "must be at least 2"));
+                    }
+                    Fractal::Mandelbulb { power }
+                },
+                "julia" => {
+                    check_object_keys(object, &["type", "c", "iterations", "material"])?;
+                    let c: [f64; 4] = match object.get("c")?.as_array().map(|c| c.iter().map(value_to_f64).collect::<Option<Vec<f64>>>()) {
+                        Some(Some(c)) if c.len() == 4 => [c[0], c[1], c[2], c[3]],
+                        _ => return Err(object.error("c", "expected an array of 4 numbers")),
+                    };
+                    Fractal::Julia { c }
+                },
+                _ => {
+                    check_object_keys(object, &["type", "iterations", "material"])?;
+                    Fractal::Menger
+                },
+            };
+            let default_iterations: u32 = if fractal == Fractal::Menger { 4 } else { 10 };
+            let iterations: u32 = object.optional("iterations", Section::count)?.unwrap_or(default_iterations);
+            Box::new(FractalHittable::fractal_hittable(fractal, iterations, lookup_material(object, materials)?))
+        },
+        "constant_medium" => {
+            check_object_keys(object, &["type", "boundary", "density", "albedo"])?;
+            let density: f64 = object.number("density")?;
+            if density <= 0.0 {
+                return Err(object.error("density", "must be positive"));
+            }
+            let mut boundary: HittableList = HittableList::default();
+            parse_object(&object.section("boundary")?, None, base_dir, meshes, &mut boundary)?;
+            Box::new(ConstantMedium::constant_medium(Box::new(boundary), density, object.vec3("albedo")?.into()))
+        },
+        "voxel_grid" => {
+            check_object_keys(object, &["type", "path", "min", "max", "density", "density_scale", "albedo",
+                                        "emission", "emission_map", "emission_scale"])?;
+            Box::new(parse_grid_medium(object, base_dir)?)
+        },
+        "obj" => {
+            // Materials come from the OBJ's own MTL libraries
+            check_object_keys(object, &["type", "path"])?;
+            let path: PathBuf = resolve_path(object.string("path")?, base_dir);
+            let load = || obj_loader::load_obj(&path).map_err(|e| object.error("path", &e.to_string()));
+
+            let transform: Transform = match transform {
+                Some(transform) => transform,
+                None => {
+                    world.append(load()?);
+                    return Ok(());
+                },
+            };
+            let mesh: Arc<dyn Hittable> = match meshes.get(&path) {
+                Some(mesh) => mesh.clone(),
+                None => {
+                    let mesh: Arc<dyn Hittable> = Arc::new(load()?.into_bvh());
+                    meshes.insert(path.clone(), mesh.clone());
+                    mesh
+                },
+            };
+            world.add(Box::new(Transformed::transformed(mesh, transform)));
+            return Ok(());
+        },
+        other => return Err(object.error("type", &format!(
+            "unknown object type '{}', expected sphere, moving_sphere, triangle, quad, plane, disk, box, cylinder, cone, torus, \
+             csg, sdf, mandelbulb, julia, menger, constant_medium, voxel_grid or obj", other))),
+    };
+
+    match transform {
+        Some(transform) => world.add(Box::new(Transformed::transformed(Arc::from(shape), transform))),
+        None => world.add(shape),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::{RED, parse_body, body_error};
+    use crate::scene::Scene;
+    use crate::ray::Ray;
+    use crate::hittable::HitRecord;
+    use crate::material::{Scatter, LambertianMaterial, DielectricMaterial};
+    use crate::texture::*;
+    use std::fs;
+
+    // Parses `objects` in a scene where they can use the material "red"
+    fn parse_objects(objects: &str) -> Result<Scene, SceneError> {
+        parse_body(&(RED.to_string() + objects), None)
+    }
+
+    // The error from a scene with just the object given by `keys`
+    fn object_error(keys: &str) -> SceneError {
+        body_error(&format!("{}\n        [[objects]]{}", RED, keys))
+    }
+
+    fn red() -> Material {
+        Material::Lambertian { lambertian: LambertianMaterial::lambertian(Vec3::new(0.8, 0.1, 0.1).into()) }
+    }
+
+    #[test]
+    fn test_scene_file_transforms() {
+        // A sphere moved into place by a transform is hit where it ends up
+        let scene: Scene = parse_objects(r#"
+        [[objects]]
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 0.5
+        material = "red"
+        scale = 2
+        translate = [0, 0, -2]
+        "#).unwrap();
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-9);
+        assert_eq!(*rec.mat_ptr, red());
+
+        let e: SceneError = object_error(r#"
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 0.5
+        material = "red"
+        scale = [1, 0, 1]
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].scale", "must not be zero"));
+    }
+
+    #[test]
+    fn test_scene_file_moving_sphere() {
+        // Only in the way of rays late in the shutter
+        let scene: Scene = parse_objects(r#"
+        [[objects]]
+        type = "moving_sphere"
+        center0 = [5, 0, -1]
+        center1 = [6, 0, -1]
+        radius = 0.5
+        material = "red"
+        "#).unwrap();
+        let mut rec: HitRecord = HitRecord::default();
+        let r: Ray = Ray::ray_with_time(Vec3::new(6.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 1.0);
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        let r: Ray = Ray::ray_with_time(Vec3::new(6.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(!scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+
+        let e: SceneError = object_error(r#"
+        type = "moving_sphere"
+        center0 = [5, 0, -1]
+        center1 = [6, 0, -1]
+        time1 = -1
+        radius = 0.5
+        material = "red"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].time1", "must be after time0"));
+    }
+
+    #[test]
+    fn test_scene_file_primitives() {
+        // Each shape sits on its own spot along x, 3 units below the origin
+        let scene: Scene = parse_objects(r#"
+        [[objects]]
+        type = "plane"
+        point = [0, -10, 0]
+        normal = [0, 2, 0]
+        material = "red"
+
+        [[objects]]
+        type = "disk"
+        center = [10, -3, 0]
+        normal = [0, 1, 0]
+        radius = 0.5
+        material = "red"
+
+        [[objects]]
+        type = "box"
+        min = [19, -4, -1]
+        max = [21, -3, 1]
+        material = "red"
+
+        [[objects]]
+        type = "cylinder"
+        base = [30, -5, 0]
+        top = [30, -3, 0]
+        radius = 0.5
+        material = "red"
+
+        [[objects]]
+        type = "cone"
+        base = [40, -5, 0]
+        top = [40, -3, 0]
+        base_radius = 1
+        material = "red"
+
+        [[objects]]
+        type = "torus"
+        center = [51, -3.5, 0]
+        major_radius = 1
+        minor_radius = 0.5
+        material = "red"
+
+        [[objects]]
+        type = "triangle"
+        vertices = [[59, -3, 1], [61, -3, 1], [60, -3, -1]]
+        material = "red"
+
+        [[objects]]
+        type = "quad"
+        q = [69, -3, -1]
+        u = [0, 0, 2]
+        v = [2, 0, 0]
+        material = "red"
+        "#).unwrap();
+
+        let mut rec: HitRecord = HitRecord::default();
+        for (x, t) in [(-20.0, 10.0), (10.0, 3.0), (20.0, 3.0), (30.0, 3.0), (40.0, 3.0), (50.0, 3.0), (60.0, 3.0), (70.0, 3.0)] {
+            let r: Ray = Ray::ray(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+            assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec), "nothing at x = {}", x);
+            assert!((rec.t - t).abs() < 1e-9, "hit at t = {} for x = {}", rec.t, x);
+            assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        }
+
+        let e: SceneError = object_error(r#"
+        type = "plane"
+        point = [0, -10, 0]
+        normal = [0, 0, 0]
+        material = "red"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].normal", "must not be zero"));
+        let e: SceneError = object_error(r#"
+        type = "box"
+        min = [19, -4, -1]
+        max = [21, -3, -1]
+        material = "red"
+        "#);
+        assert_eq!(e.key, "objects[0].max");
+        let e: SceneError = object_error(r#"
+        type = "cylinder"
+        base = [30, -5, 0]
+        top = [30, -5, 0]
+        radius = 0.5
+        material = "red"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].top", "must differ from base"));
+        let e: SceneError = object_error(r#"
+        type = "torus"
+        center = [51, -3.5, 0]
+        major_radius = 1
+        minor_radius = 1.5
+        material = "red"
+        "#);
+        assert_eq!(e.key, "objects[0].minor_radius");
+        let e: SceneError = object_error(r#"
+        type = "triangle"
+        vertices = [[59, -3, 1], [61, -3, 1], [60, -3]]
+        material = "red"
+        "#);
+        assert_eq!(e.key, "objects[0].vertices[2]");
+        let e: SceneError = object_error(r#"
+        type = "quad"
+        q = [69, -3, -1]
+        u = [0, 0, 2]
+        v = [0, 0, -4]
+        material = "red"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].v", "must not be parallel to u"));
+    }
+
+    #[test]
+    fn test_scene_file_csg() {
+        // A red ball with its front cut off by a glass box, and then its
+        // back instead, once the whole node is turned half around and the
+        // ball moved back into place
+        let glass: &str = r#"
+        [materials.glass]
+        type = "dielectric"
+        ir = 1.5
+        "#;
+        let cut = |placement: &str, right_material: &str| -> Result<Scene, SceneError> {
+            parse_objects(&format!(r#"{}
+        [[objects]]
+        type = "csg"
+        operation = "difference"
+        left = {{ type = "sphere", center = [0, 0, -1], radius = 0.5, material = "red" }}
+        right = {{ type = "box", min = [-1, -1, -0.8], max = [1, 1, 1], material = "{}" }}
+        {}
+        "#, glass, right_material, placement))
+        };
+
+        // Straight on, the ray passes the ball's front and meets the cut
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        let scene: Scene = cut("", "glass").unwrap();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.8).abs() < 1e-9 && rec.front_face);
+        assert_eq!(*rec.mat_ptr, Material::Dielectric { dielectric: DielectricMaterial::dielectric(1.5) });
+
+        let turned: Scene = cut("rotate = [0, 180, 0]\n        translate = [0, 0, -2]", "glass").unwrap();
+        assert!(turned.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.5).abs() < 1e-9);
+
+        match cut("", "stone") {
+            Err(e) => assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].right.material", "unknown material 'stone'")),
+            Ok(_) => panic!("expected an error"),
+        }
+        let e: SceneError = object_error(r#"
+        type = "csg"
+        operation = "xor"
+        left = { type = "sphere", center = [0, 0, -1], radius = 0.5, material = "red" }
+        right = { type = "sphere", center = [0, 0, -2], radius = 0.5, material = "red" }
+        "#);
+        assert_eq!(e.key, "objects[0].operation");
+    }
+
+    #[test]
+    fn test_scene_file_sdf() {
+        // A rounded box smoothly joined to a capsule
+        let scene: Scene = parse_objects(r#"
+        [[objects]]
+        type = "sdf"
+        material = "red"
+        translate = [0, 0, -1]
+
+        [objects.shape]
+        type = "union"
+        smooth = 0.1
+        a = { type = "round", radius = 0.1, shape = { type = "box", half_size = [0.4, 0.4, 0.4] } }
+        b = { type = "capsule", a = [0, 0, 0], b = [0, 1, 0], radius = 0.1, rotate = [0, 0, 90] }
+        "#).unwrap();
+
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.5).abs() < 1e-3 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+        assert_eq!(*rec.mat_ptr, red());
+
+        // The capsule was turned from +y to -x
+        let side: Ray = Ray::ray(Vec3::new(-0.9, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(scene.world.hit(&side, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.9).abs() < 1e-3);
+
+        let e: SceneError = object_error(r#"
+        type = "sdf"
+        material = "red"
+        shape = { type = "union", a = { type = "sphere", radius = 1 }, b = { type = "cone", radius = 1 } }
+        "#);
+        assert_eq!(e.key, "objects[0].shape.b.type");
+        let e: SceneError = object_error(r#"
+        type = "sdf"
+        material = "red"
+        shape = { type = "round", radius = 0.1, shape = { type = "box", half_size = [0.4, 0, 0.4] } }
+        "#);
+        assert_eq!(e.key, "objects[0].shape.shape.half_size");
+        let e: SceneError = object_error(r#"
+        type = "sdf"
+        material = "red"
+        shape = { type = "sphere", radius = 1, scale = [1, 2, 1] }
+        "#);
+        assert_eq!(e.key, "objects[0].shape.scale");
+
+        // Endless repetitions have to be clipped
+        let e: SceneError = object_error(r#"
+        type = "sdf"
+        material = "red"
+        shape = { type = "repeat", period = [2, 0, 0], shape = { type = "sphere", radius = 0.1 } }
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].shape", "repeats endlessly, give min and max to bound it"));
+        assert!(parse_objects(r#"
+        [[objects]]
+        type = "sdf"
+        material = "red"
+        shape = { type = "repeat", period = [2, 0, 0], shape = { type = "sphere", radius = 0.1 } }
+        min = [-3, -1, -1]
+        max = [3, 1, 1]
+        "#).is_ok());
+    }
+
+    #[test]
+    fn test_scene_file_fractals() {
+        // A small sponge colored by its orbit trap
+        let scene: Scene = parse_body(r#"
+        [textures.trap]
+        type = "ramp"
+        map = [[0, [0, 0, 0]], [1, [1, 1, 1]]]
+
+        [materials.trap]
+        type = "lambertian"
+        albedo = "trap"
+
+        [[objects]]
+        type = "menger"
+        iterations = 2
+        material = "trap"
+        scale = 0.25
+        translate = [0, 0, -1]
+        "#, None).unwrap();
+
+        // Onto the front face, halfway between the tunnel and the corner
+        let r: Ray = Ray::ray(Vec3::new(0.125, 0.125, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 0.75).abs() < 1e-3 && rec.front_face);
+        assert!((rec.u - 0.5f64.sqrt()).abs() < 1e-2);
+        let map: ColorMap = ColorMap::ramp(vec![(0.0, Vec3::zero()), (1.0, Vec3::one())]).unwrap();
+        assert_eq!(*rec.mat_ptr, Material::Lambertian { lambertian: LambertianMaterial::lambertian(
+            Texture::Ramp { ramp: RampTexture::ramp(map, TextureCoordinate::U) }) });
+
+        assert!(parse_objects(r#"
+        [[objects]]
+        type = "mandelbulb"
+        power = 6
+        material = "red"
+
+        [[objects]]
+        type = "julia"
+        c = [-0.2, 0.6, 0.2, 0.2]
+        material = "red"
+        "#).is_ok());
+        let e: SceneError = object_error(r#"
+        type = "mandelbulb"
+        power = 1
+        material = "red"
+        "#);
+        assert_eq!(e.key, "objects[0].power");
+        let e: SceneError = object_error(r#"
+        type = "julia"
+        c = [-0.2, 0.6, 0.2]
+        material = "red"
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].c", "expected an array of 4 numbers"));
+        let e: SceneError = object_error(r#"
+        type = "menger"
+        iterations = 0
+        material = "red"
+        "#);
+        assert_eq!(e.key, "objects[0].iterations");
+    }
+
+    #[test]
+    fn test_scene_file_constant_medium() {
+        // So dense that rays scatter right where they enter the boundary
+        let scene: Scene = parse_objects(r#"
+        [[objects]]
+        type = "constant_medium"
+        density = 1e6
+        albedo = [0.5, 0.5, 0.5]
+        boundary = { type = "sphere", center = [0, 0, 0], radius = 1, translate = [10, 0, 0] }
+        "#).unwrap();
+        let r: Ray = Ray::ray(Vec3::new(10.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-3);
+        assert!(matches!(*rec.mat_ptr, Material::Isotropic { .. }));
+
+        let e: SceneError = object_error(r#"
+        type = "constant_medium"
+        density = 1e6
+        albedo = [0.5, 0.5, 0.5]
+        boundary = { type = "sphere", center = [0, 0, 0], radius = 1, height = 2 }
+        "#);
+        assert_eq!((e.key.as_str(), e.message.as_str()), ("objects[0].boundary.height", "unknown key"));
+    }
+
+    #[test]
+    fn test_scene_file_voxel_grid() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("scene_file_voxels_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ball.vox"), b"VOXELS 1\nsize 1 1 1\nchannels smoke heat\ndata u8\n\xff\x80").unwrap();
+
+        let grid = |emission: &str, albedo: &str| -> Result<Scene, SceneError> {
+            parse_body(&format!(r#"
+        [[objects]]
+        type = "voxel_grid"
+        path = "ball.vox"
+        min = [9, -1, -1]
+        max = [11, 1, 1]
+        density = "smoke"
+        density_scale = 1e6
+        albedo = {}
+        emission = "{}"
+        emission_map = [0.0, 1.0, 0.0]
+        emission_scale = 2
+        "#, albedo, emission), Some(&dir))
+        };
+        let scene: Result<Scene, SceneError> = grid("heat", "[[0, [1, 1, 1]], [1, [0.5, 0.5, 0.5]]]");
+        let missing: Result<Scene, SceneError> = grid("fuel", "[[0, [1, 1, 1]], [1, [0.5, 0.5, 0.5]]]");
+        let unsorted: Result<Scene, SceneError> = grid("heat", "[[2, [1, 1, 1]], [1, [0.5, 0.5, 0.5]]]");
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Dense enough to collide where the ray enters, glowing green where
+        // half of the light is absorbed
+        let scene: Scene = scene.unwrap();
+        let r: Ray = Ray::ray(Vec3::new(10.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(scene.world.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-3);
+        assert_eq!(rec.mat_ptr.emitted(&r, &rec), Vec3::new(0.0, 1.0, 0.0));
+
+        for (result, key, message) in [
+            (missing, "objects[0].emission", "no channel 'fuel' in the voxel file"),
+            (unsorted, "objects[0].albedo", "stops must be sorted by value"),
+        ] {
+            match result {
+                Err(e) => assert_eq!((e.key.as_str(), e.message.as_str()), (key, message)),
+                Ok(_) => panic!("expected an error"),
+            }
+        }
+    }
+}
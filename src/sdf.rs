@@ -0,0 +1,370 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::mat4::Transform;
+use crate::sphere::Sphere;
+
+// A ray is on the surface once it is this close to it
+const HIT_DISTANCE: f64 = 1e-4;
+// Offset for the finite differences that give the normal
+const NORMAL_OFFSET: f64 = 1e-5;
+const MAX_STEPS: u32 = 2000;
+// Rays entering tight bounds would start right on the surface, as if
+// leaving it, so bounds are grown by this much
+pub const BOUNDS_MARGIN: f64 = 10.0 * HIT_DISTANCE;
+
+// Signed distance to a shape: negative inside and positive outside. The
+// distances of combined and modified shapes are only bounds on the true
+// distance, as in Quilez' collection of distance functions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sdf {
+    // Primitives, centered on the origin. Tori lie in the xz plane and
+    // cylinders stand along y.
+    Sphere { radius: f64 },
+    Box { half_size: Vec3 },
+    Torus { major_radius: f64, minor_radius: f64 },
+    Cylinder { radius: f64, half_height: f64 },
+    // Segment from a to b with a radius around it
+    Capsule { a: Vec3, b: Vec3, radius: f64 },
+
+    // Combinations. With a blend distance k above zero the shapes are
+    // smoothly merged where they are within k of each other.
+    Union { a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+    Intersection { a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+    Difference { a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+
+    // Grows the shape by a radius, rounding its edges
+    Round { sdf: Box<Sdf>, radius: f64 },
+    Translate { sdf: Box<Sdf>, offset: Vec3 },
+    // Turns the shape by the inverse of `to_local`
+    Rotate { sdf: Box<Sdf>, to_local: Box<Transform> },
+    Scale { sdf: Box<Sdf>, factor: f64 },
+    // Copies of the shape every `period` along each axis, or just one
+    // along axes where the period is zero. The shape has to fit in its
+    // cell.
+    Repeat { sdf: Box<Sdf>, period: Vec3 },
+    // Turns each slice across y by `rate` radians per unit of height
+    Twist { sdf: Box<Sdf>, rate: f64 },
+    // Bends the x axis around z, `rate` radians per unit along x
+    Bend { sdf: Box<Sdf>, rate: f64 },
+}
+
+// Polynomial smooth minimum, which stays below both values by at most k / 4
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h: f64 = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    -smooth_min(-a, -b, k)
+}
+
+fn abs(v: &Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+fn max_zero(v: &Vec3) -> Vec3 {
+    Vec3::new(v.x().max(0.0), v.y().max(0.0), v.z().max(0.0))
+}
+
+impl Sdf {
+    pub fn distance(&self, p: &Vec3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Box { half_size } => {
+                let q: Vec3 = abs(p) - *half_size;
+                max_zero(&q).length() + q.x().max(q.y()).max(q.z()).min(0.0)
+            },
+            Sdf::Torus { major_radius, minor_radius } => {
+                let ring: f64 = (p.x() * p.x() + p.z() * p.z()).sqrt() - major_radius;
+                (ring * ring + p.y() * p.y()).sqrt() - minor_radius
+            },
+            Sdf::Cylinder { radius, half_height } => {
+                let dx: f64 = (p.x() * p.x() + p.z() * p.z()).sqrt() - radius;
+                let dy: f64 = p.y().abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            },
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba): (Vec3, Vec3) = (*p - *a, *b - *a);
+                let h: f64 = (pa.dot(&ba) / ba.length_squared()).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            },
+            Sdf::Union { a, b, k } => smooth_min(a.distance(p), b.distance(p), *k),
+            Sdf::Intersection { a, b, k } => smooth_max(a.distance(p), b.distance(p), *k),
+            Sdf::Difference { a, b, k } => smooth_max(a.distance(p), -b.distance(p), *k),
+            Sdf::Round { sdf, radius } => sdf.distance(p) - radius,
+            Sdf::Translate { sdf, offset } => sdf.distance(&(*p - *offset)),
+            Sdf::Rotate { sdf, to_local } => sdf.distance(&to_local.point(p)),
+            Sdf::Scale { sdf, factor } => sdf.distance(&(*p / *factor)) * factor,
+            Sdf::Repeat { sdf, period } => {
+                let wrap = |x: f64, period: f64| if period > 0.0 { x - period * (x / period).round() } else { x };
+                sdf.distance(&Vec3::new(wrap(p.x(), period.x()), wrap(p.y(), period.y()), wrap(p.z(), period.z())))
+            },
+            Sdf::Twist { sdf, rate } => {
+                let (s, c): (f64, f64) = (rate * p.y()).sin_cos();
+                sdf.distance(&Vec3::new(c * p.x() - s * p.z(), p.y(), s * p.x() + c * p.z()))
+            },
+            Sdf::Bend { sdf, rate } => {
+                let (s, c): (f64, f64) = (rate * p.x()).sin_cos();
+                sdf.distance(&Vec3::new(c * p.x() - s * p.y(), s * p.x() + c * p.y(), p.z()))
+            },
+        }
+    }
+
+    // Box around the shape, None for endless repetitions
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let pad = |b: Aabb, by: f64| Aabb::aabb(b.minimum - Vec3::one() * by, b.maximum + Vec3::one() * by);
+        // Anything a shape turns into when turned about the origin in the
+        // plane of axes i and j
+        let spun = |b: Aabb, i: usize, j: usize| {
+            let reach: f64 = [b.minimum, b.maximum].iter()
+                .flat_map(|u| [b.minimum, b.maximum].map(|v| (u.e[i] * u.e[i] + v.e[j] * v.e[j]).sqrt()))
+                .fold(0.0, f64::max);
+            let (mut minimum, mut maximum): (Vec3, Vec3) = (b.minimum, b.maximum);
+            (minimum.e[i], minimum.e[j], maximum.e[i], maximum.e[j]) = (-reach, -reach, reach, reach);
+            Aabb::aabb(minimum, maximum)
+        };
+
+        Some(match self {
+            Sdf::Sphere { radius } => pad(Aabb::aabb(Vec3::zero(), Vec3::zero()), *radius),
+            Sdf::Box { half_size } => Aabb::aabb(-*half_size, *half_size),
+            Sdf::Torus { major_radius, minor_radius } => {
+                let reach: f64 = major_radius + minor_radius;
+                Aabb::aabb(Vec3::new(-reach, -minor_radius, -reach), Vec3::new(reach, *minor_radius, reach))
+            },
+            Sdf::Cylinder { radius, half_height } =>
+                Aabb::aabb(Vec3::new(-radius, -half_height, -radius), Vec3::new(*radius, *half_height, *radius)),
+            Sdf::Capsule { a, b, radius } => pad(Aabb::aabb(*a, *b), *radius),
+            // Smooth blends swell by up to k / 4
+            Sdf::Union { a, b, k } => pad(Aabb::surrounding_box(&a.bounding_box()?, &b.bounding_box()?), k * 0.25),
+            Sdf::Intersection { a, b, .. } => match (a.bounding_box(), b.bounding_box()) {
+                (Some(a), Some(b)) => Aabb::overlap(&a, &b),
+                (a, b) => a.or(b)?,
+            },
+            Sdf::Difference { a, .. } => a.bounding_box()?,
+            Sdf::Round { sdf, radius } => pad(sdf.bounding_box()?, *radius),
+            Sdf::Translate { sdf, offset } => {
+                let b: Aabb = sdf.bounding_box()?;
+                Aabb::aabb(b.minimum + *offset, b.maximum + *offset)
+            },
+            Sdf::Rotate { sdf, to_local } => to_local.inverse().bounding_box(&sdf.bounding_box()?),
+            Sdf::Scale { sdf, factor } => {
+                let b: Aabb = sdf.bounding_box()?;
+                Aabb::aabb(b.minimum * *factor, b.maximum * *factor)
+            },
+            Sdf::Repeat { .. } => return None,
+            Sdf::Twist { sdf, .. } => spun(sdf.bounding_box()?, 0, 2),
+            Sdf::Bend { sdf, .. } => spun(sdf.bounding_box()?, 0, 1),
+        })
+    }
+
+    // How much faster than the true distance the function can change
+    // within `radius` of the origin. Twists and bends stretch space more
+    // the further they reach, so rays must take shorter steps through them.
+    pub fn lipschitz(&self, radius: f64) -> f64 {
+        match self {
+            Sdf::Sphere { .. } | Sdf::Box { .. } | Sdf::Torus { .. } | Sdf::Cylinder { .. } | Sdf::Capsule { .. } => 1.0,
+            Sdf::Union { a, b, .. } | Sdf::Intersection { a, b, .. } | Sdf::Difference { a, b, .. } =>
+                a.lipschitz(radius).max(b.lipschitz(radius)),
+            Sdf::Round { sdf, .. } | Sdf::Rotate { sdf, .. } => sdf.lipschitz(radius),
+            Sdf::Translate { sdf, offset } => sdf.lipschitz(radius + offset.length()),
+            Sdf::Scale { sdf, factor } => sdf.lipschitz(radius / factor),
+            // Points fold into a cell only along the repeated axes, and
+            // keep their full reach along the others
+            Sdf::Repeat { sdf, period } => {
+                let half: [f64; 3] = period.e.map(|p| if p > 0.0 { (0.5 * p).min(radius) } else { radius });
+                sdf.lipschitz(radius.min(Vec3::new(half[0], half[1], half[2]).length()))
+            },
+            Sdf::Twist { sdf, rate } | Sdf::Bend { sdf, rate } => sdf.lipschitz(radius) * (1.0 + rate.abs() * radius),
+        }
+    }
+}
+
+// Unit normal from central differences of a distance function
+pub fn gradient_normal(distance: &dyn Fn(&Vec3) -> f64, p: &Vec3) -> Vec3 {
+    let axes: [Vec3; 3] = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+    let [x, y, z]: [f64; 3] = axes.map(|axis| {
+        let h: Vec3 = axis * NORMAL_OFFSET;
+        distance(&(*p + h)) - distance(&(*p - h))
+    });
+    Utils::unit_vector(&Vec3::new(x, y, z))
+}
+
+// Sphere traces the ray between t_start and t_end: it advances by the
+// distance to the surface times `step_scale`, which it cannot overshoot as
+// long as that stays below the true distance. Returns where the surface is
+// reached.
+//
+// Rays that start inside, e.g. through glass, look for the surface from the
+// other side. A ray leaving the surface it starts on has to get clear of it
+// before it can hit anything.
+pub fn sphere_trace(distance: &dyn Fn(&Vec3) -> f64, r: &Ray, t_start: f64, t_end: f64, step_scale: f64) -> Option<f64> {
+    let length: f64 = r.direction().length();
+    let start: Vec3 = r.point_at_parameter(t_start);
+    let d: f64 = distance(&start);
+    let side: f64 = if d.abs() > HIT_DISTANCE {
+        d.signum()
+    } else if gradient_normal(distance, &start).dot(&r.direction()) > 0.0 {
+        1.0
+    } else {
+        -1.0
+    };
+    let mut clear: bool = side * d > HIT_DISTANCE;
+
+    let mut t: f64 = t_start;
+    let mut steps: u32 = 0;
+    loop {
+        let d: f64 = side * distance(&r.point_at_parameter(t));
+        if d < HIT_DISTANCE && clear {
+            return Some(t);
+        }
+        clear = clear || d > HIT_DISTANCE;
+
+        t += d.max(HIT_DISTANCE) * step_scale / length;
+        steps += 1;
+        if t > t_end || steps == MAX_STEPS {
+            return None;
+        }
+    }
+}
+
+// Surface where a distance function is zero, found by sphere tracing: the
+// ray advances by the distance to the shape, which it cannot overshoot
+pub struct SdfHittable {
+    sdf: Sdf,
+    // Only this part of space is marched through
+    bounds: Aabb,
+    // Fraction of the distance taken per step
+    step_scale: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl SdfHittable {
+    pub fn sdf_hittable(sdf: Sdf, bounds: Aabb, m: Box<Material>) -> Self {
+        let margin: Vec3 = Vec3::one() * BOUNDS_MARGIN;
+        let bounds: Aabb = Aabb::aabb(bounds.minimum - margin, bounds.maximum + margin);
+        let radius: f64 = abs(&bounds.minimum).length().max(abs(&bounds.maximum).length());
+        let step_scale: f64 = 1.0 / sdf.lipschitz(radius);
+        SdfHittable { sdf, bounds, step_scale, mat_ptr: m }
+    }
+}
+
+impl Hittable for SdfHittable {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t_start, t_end): (f64, f64) = match self.bounds.intersect(r, t_min, t_max) {
+            Some(span) => span,
+            None => return false,
+        };
+        let distance = |p: &Vec3| self.sdf.distance(p);
+        let t: f64 = match sphere_trace(&distance, r, t_start, t_end, self.step_scale) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.p = r.point_at_parameter(t);
+        let outward_normal: Vec3 = gradient_normal(&distance, &rec.p);
+        rec.set_face_normal(r, &outward_normal);
+        // Distance functions have no surface coordinates of their own
+        (rec.u, rec.v) = Sphere::get_sphere_uv(&outward_normal);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bounds;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(radius: f64, x: f64) -> Box<Sdf> {
+        Box::new(Sdf::Translate { sdf: Box::new(Sdf::Sphere { radius }), offset: Vec3::new(x, 0.0, 0.0) })
+    }
+
+    #[test]
+    fn test_sdf_distances() {
+        let cube: Sdf = Sdf::Box { half_size: Vec3::one() };
+        assert!((cube.distance(&Vec3::new(3.0, 0.5, 0.0)) - 2.0).abs() < 1e-12);
+        assert!((cube.distance(&Vec3::new(2.0, 2.0, 0.0)) - 2f64.sqrt()).abs() < 1e-12);
+        assert!((cube.distance(&Vec3::new(0.5, 0.0, 0.0)) + 0.5).abs() < 1e-12);
+
+        // Rounding grows the box, and a smooth union bulges between its
+        // two spheres but matches the plain union away from the seam
+        let rounded: Sdf = Sdf::Round { sdf: Box::new(cube.clone()), radius: 0.25 };
+        assert!((rounded.distance(&Vec3::new(3.0, 0.0, 0.0)) - 1.75).abs() < 1e-12);
+        let blob: Sdf = Sdf::Union { a: sphere(1.0, -1.2), b: sphere(1.0, 1.2), k: 0.5 };
+        let plain: Sdf = Sdf::Union { a: sphere(1.0, -1.2), b: sphere(1.0, 1.2), k: 0.0 };
+        let seam: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+        assert!(blob.distance(&seam) < plain.distance(&seam) - 0.1);
+        assert_eq!(blob.distance(&Vec3::new(-3.0, 0.0, 0.0)), plain.distance(&Vec3::new(-3.0, 0.0, 0.0)));
+        let bitten: Sdf = Sdf::Difference { a: sphere(1.0, 0.0), b: sphere(1.0, 1.0), k: 0.0 };
+        assert!((bitten.distance(&Vec3::new(0.25, 0.0, 0.0)) - 0.25).abs() < 1e-12);
+
+        // Repeated copies sit at every multiple of the period
+        let grid: Sdf = Sdf::Repeat { sdf: Box::new(Sdf::Sphere { radius: 0.5 }), period: Vec3::new(2.0, 0.0, 2.0) };
+        assert!((grid.distance(&Vec3::new(10.0, 0.0, -4.0)) + 0.5).abs() < 1e-12);
+        assert!((grid.distance(&Vec3::new(10.0, 3.0, -4.0)) - 2.5).abs() < 1e-12);
+        assert_eq!(grid.bounding_box(), None);
+
+        // A quarter twist over a height of one turns the top of a bar
+        // sideways, and costs shorter steps
+        let bar: Sdf = Sdf::Box { half_size: Vec3::new(1.0, 1.0, 0.2) };
+        let twisted: Sdf = Sdf::Twist { sdf: Box::new(bar.clone()), rate: Utils::pi() / 2.0 };
+        assert!(twisted.distance(&Vec3::new(0.0, 0.9, 0.9)) < 0.0 && bar.distance(&Vec3::new(0.0, 0.9, 0.9)) > 0.0);
+        assert_eq!(bar.lipschitz(2.0), 1.0);
+        assert!((twisted.lipschitz(2.0) - (1.0 + Utils::pi())).abs() < 1e-12);
+        // Repeating along x alone leaves the twist its full height in y
+        let row: Sdf = Sdf::Repeat { sdf: Box::new(twisted.clone()), period: Vec3::new(3.0, 0.0, 0.0) };
+        assert_eq!(row.lipschitz(2.0), twisted.lipschitz(2.0));
+        let reach: f64 = (1.0f64 + 0.2 * 0.2).sqrt();
+        assert_eq!(twisted.bounding_box(), Some(Aabb::aabb(Vec3::new(-reach, -1.0, -reach), Vec3::new(reach, 1.0, reach))));
+    }
+
+    #[test]
+    fn test_sdf_hittable_hit() {
+        let ball: SdfHittable = SdfHittable::sdf_hittable(
+            Sdf::Sphere { radius: 1.0 }, Aabb::aabb(-Vec3::one() * 1.5, Vec3::one() * 1.5), Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        let r: Ray = Ray::ray(Vec3::new(0.0, 0.6, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!(ball.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 2.1).abs() < 1e-4 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.6, 0.8)).length() < 1e-4);
+
+        // From just inside the surface it finds the far side, and it stops
+        // at t_max
+        assert!(ball.hit(&r, rec.t + 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 2.9).abs() < 1e-4 && !rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, -0.6, 0.8)).length() < 1e-4);
+        assert!(!ball.hit(&r, 0.001, 2.0, &mut rec));
+        let miss: Ray = Ray::ray(Vec3::new(0.0, 1.1, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!ball.hit(&miss, 0.001, Utils::infinity(), &mut rec));
+
+        // The same for a twisted bar, whose flat sides turn away with height
+        let twisted: Sdf = Sdf::Twist { sdf: Box::new(Sdf::Box { half_size: Vec3::new(1.0, 1.0, 0.2) }), rate: 1.0 };
+        let bounds: Aabb = twisted.bounding_box().unwrap();
+        let bar: SdfHittable = SdfHittable::sdf_hittable(twisted.clone(), bounds, Box::default());
+        for y in [-0.8, -0.3, 0.0, 0.4, 0.9] {
+            let r: Ray = Ray::ray(Vec3::new(0.1, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            assert!(bar.hit(&r, 0.001, Utils::infinity(), &mut rec));
+            assert!(twisted.distance(&rec.p).abs() < 2.0 * HIT_DISTANCE);
+            // Nothing was skipped on the way in
+            for i in 0..100 {
+                let t: f64 = rec.t * i as f64 / 100.0;
+                assert!(twisted.distance(&r.point_at_parameter(t)) > 0.0);
+            }
+        }
+    }
+}
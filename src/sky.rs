@@ -0,0 +1,276 @@
+// Analytic daylight from Preetham, Shirley and Smits 1999, "A Practical
+// Analytic Model for Daylight", with the sun as a disk of its true size
+// and a diffuse ground below the horizon.
+//
+// Radiance is in units of 10 kcd/m^2, which puts a clear sky near one.
+
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+use crate::onb::Onb;
+use crate::distribution::Distribution2D;
+use crate::background::{direction_to_equirect, equirect_to_direction};
+use crate::tone_map::luminance;
+
+// The sun's disk is about 0.53 degrees across
+const SUN_ANGULAR_RADIUS: f64 = 0.2665 * std::f64::consts::PI / 180.0;
+
+// Luminance of the sun outside the atmosphere, in the units above
+const SUN_LUMINANCE: f64 = 2.0e5;
+
+// Resolution of the table the sky is importance sampled from
+const SKY_TABLE_WIDTH: usize = 64;
+const SKY_TABLE_HEIGHT: usize = 32;
+
+// Coefficients A to E of the Perez luminance distribution, each linear in
+// the turbidity, for Y, x and y
+const PEREZ: [[[f64; 2]; 5]; 3] = [
+    [[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]],
+    [[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]],
+    [[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]],
+];
+
+// Zenith chromaticity as polynomials in the sun's zenith angle, with one
+// row per power of the turbidity
+const ZENITH_X: [[f64; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+const ZENITH_Y: [[f64; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicalSky {
+    sun: Vec3,
+    sun_radiance: Vec3,
+    cos_sun_radius: f64,
+    // Perez coefficients and zenith values for Y, x and y, the latter
+    // already divided by the Perez function at the zenith
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    ground: Vec3,
+    // Chance that random() aims at the sun rather than the rest of the sky
+    sun_probability: f64,
+    distribution: Distribution2D,
+}
+
+impl PhysicalSky {
+    // `turbidity` runs from about 2 for a clear sky to 10 for haze, and
+    // the ground reflects the sky and the sun with `ground_albedo`
+    pub fn physical_sky(sun_direction: Vec3, turbidity: f64, ground_albedo: Vec3) -> PhysicalSky {
+        let sun: Vec3 = Utils::unit_vector(&sun_direction);
+        let theta_s: f64 = sun.y().clamp(0.0, 1.0).acos();
+        let t: f64 = turbidity;
+
+        let perez: [[f64; 5]; 3] = PEREZ.map(|channel| channel.map(|[a, b]| a * t + b));
+        let chi: f64 = (4.0 / 9.0 - t / 120.0) * (Utils::pi() - 2.0 * theta_s);
+        let zenith_luminance: f64 = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) / 10.0;
+        let polynomial = |rows: &[[f64; 4]; 3]| -> f64 {
+            let powers: [f64; 3] = [t * t, t, 1.0];
+            rows.iter().zip(powers).map(|(row, power)| {
+                power * row.iter().fold(0.0, |sum, c| sum * theta_s + c)
+            }).sum()
+        };
+        let zenith_values: [f64; 3] = [zenith_luminance, polynomial(&ZENITH_X), polynomial(&ZENITH_Y)];
+        let mut zenith: [f64; 3] = [0.0; 3];
+        for channel in 0..3 {
+            zenith[channel] = zenith_values[channel] / PhysicalSky::perez_function(&perez[channel], 0.0, theta_s);
+        }
+
+        let cos_sun_radius: f64 = SUN_ANGULAR_RADIUS.cos();
+        let mut sky: PhysicalSky = PhysicalSky {
+            sun,
+            sun_radiance: PhysicalSky::sun_transmittance(theta_s, t) * SUN_LUMINANCE,
+            cos_sun_radius,
+            perez,
+            zenith,
+            ground: Vec3::zero(),
+            sun_probability: 0.0,
+            distribution: Distribution2D::distribution_2d(&[1.0], 1, 1),
+        };
+
+        // Light falling on the ground, from the sky around the center of
+        // every table cell above the horizon and from the sun
+        let cell: f64 = 2.0 * Utils::pi() * Utils::pi() / (SKY_TABLE_WIDTH * SKY_TABLE_HEIGHT) as f64;
+        let mut irradiance: Vec3 = sky.sun_radiance * (sky.sun_solid_angle() * sun.y().max(0.0));
+        let mut sky_power: f64 = 0.0;
+        let mut func: Vec<f64> = Vec::with_capacity(SKY_TABLE_WIDTH * SKY_TABLE_HEIGHT);
+        for y in 0..SKY_TABLE_HEIGHT {
+            for x in 0..SKY_TABLE_WIDTH {
+                let (u, v): (f64, f64) = ((x as f64 + 0.5) / SKY_TABLE_WIDTH as f64, (y as f64 + 0.5) / SKY_TABLE_HEIGHT as f64);
+                let (direction, sin_theta): (Vec3, f64) = equirect_to_direction(u, v);
+                if direction.y() > 0.0 {
+                    let radiance: Vec3 = sky.sky_radiance(&direction);
+                    irradiance += radiance * (direction.y() * sin_theta * cell);
+                    func.push(luminance(&radiance) * sin_theta);
+                } else {
+                    // Filled in below once the ground is known
+                    func.push(sin_theta);
+                }
+            }
+        }
+        sky.ground = ground_albedo * irradiance / Utils::pi();
+        for (i, f) in func.iter_mut().enumerate() {
+            if i / SKY_TABLE_WIDTH >= SKY_TABLE_HEIGHT / 2 {
+                *f *= luminance(&sky.ground);
+            }
+            sky_power += *f * cell;
+        }
+
+        let sun_power: f64 = luminance(&sky.sun_radiance) * sky.sun_solid_angle();
+        sky.sun_probability = if sun_power + sky_power > 0.0 { sun_power / (sun_power + sky_power) } else { 0.0 };
+        sky.distribution = Distribution2D::distribution_2d(&func, SKY_TABLE_WIDTH, SKY_TABLE_HEIGHT);
+        sky
+    }
+
+    // Perez et al. 1993 relative to the zenith, for a view at zenith angle
+    // theta that is gamma away from the sun
+    fn perez_function(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e]: [f64; 5] = *coefficients;
+        let cos_theta: f64 = theta.cos().max(0.01);
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+
+    // Fraction of sunlight at red, green and blue wavelengths that makes it
+    // through Rayleigh and aerosol scattering, with the relative air mass of
+    // Kasten and Young
+    fn sun_transmittance(theta_s: f64, turbidity: f64) -> Vec3 {
+        let theta_degrees: f64 = theta_s.to_degrees();
+        let air_mass: f64 = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+        let beta: f64 = 0.04608 * turbidity - 0.04586;
+        let channel = |micrometers: f64| -> f64 {
+            let rayleigh: f64 = 0.008735 * micrometers.powf(-4.08);
+            let aerosol: f64 = beta * micrometers.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        };
+        Vec3::new(channel(0.680), channel(0.550), channel(0.440))
+    }
+
+    fn sun_solid_angle(&self) -> f64 {
+        2.0 * Utils::pi() * (1.0 - self.cos_sun_radius)
+    }
+
+    // Sky without the sun, above the horizon
+    fn sky_radiance(&self, direction: &Vec3) -> Vec3 {
+        let theta: f64 = direction.y().clamp(-1.0, 1.0).acos();
+        let gamma: f64 = direction.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let [big_y, x, y]: [f64; 3] = [0, 1, 2].map(|channel| {
+            self.zenith[channel] * PhysicalSky::perez_function(&self.perez[channel], theta, gamma)
+        });
+        if y <= 0.0 {
+            return Vec3::zero();
+        }
+
+        // xyY to XYZ to linear sRGB
+        let big_x: f64 = x / y * big_y;
+        let big_z: f64 = (1.0 - x - y) / y * big_y;
+        Vec3::new(
+            (3.2404542 * big_x - 1.5371385 * big_y - 0.4985314 * big_z).max(0.0),
+            (-0.9692660 * big_x + 1.8760108 * big_y + 0.0415560 * big_z).max(0.0),
+            (0.0556434 * big_x - 0.2040259 * big_y + 1.0572252 * big_z).max(0.0))
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Vec3 {
+        let d: Vec3 = Utils::unit_vector(direction);
+        if d.y() <= 0.0 {
+            return self.ground;
+        }
+        let sun: Vec3 = if d.dot(&self.sun) >= self.cos_sun_radius { self.sun_radiance } else { Vec3::zero() };
+        self.sky_radiance(&d) + sun
+    }
+
+    // Mixture of a uniform cone around the sun and the tabulated sky
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let d: Vec3 = Utils::unit_vector(direction);
+        let sun: f64 = if d.dot(&self.sun) >= self.cos_sun_radius { 1.0 / self.sun_solid_angle() } else { 0.0 };
+
+        let (u, v): (f64, f64) = direction_to_equirect(&d);
+        let sin_theta: f64 = (v * Utils::pi()).sin();
+        let sky: f64 = if sin_theta > 0.0 {
+            self.distribution.pdf(u, v) / (2.0 * Utils::pi() * Utils::pi() * sin_theta)
+        } else {
+            0.0
+        };
+        self.sun_probability * sun + (1.0 - self.sun_probability) * sky
+    }
+
+    pub fn random(&self) -> Vec3 {
+        if Utils::random_double() < self.sun_probability {
+            let cos_theta: f64 = 1.0 - Utils::random_double() * (1.0 - self.cos_sun_radius);
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi: f64 = 2.0 * Utils::pi() * Utils::random_double();
+            return Onb::onb(&self.sun).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        }
+        let (u, v, _): (f64, f64, f64) = self.distribution.sample(Utils::random_double(), Utils::random_double());
+        equirect_to_direction(u, v).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sun_at(elevation: f64) -> Vec3 {
+        let e: f64 = elevation.to_radians();
+        Vec3::new(0.3 * e.cos(), e.sin(), -0.95 * e.cos())
+    }
+
+    #[test]
+    fn test_physical_sky_radiance() {
+        let noon: PhysicalSky = PhysicalSky::physical_sky(sun_at(60.0), 3.0, Vec3::new(0.2, 0.3, 0.4));
+        let zenith: Vec3 = noon.value(&Vec3::new(0.0, 1.0, 0.0));
+        let horizon: Vec3 = noon.value(&Vec3::new(0.0, 0.05, 1.0));
+        // A clear sky is blue overhead and paler towards the horizon, and
+        // brightest around the sun
+        assert!(zenith.b() > zenith.r() && zenith.b() / zenith.r() > horizon.b() / horizon.r());
+        assert!(zenith.g() > 0.2 && zenith.g() < 5.0, "{:?}", zenith);
+        let near_sun: Vec3 = noon.value(&Utils::unit_vector(&(sun_at(60.0) + Vec3::new(0.1, 0.0, 0.0))));
+        let away: Vec3 = noon.value(&Utils::unit_vector(&(sun_at(60.0) * -1.0 + Vec3::new(0.0, 1.5, 0.0))));
+        assert!(luminance(&near_sun) > 2.0 * luminance(&away));
+
+        // The ground is diffuse under the sky, tinted by its albedo
+        let ground: Vec3 = noon.value(&Vec3::new(0.3, -1.0, 0.2));
+        assert_eq!(ground, noon.value(&Vec3::new(0.0, -0.1, -1.0)));
+        assert!((ground.g() / ground.r() - 1.5).abs() < 0.3 && ground.b() > ground.g());
+
+        // The sun is far brighter than the sky, and redder when it is low
+        let sun: Vec3 = noon.value(&sun_at(60.0));
+        assert!(luminance(&sun) > 1e4 * luminance(&zenith));
+        let sunset: PhysicalSky = PhysicalSky::physical_sky(sun_at(3.0), 3.0, Vec3::one() * 0.3);
+        let low: Vec3 = sunset.value(&sun_at(3.0));
+        assert!(low.r() / low.b() > 2.0 * sun.r() / sun.b());
+        assert!(luminance(&low) < luminance(&sun));
+    }
+
+    #[test]
+    fn test_physical_sky_sampling() {
+        let sky: PhysicalSky = PhysicalSky::physical_sky(sun_at(35.0), 4.0, Vec3::one() * 0.3);
+        assert!(sky.sun_probability > 0.3 && sky.sun_probability < 0.95, "{}", sky.sun_probability);
+        Utils::seed_random(9);
+
+        // The sun disk is picked with its probability, and every sample
+        // has the density pdf_value() reports
+        let n: usize = 100_000;
+        let mut in_sun: usize = 0;
+        for _ in 0..n {
+            let d: Vec3 = sky.random();
+            assert!(sky.pdf_value(&d) > 0.0);
+            if d.dot(&sky.sun) >= sky.cos_sun_radius {
+                in_sun += 1;
+            }
+        }
+        assert!((in_sun as f64 / n as f64 - sky.sun_probability).abs() < 0.01);
+
+        // The sun's share integrates to its probability over its tiny cone,
+        // the rest to the remaining probability over the whole sphere
+        let integral: f64 = (0..n)
+            .map(|_| Utils::random_unit_vector())
+            .filter(|d| d.dot(&sky.sun) < sky.cos_sun_radius)
+            .map(|d| sky.pdf_value(&d))
+            .sum::<f64>() * 4.0 * Utils::pi() / n as f64;
+        assert!((integral - (1.0 - sky.sun_probability)).abs() < 0.03 * (1.0 - sky.sun_probability), "{}", integral);
+    }
+}
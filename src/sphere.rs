@@ -3,7 +3,11 @@ use crate::hittable::*;
 use crate::ray::Ray;
 use crate::utils::Utils;
 use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Vec3,
     radius: f64,
@@ -14,41 +18,150 @@ impl Sphere {
     pub fn sphere(cen: Vec3, r: f64, m: Box<Material>) -> Self {
         Sphere{ center: cen, radius: r, mat_ptr: m }
     }
+
+    // Spherical mapping of a point on the unit sphere: u is the angle
+    // around y starting at -x, v runs from the bottom pole to the top
+    pub fn get_sphere_uv(p: &Vec3) -> (f64, f64) {
+        let theta: f64 = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi: f64 = (-p.z()).atan2(p.x()) + Utils::pi();
+
+        (phi / (2.0 * Utils::pi()), theta / Utils::pi())
+    }
+}
+
+// Intersection with a sphere of the given center, shared with MovingSphere.
+// Fills in everything but the material.
+pub fn sphere_hit(center: &Vec3, radius: f64, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    let oc: Vec3 = r.origin() - *center;
+    let a: f64 = r.direction().length_squared();
+    let half_b: f64 = Utils::dot(&oc, &r.direction());
+    let c: f64 = oc.length_squared() - radius * radius;
+
+    let discriminant: f64 = half_b * half_b - a * c;
+
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let sqrtd: f64 = discriminant.sqrt();
+
+    // find the nearest root that lies in the acceptable range
+    let mut root: f64 = (-half_b - sqrtd) / a;
+
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
+
+        if root < t_min || t_max < root {
+            return false;
+        }
+    }
+
+    rec.t = root;
+    rec.p = r.point_at_parameter(rec.t);
+    let outward_normal: Vec3 = (rec.p - *center) / radius;
+    rec.set_face_normal(r, &outward_normal);
+    (rec.u, rec.v) = Sphere::get_sphere_uv(&outward_normal);
+
+    true
+}
+
+pub fn sphere_bounding_box(center: &Vec3, radius: f64) -> Aabb {
+    let r: Vec3 = Vec3::new(radius, radius, radius);
+    Aabb::aabb(*center - r, *center + r)
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc: Vec3 = r.origin() - self.center;
-        let a: f64 = r.direction().length_squared();
-        let half_b: f64 = Utils::dot(&oc, &r.direction());
-        let c: f64 = oc.length_squared() - self.radius * self.radius;
+        if !sphere_hit(&self.center, self.radius, r, t_min, t_max, rec) {
+            return false;
+        }
 
-        let discriminant: f64 = half_b * half_b - a * c;
+        rec.mat_ptr = self.mat_ptr.clone();
 
-        if discriminant < 0.0 {
-            return false;
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = sphere_bounding_box(&self.center, self.radius);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let mut rec: HitRecord = HitRecord::default();
+        if !self.hit(&Ray::ray(*origin, *direction), 0.001, Utils::infinity(), &mut rec) {
+            return 0.0;
         }
 
-        let sqrtd: f64 = discriminant.sqrt();
+        let radius_squared: f64 = self.radius * self.radius;
+        let distance_squared: f64 = (self.center - *origin).length_squared();
 
-        // find the nearest root that lies in the acceptable range
-        let mut root: f64 = (-half_b - sqrtd) / a;
+        if distance_squared <= radius_squared {
+            // From inside, points are picked uniformly over the whole surface
+            let cosine: f64 = direction.dot(&rec.normal).abs() / direction.length();
+            let area: f64 = 4.0 * Utils::pi() * radius_squared;
+            return rec.t * rec.t * direction.length_squared() / (cosine * area);
+        }
 
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
+        // Uniform over the cone of directions that see the sphere. 1 - cos
+        // is rewritten to stay accurate for small or distant spheres.
+        let sin2_theta_max: f64 = radius_squared / distance_squared;
+        let cos_theta_max: f64 = (1.0 - sin2_theta_max).sqrt();
+        let solid_angle: f64 = 2.0 * Utils::pi() * sin2_theta_max / (1.0 + cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let direction: Vec3 = self.center - *origin;
+        let radius_squared: f64 = self.radius * self.radius;
+        let distance_squared: f64 = direction.length_squared();
 
-            if root < t_min || t_max < root {
-                return false;
-            }
+        if distance_squared <= radius_squared {
+            return self.center + Utils::random_unit_vector() * self.radius.abs() - *origin;
         }
 
-        rec.t = root;
-        rec.p = r.point_at_parameter(rec.t);
-        let outward_normal: Vec3 = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(r, &outward_normal);
+        let sin2_theta_max: f64 = radius_squared / distance_squared;
+        let cos_theta_max: f64 = (1.0 - sin2_theta_max).sqrt();
+        let one_minus_z: f64 = Utils::random_double() * sin2_theta_max / (1.0 + cos_theta_max);
+        let z: f64 = 1.0 - one_minus_z;
+        let sin_theta: f64 = (one_minus_z * (1.0 + z)).sqrt();
+        let phi: f64 = 2.0 * Utils::pi() * Utils::random_double();
 
-        rec.mat_ptr = self.mat_ptr.clone();
+        Onb::onb(&direction).local(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_uv() {
+        let sphere: Sphere = Sphere::sphere(Vec3::new(0.0, 0.0, -2.0), 0.5, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        // The point facing +z sits a quarter of the way around
+        let r: Ray = Ray::ray(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        let from_above: Ray = Ray::ray(Vec3::new(0.0, 3.0, -2.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(sphere.hit(&from_above, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.v - 1.0).abs() < 1e-12);
 
-        return true;
+        let from_left: Ray = Ray::ray(Vec3::new(-3.0, 0.0, -2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(sphere.hit(&from_left, 0.001, Utils::infinity(), &mut rec));
+        assert!(rec.u.abs() < 1e-12 || (rec.u - 1.0).abs() < 1e-12);
     }
 }
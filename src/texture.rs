@@ -0,0 +1,319 @@
+use crate::vec3::Vec3;
+use crate::perlin::Perlin;
+use crate::image_reader::Image;
+use crate::medium::ColorMap;
+use std::sync::Arc;
+
+// Color looked up at surface coordinates (u, v) and hit point p
+pub trait TextureValue {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3;
+}
+
+// -------- Solid color texture ------------
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SolidColorTexture {
+    color: Vec3,
+}
+
+impl SolidColorTexture {
+    pub fn solid_color(color: Vec3) -> SolidColorTexture {
+        SolidColorTexture { color }
+    }
+}
+
+impl TextureValue for SolidColorTexture {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+// -----------------------------------------
+
+// -------- Checker texture ----------------
+// Alternates between two textures on a 3D grid of cubes with edge `scale`
+#[derive(Clone, PartialEq, Debug)]
+pub struct CheckerTexture {
+    scale: f64,
+    even: Box<Texture>,
+    odd: Box<Texture>,
+}
+
+impl CheckerTexture {
+    pub fn checker(scale: f64, even: Texture, odd: Texture) -> CheckerTexture {
+        CheckerTexture { scale, even: Box::new(even), odd: Box::new(odd) }
+    }
+}
+
+impl TextureValue for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        let cell: i64 = (p.x() / self.scale).floor() as i64
+            + (p.y() / self.scale).floor() as i64
+            + (p.z() / self.scale).floor() as i64;
+
+        if cell.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+// -----------------------------------------
+
+// -------- Image texture ------------------
+// How texel coordinates outside the image are mapped back into it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<WrapMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, i: i64, size: u32) -> u32 {
+        let size: i64 = size as i64;
+        let wrapped: i64 = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let period: i64 = i.rem_euclid(2 * size);
+                if period < size { period } else { 2 * size - 1 - period }
+            },
+        };
+        wrapped as u32
+    }
+}
+
+// Bilinearly filtered image with (0, 0) at the bottom left corner. The
+// pixels are shared so that cloning materials stays cheap.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn image(image: Arc<Image>, wrap: WrapMode) -> ImageTexture {
+        ImageTexture { image, wrap }
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Vec3 {
+        self.image.pixel(self.wrap.apply(i, self.image.width()), self.wrap.apply(j, self.image.height()))
+    }
+}
+
+impl TextureValue for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3 {
+        // Texel centers sit at half-integer coordinates; image rows run
+        // from the top
+        let x: f64 = u * self.image.width() as f64 - 0.5;
+        let y: f64 = (1.0 - v) * self.image.height() as f64 - 0.5;
+        let (i, j): (f64, f64) = (x.floor(), y.floor());
+        let (fx, fy): (f64, f64) = (x - i, y - j);
+        let (i, j): (i64, i64) = (i as i64, j as i64);
+
+        let top: Vec3 = self.texel(i, j) * (1.0 - fx) + self.texel(i + 1, j) * fx;
+        let bottom: Vec3 = self.texel(i, j + 1) * (1.0 - fx) + self.texel(i + 1, j + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+// -----------------------------------------
+
+// -------- Noise texture ------------------
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NoiseKind {
+    // Smooth gray noise
+    Perlin,
+    // Several octaves of noise, like a rough stone
+    Turbulence,
+    // Bands along z disturbed by turbulence
+    Marble,
+}
+
+impl NoiseKind {
+    pub fn from_name(name: &str) -> Option<NoiseKind> {
+        match name.to_ascii_lowercase().as_str() {
+            "perlin" => Some(NoiseKind::Perlin),
+            "turbulence" => Some(NoiseKind::Turbulence),
+            "marble" => Some(NoiseKind::Marble),
+            _ => None,
+        }
+    }
+}
+
+const TURBULENCE_DEPTH: u32 = 7;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct NoiseTexture {
+    noise: Arc<Perlin>,
+    kind: NoiseKind,
+    // Frequency of the pattern in world space
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn noise(kind: NoiseKind, scale: f64) -> NoiseTexture {
+        NoiseTexture { noise: Arc::new(Perlin::perlin()), kind, scale }
+    }
+}
+
+impl TextureValue for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Vec3) -> Vec3 {
+        let scaled: Vec3 = *p * self.scale;
+        let gray: f64 = match self.kind {
+            NoiseKind::Perlin => 0.5 * (1.0 + self.noise.noise(&scaled)),
+            NoiseKind::Turbulence => self.noise.turbulence(&scaled, TURBULENCE_DEPTH),
+            NoiseKind::Marble =>
+                0.5 * (1.0 + (scaled.z() + 10.0 * self.noise.turbulence(p, TURBULENCE_DEPTH)).sin()),
+        };
+        Vec3::one() * gray
+    }
+}
+// -----------------------------------------
+
+// -------- Ramp texture -------------------
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TextureCoordinate {
+    U,
+    V,
+}
+
+impl TextureCoordinate {
+    pub fn from_name(name: &str) -> Option<TextureCoordinate> {
+        match name.to_ascii_lowercase().as_str() {
+            "u" => Some(TextureCoordinate::U),
+            "v" => Some(TextureCoordinate::V),
+            _ => None,
+        }
+    }
+}
+
+// Colors one surface coordinate through a color map, e.g. the orbit traps
+// a fractal stores in u and v
+#[derive(Clone, PartialEq, Debug)]
+pub struct RampTexture {
+    map: ColorMap,
+    coordinate: TextureCoordinate,
+}
+
+impl RampTexture {
+    pub fn ramp(map: ColorMap, coordinate: TextureCoordinate) -> RampTexture {
+        RampTexture { map, coordinate }
+    }
+}
+
+impl TextureValue for RampTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3 {
+        match self.coordinate {
+            TextureCoordinate::U => self.map.value(u),
+            TextureCoordinate::V => self.map.value(v),
+        }
+    }
+}
+// -----------------------------------------
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Texture {
+    SolidColor { solid_color: SolidColorTexture },
+    Checker { checker: CheckerTexture },
+    Image { image: ImageTexture },
+    Noise { noise: NoiseTexture },
+    Ramp { ramp: RampTexture },
+}
+
+impl TextureValue for Texture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        match self {
+            Texture::SolidColor { solid_color } => solid_color.value(u, v, p),
+            Texture::Checker { checker } => checker.value(u, v, p),
+            Texture::Image { image } => image.value(u, v, p),
+            Texture::Noise { noise } => noise.value(u, v, p),
+            Texture::Ramp { ramp } => ramp.value(u, v, p),
+        }
+    }
+}
+
+// Plain colors are by far the most common texture
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Texture {
+        Texture::SolidColor { solid_color: SolidColorTexture::solid_color(color) }
+    }
+}
+
+// Scalar parameters are textures too, gray with the value in every channel
+impl From<f64> for Texture {
+    fn from(value: f64) -> Texture {
+        (Vec3::one() * value).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checker_texture() {
+        let checker: Texture = Texture::Checker { checker: CheckerTexture::checker(
+            0.5, Vec3::one().into(), Vec3::zero().into()) };
+
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.1, 0.1, 0.1)), Vec3::one());
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.6, 0.1, 0.1)), Vec3::zero());
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.6, -0.1, 0.1)), Vec3::one());
+        assert_eq!(checker.value(0.0, 0.0, &Vec3::new(-0.1, -0.1, -0.1)), Vec3::zero());
+    }
+
+    #[test]
+    fn test_image_texture_filtering_and_wrap() {
+        // 2x1 image, black on the left and white on the right
+        let image: Arc<Image> = Arc::new(Image::image(2, 1, vec![Vec3::zero(), Vec3::one()]));
+        let p: Vec3 = Vec3::zero();
+
+        let clamp: ImageTexture = ImageTexture::image(image.clone(), WrapMode::Clamp);
+        assert_eq!(clamp.value(0.25, 0.5, &p), Vec3::zero());
+        assert_eq!(clamp.value(0.75, 0.5, &p), Vec3::one());
+        assert_eq!(clamp.value(0.5, 0.5, &p), Vec3::one() * 0.5);
+        assert_eq!(clamp.value(0.0, 0.5, &p), Vec3::zero());
+        assert_eq!(clamp.value(1.5, 0.5, &p), Vec3::one());
+
+        // Repeating blends the left edge with the right column
+        let repeat: ImageTexture = ImageTexture::image(image.clone(), WrapMode::Repeat);
+        assert_eq!(repeat.value(0.0, 0.5, &p), Vec3::one() * 0.5);
+        assert_eq!(repeat.value(1.25, 0.5, &p), Vec3::zero());
+
+        let mirror: ImageTexture = ImageTexture::image(image, WrapMode::Mirror);
+        assert_eq!(mirror.value(0.0, 0.5, &p), Vec3::zero());
+        assert_eq!(mirror.value(1.25, 0.5, &p), Vec3::one());
+
+        assert_eq!(WrapMode::Mirror.apply(-1, 3), 0);
+        assert_eq!(WrapMode::Mirror.apply(4, 3), 1);
+    }
+
+    #[test]
+    fn test_noise_texture_range() {
+        for kind in [NoiseKind::Perlin, NoiseKind::Turbulence, NoiseKind::Marble] {
+            let texture: NoiseTexture = NoiseTexture::noise(kind, 4.0);
+            for i in 0..500 {
+                let p: Vec3 = Vec3::new(i as f64 * 0.031, i as f64 * -0.017, i as f64 * 0.023);
+                let gray: f64 = texture.value(0.0, 0.0, &p).x();
+                assert!((0.0..=2.0).contains(&gray), "{:?} gave {}", kind, gray);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ramp_texture() {
+        let map: ColorMap = ColorMap::ramp(vec![(0.0, Vec3::zero()), (1.0, Vec3::new(1.0, 0.5, 0.0))]).unwrap();
+        let p: Vec3 = Vec3::zero();
+        let along_u: RampTexture = RampTexture::ramp(map.clone(), TextureCoordinate::U);
+        assert_eq!(along_u.value(0.5, 1.0, &p), Vec3::new(0.5, 0.25, 0.0));
+        let along_v: RampTexture = RampTexture::ramp(map, TextureCoordinate::V);
+        assert_eq!(along_v.value(0.5, 1.0, &p), Vec3::new(1.0, 0.5, 0.0));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::vec3::Vec3;
+use crate::utils::Utils;
+
+// Maps linear scene radiance to display values in [0, 1]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapOperator {
+    // Hard clamp, what write_color used to do
+    Clamp,
+    // Reinhard et al. 2002 on luminance
+    Reinhard,
+    // Reinhard with a white point that maps to 1
+    ExtendedReinhard { white_point: f64 },
+    // Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+    // John Hable's Uncharted 2 curve
+    Hable,
+}
+
+impl ToneMapOperator {
+    pub fn from_name(name: &str) -> Option<ToneMapOperator> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Some(ToneMapOperator::Clamp),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            "reinhard-extended" => Some(ToneMapOperator::ExtendedReinhard { white_point: 4.0 }),
+            "aces" => Some(ToneMapOperator::AcesFilmic),
+            "hable" | "uncharted2" => Some(ToneMapOperator::Hable),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, c: &Vec3) -> Vec3 {
+        match self {
+            ToneMapOperator::Clamp => *c,
+            ToneMapOperator::Reinhard => {
+                ToneMapOperator::scale_luminance(c, |l| l / (1.0 + l))
+            },
+            ToneMapOperator::ExtendedReinhard { white_point } => {
+                let w2: f64 = white_point * white_point;
+                ToneMapOperator::scale_luminance(c, |l| l * (1.0 + l / w2) / (1.0 + l))
+            },
+            ToneMapOperator::AcesFilmic => {
+                ToneMapOperator::per_channel(c, |x| {
+                    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+                })
+            },
+            ToneMapOperator::Hable => {
+                // The curve is normalized so that the linear white point maps to 1
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE_POINT: f64 = 11.2;
+                let white_scale: f64 = 1.0 / ToneMapOperator::hable_partial(WHITE_POINT);
+                ToneMapOperator::per_channel(c, |x| {
+                    ToneMapOperator::hable_partial(x * EXPOSURE_BIAS) * white_scale
+                })
+            },
+        }
+    }
+
+    fn hable_partial(x: f64) -> f64 {
+        let a: f64 = 0.15;
+        let b: f64 = 0.50;
+        let c: f64 = 0.10;
+        let d: f64 = 0.20;
+        let e: f64 = 0.02;
+        let f: f64 = 0.30;
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+
+    fn per_channel(c: &Vec3, curve: impl Fn(f64) -> f64) -> Vec3 {
+        Vec3::new(curve(c.r().max(0.0)), curve(c.g().max(0.0)), curve(c.b().max(0.0)))
+    }
+
+    fn scale_luminance(c: &Vec3, curve: impl Fn(f64) -> f64) -> Vec3 {
+        let l: f64 = luminance(c);
+        if l <= 0.0 {
+            return Vec3::zero();
+        }
+        *c * (curve(l) / l)
+    }
+}
+
+// Rec. 709 / sRGB luminance
+pub fn luminance(c: &Vec3) -> f64 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+// sRGB opto-electronic transfer function for a value in [0, 1]
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of srgb_encode, for reading 8-bit images back as linear color
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Exposure, tone mapping and sRGB encoding for display formats
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorPipeline {
+    // In stops, +1 doubles the brightness
+    pub exposure: f64,
+    pub operator: ToneMapOperator,
+}
+
+impl Default for ColorPipeline {
+    fn default() -> Self {
+        ColorPipeline { exposure: 0.0, operator: ToneMapOperator::Clamp }
+    }
+}
+
+impl ColorPipeline {
+    // Linear radiance to display-referred sRGB values in [0, 1]
+    pub fn apply(&self, linear: &Vec3) -> Vec3 {
+        let exposed: Vec3 = *linear * 2f64.powf(self.exposure);
+        let mapped: Vec3 = self.operator.apply(&exposed);
+
+        Vec3::new(
+            srgb_encode(Utils::clamp(mapped.r(), 0.0, 1.0)),
+            srgb_encode(Utils::clamp(mapped.g(), 0.0, 1.0)),
+            srgb_encode(Utils::clamp(mapped.b(), 0.0, 1.0)))
+    }
+
+    pub fn encode_rgb8(&self, linear: &Vec3) -> [u8; 3] {
+        let c: Vec3 = self.apply(linear);
+        [
+            (255.0 * c.r()).round() as u8,
+            (255.0 * c.g()).round() as u8,
+            (255.0 * c.b()).round() as u8,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_srgb_encode() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!(close(srgb_encode(1.0), 1.0));
+        assert!(close(srgb_encode(0.0031308), 0.0404500));
+        assert!(close(srgb_encode(0.5), 0.7353570));
+
+        for i in 0..=20 {
+            let x: f64 = i as f64 / 20.0;
+            assert!(close(srgb_decode(srgb_encode(x)), x));
+        }
+    }
+
+    #[test]
+    fn test_reinhard() {
+        let grey: Vec3 = Vec3::one();
+        assert!(close(ToneMapOperator::Reinhard.apply(&grey).g(), 0.5));
+
+        let white: Vec3 = Vec3::one() * 4.0;
+        let extended: ToneMapOperator = ToneMapOperator::ExtendedReinhard { white_point: 4.0 };
+        assert!(close(extended.apply(&white).r(), 1.0));
+
+        // Hue is preserved because only luminance is compressed
+        let red: Vec3 = ToneMapOperator::Reinhard.apply(&Vec3::new(2.0, 1.0, 0.0));
+        assert!(close(red.r(), 2.0 * red.g()));
+    }
+
+    #[test]
+    fn test_filmic_curves() {
+        for op in [ToneMapOperator::AcesFilmic, ToneMapOperator::Hable] {
+            assert!(op.apply(&Vec3::zero()).r().abs() < 1e-12);
+            let mut previous: f64 = 0.0;
+            for i in 1..100 {
+                let v: f64 = op.apply(&(Vec3::one() * (i as f64 * 0.1))).r();
+                assert!(v > previous);
+                previous = v;
+            }
+        }
+
+        assert!(close(ToneMapOperator::Hable.apply(&(Vec3::one() * 5.6)).r(), 1.0));
+        assert!(ToneMapOperator::AcesFilmic.apply(&(Vec3::one() * 1000.0)).r() > 0.99);
+    }
+
+    #[test]
+    fn test_color_pipeline() {
+        let pipeline: ColorPipeline = ColorPipeline::default();
+        assert_eq!(pipeline.encode_rgb8(&Vec3::new(0.0, 1.0, 4.0)), [0, 255, 255]);
+        assert_eq!(pipeline.encode_rgb8(&Vec3::new(0.5, 0.5, 0.5)), [188, 188, 188]);
+
+        // One stop up brings 0.25 to 0.5
+        let brighter: ColorPipeline = ColorPipeline { exposure: 1.0, operator: ToneMapOperator::Clamp };
+        assert_eq!(brighter.encode_rgb8(&Vec3::new(0.25, 0.25, 0.25)), [188, 188, 188]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::plane::disk_bounding_box;
+
+// Value of the polynomial with coefficients c[0] + c[1] x + c[2] x^2 + ...
+fn evaluate(c: &[f64], x: f64) -> f64 {
+    c.iter().rev().fold(0.0, |value, coefficient| value * x + coefficient)
+}
+
+// Real roots in [lo, hi], in increasing order. Between two neighbouring
+// roots of the derivative the polynomial is monotonic, so each of those
+// intervals holds at most one root, found by bisection. Roots where the
+// polynomial only touches zero without crossing it are missed, which for a
+// ray means a grazing hit.
+fn real_roots(c: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    if c.len() == 2 {
+        let root: f64 = -c[0] / c[1];
+        return if (lo..=hi).contains(&root) { vec![root] } else { vec![] };
+    }
+
+    let derivative: Vec<f64> = c.iter().enumerate().skip(1).map(|(i, c)| i as f64 * c).collect();
+    let mut bounds: Vec<f64> = vec![lo];
+    bounds.extend(real_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots: Vec<f64> = Vec::new();
+    for interval in bounds.windows(2) {
+        let (mut a, mut b): (f64, f64) = (interval[0], interval[1]);
+        let rising: bool = evaluate(c, a) < 0.0;
+        if rising == (evaluate(c, b) < 0.0) {
+            continue;
+        }
+        loop {
+            let mid: f64 = 0.5 * (a + b);
+            if mid <= a || mid >= b {
+                break;
+            }
+            if (evaluate(c, mid) < 0.0) == rising {
+                a = mid;
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(a);
+    }
+    roots
+}
+
+// Ring around an axis through `center`: the points at distance
+// minor_radius from the circle of major_radius in the plane across the axis
+pub struct Torus {
+    center: Vec3,
+    // w runs along the axis
+    uvw: Onb,
+    major_radius: f64,
+    minor_radius: f64,
+    mat_ptr: Box<Material>,
+}
+
+impl Torus {
+    pub fn torus(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, m: Box<Material>) -> Self {
+        Torus { center, uvw: Onb::onb(&axis), major_radius, minor_radius, mat_ptr: m }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Unit direction in the torus' frame, so that the quartic's
+        // coefficients stay well scaled
+        let length: f64 = r.direction().length();
+        let d: Vec3 = self.uvw.world_to_local(&r.direction()) / length;
+        let o: Vec3 = self.uvw.world_to_local(&(r.origin() - self.center));
+
+        // Only the part of the ray inside the bounding sphere can hit, and
+        // starting from there keeps the origin close
+        let bound: f64 = self.major_radius + self.minor_radius;
+        let b: f64 = o.dot(&d);
+        let discriminant: f64 = b * b - (o.length_squared() - bound * bound);
+        if discriminant <= 0.0 {
+            return false;
+        }
+        let (enter, exit): (f64, f64) = (-b - discriminant.sqrt(), -b + discriminant.sqrt());
+        let (lo, hi): (f64, f64) = (enter.max(t_min * length), exit.min(t_max * length));
+        if lo > hi {
+            return false;
+        }
+        let o: Vec3 = o + d * lo;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + s d
+        let r2: f64 = self.major_radius * self.major_radius;
+        let b: f64 = o.dot(&d);
+        let g: f64 = o.length_squared() + r2 - self.minor_radius * self.minor_radius;
+        let coefficients: [f64; 5] = [
+            g * g - 4.0 * r2 * (o.x() * o.x() + o.y() * o.y()),
+            4.0 * b * g - 8.0 * r2 * (o.x() * d.x() + o.y() * d.y()),
+            4.0 * b * b + 2.0 * g - 4.0 * r2 * (d.x() * d.x() + d.y() * d.y()),
+            4.0 * b,
+            1.0,
+        ];
+        let s: f64 = match real_roots(&coefficients, 0.0, hi - lo).first() {
+            Some(s) => *s,
+            None => return false,
+        };
+
+        // The normal points away from the nearest point on the center circle
+        let p: Vec3 = o + d * s;
+        let ring: f64 = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let core: Vec3 = Vec3::new(p.x(), p.y(), 0.0) * (self.major_radius / ring);
+        let outward_normal: Vec3 = Utils::unit_vector(&self.uvw.local((p - core).x(), (p - core).y(), (p - core).z()));
+
+        // u runs around the axis, v around the tube starting outside
+        rec.t = (lo + s) / length;
+        rec.p = r.point_at_parameter(rec.t);
+        rec.u = (p.y().atan2(p.x()) / (2.0 * Utils::pi())).rem_euclid(1.0);
+        rec.v = (p.z().atan2(ring - self.major_radius) / (2.0 * Utils::pi())).rem_euclid(1.0);
+        rec.set_face_normal(r, &outward_normal);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let ring: Aabb = disk_bounding_box(&self.center, &self.uvw.w, self.major_radius);
+        let tube: Vec3 = Vec3::one() * self.minor_radius;
+        *output_box = Aabb::aabb(ring.minimum - tube, ring.maximum + tube);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_real_roots() {
+        // (x + 2)(x - 0.5)(x - 1)(x - 3), and a quartic with no real roots
+        let c: [f64; 5] = [-3.0, 8.5, -4.0, -2.5, 1.0];
+        let roots: Vec<f64> = real_roots(&c, -10.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0]) {
+            assert!((root - expected).abs() < 1e-12, "{:?}", roots);
+        }
+        assert_eq!(real_roots(&c, 0.75, 2.0).len(), 1);
+        assert!(real_roots(&[2.0, 0.0, 1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn test_torus_hit() {
+        let torus: Torus = Torus::torus(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, Box::default());
+        let mut rec: HitRecord = HitRecord::default();
+
+        // Across the ring: in through the outside, out into the hole, and
+        // in again on the far side
+        let across: Ray = Ray::ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!(torus.hit(&across, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.25).abs() < 1e-9 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(torus.hit(&across, 1.3, Utils::infinity(), &mut rec));
+        assert!((rec.t - 1.75).abs() < 1e-9 && !rec.front_face);
+        assert!(torus.hit(&across, 1.8, Utils::infinity(), &mut rec));
+        assert!((rec.t - 3.25).abs() < 1e-9 && rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(!torus.hit(&across, 0.001, 1.2, &mut rec));
+
+        // Onto the top of the tube, and down through the hole
+        let top: Ray = Ray::ray(Vec3::new(2.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&top, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.t - 4.5).abs() < 1e-9 && (rec.v - 0.25).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        let hole: Ray = Ray::ray(Vec3::new(0.5, 5.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(!torus.hit(&hole, 0.001, Utils::infinity(), &mut rec));
+
+        // Oblique hits land on the surface
+        let oblique: Ray = Ray::ray(Vec3::new(-4.0, 3.0, 1.0), Vec3::new(1.646, -2.646, -1.0));
+        assert!(torus.hit(&oblique, 0.001, Utils::infinity(), &mut rec));
+        let ring: f64 = (rec.p.x() * rec.p.x() + rec.p.z() * rec.p.z()).sqrt();
+        assert!(((ring - 2.0).powi(2) + rec.p.y() * rec.p.y() - 0.25).abs() < 1e-9);
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(torus.bounding_box(&mut bbox));
+        assert!((bbox.maximum - Vec3::new(2.5, 0.5, 2.5)).length() < 1e-12);
+    }
+}
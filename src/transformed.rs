@@ -0,0 +1,250 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::aabb::Aabb;
+use crate::mat4::Transform;
+use std::sync::Arc;
+
+// Instance of a shared object placed in the world by an affine transform.
+// Any number of instances can refer to the same object, e.g. one mesh BVH.
+#[derive(Clone)]
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    // Object space to world space
+    transform: Transform,
+    bbox: Option<Aabb>,
+    // Emissive primitives of the object, found once instead of per sample
+    lights: Arc<Vec<Arc<dyn Hittable>>>,
+}
+
+impl Transformed {
+    pub fn transformed(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let mut object_box: Aabb = Aabb::default();
+        let bbox: Option<Aabb> = if object.bounding_box(&mut object_box) {
+            Some(transform.bounding_box(&object_box))
+        } else {
+            None
+        };
+
+        let mut lights: Vec<Arc<dyn Hittable>> = Vec::new();
+        object.collect_light_copies(&mut lights);
+
+        Transformed { object, transform, bbox, lights: Arc::new(lights) }
+    }
+
+    // The direction is not normalized, so t is the same in both spaces
+    fn object_ray(&self, r: &Ray) -> Ray {
+        let world_to_object: Transform = self.transform.inverse();
+        Ray::ray_with_time(
+            world_to_object.point(&r.origin()), world_to_object.vector(&r.direction()), r.time())
+    }
+
+    // The normal already faces against the ray, and the inverse transpose
+    // keeps it that way
+    fn to_world(&self, rec: &mut HitRecord) {
+        rec.p = self.transform.point(&rec.p);
+        rec.normal = Utils::unit_vector(&self.transform.normal(&rec.normal));
+    }
+
+    // Ratio between a solid angle around `direction` in world space and the
+    // one it maps to in object space
+    fn solid_angle_scale(&self, object_direction: &Vec3) -> f64 {
+        let d: Vec3 = Utils::unit_vector(object_direction);
+        let stretched: f64 = self.transform.vector(&d).length();
+        self.transform.matrix().determinant3().abs() / (stretched * stretched * stretched)
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(&self.object_ray(r), t_min, t_max, rec) {
+            return false;
+        }
+
+        self.to_world(rec);
+        true
+    }
+
+    fn hit_surface(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.object.hit_surface(&self.object_ray(r), t_min, t_max, rec) {
+            return false;
+        }
+
+        self.to_world(rec);
+        true
+    }
+
+    // Open ends have no surface to move
+    fn hit_intervals(&self, r: &Ray, t_min: f64) -> Vec<HitInterval> {
+        let mut intervals: Vec<HitInterval> = self.object.hit_intervals(&self.object_ray(r), t_min);
+        for interval in intervals.iter_mut() {
+            for end in [&mut interval.enter, &mut interval.exit] {
+                if end.t.is_finite() {
+                    self.to_world(end);
+                }
+            }
+        }
+        intervals
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.object.transmittance(&self.object_ray(r), t_min, t_max)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // The lights inside the object are picked uniformly and sampled in
+    // object space, and the density is carried over to world space
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let world_to_object: Transform = self.transform.inverse();
+        let object_origin: Vec3 = world_to_object.point(origin);
+        let object_direction: Vec3 = world_to_object.vector(direction);
+        let sum: f64 = self.lights.iter().map(|light| light.pdf_value(&object_origin, &object_direction)).sum();
+
+        sum / self.lights.len() as f64 / self.solid_angle_scale(&object_direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let count: usize = self.lights.len();
+        let index: usize = ((Utils::random_double() * count as f64) as usize).min(count - 1);
+        self.transform.vector(&self.lights[index].random(&self.transform.inverse().point(origin)))
+    }
+
+    // The instance stands in for all the lights of its object
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if !self.lights.is_empty() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if !self.lights.is_empty() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::sphere::Sphere;
+    use crate::quad::Quad;
+    use crate::hittable_list::HittableList;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_transformed_hit() {
+        // Unit sphere stretched into an ellipsoid and moved up
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::sphere(Vec3::zero(), 1.0, Box::default()));
+        let transform: Transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0)).unwrap()
+            .then(&Transform::translate(&Vec3::new(0.0, 3.0, 0.0)));
+        let instance: Transformed = Transformed::transformed(sphere.clone(), transform);
+        let mut rec: HitRecord = HitRecord::default();
+
+        let r: Ray = Ray::ray(Vec3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(instance.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!(close(rec.t, 3.0));
+        assert!((rec.p - Vec3::new(-2.0, 3.0, 0.0)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(rec.front_face);
+
+        // Off the pole the normal tilts towards x
+        let r: Ray = Ray::ray(Vec3::new(1.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(instance.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        let expected: Vec3 = Utils::unit_vector(&Vec3::new(0.5 / 2.0, 0.75_f64.sqrt(), 0.0));
+        assert!((rec.normal - expected).length() < 1e-9);
+
+        let mut bbox: Aabb = Aabb::default();
+        assert!(instance.bounding_box(&mut bbox));
+        assert_eq!(bbox, Aabb::aabb(Vec3::new(-2.0, 2.0, -1.0), Vec3::new(2.0, 4.0, 1.0)));
+
+        // Instances share the object
+        let other: Transformed = Transformed::transformed(sphere.clone(), Transform::identity());
+        assert!(!other.hit(&Ray::ray(Vec3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.001, Utils::infinity(), &mut rec));
+        assert_eq!(Arc::strong_count(&sphere), 3);
+    }
+
+    #[test]
+    fn test_transformed_light_pdf() {
+        // A stretched and tilted light still has a density that integrates
+        // to one over the directions that see it
+        let light: Arc<dyn Hittable> = Arc::new(Quad::quad(
+            Vec3::new(-0.5, 0.0, -0.5), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0),
+            Box::new(Material::DiffuseLight { diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::one()) })));
+        let transform: Transform = Transform::scale(&Vec3::new(3.0, 1.0, 0.5)).unwrap()
+            .then(&Transform::rotate(&Vec3::new(1.0, 0.0, 0.0), 30.0))
+            .then(&Transform::translate(&Vec3::new(0.0, 2.0, 0.0)));
+        let instance: Transformed = Transformed::transformed(light, transform);
+
+        let mut lights: Vec<&dyn Hittable> = Vec::new();
+        instance.collect_lights(&mut lights);
+        assert_eq!(lights.len(), 1);
+
+        // Uniform sphere directions, weighted by 4 pi
+        let origin: Vec3 = Vec3::new(0.3, 0.0, 0.2);
+        let n: u32 = 200_000;
+        let mut integral: f64 = 0.0;
+        for _ in 0..n {
+            integral += instance.pdf_value(&origin, &Utils::random_unit_vector());
+        }
+        integral *= 4.0 * Utils::pi() / n as f64;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+
+        // Sampled directions hit the instance
+        let mut rec: HitRecord = HitRecord::default();
+        for _ in 0..100 {
+            let direction: Vec3 = instance.random(&origin);
+            assert!(instance.hit(&Ray::ray(origin, direction), 0.001, Utils::infinity(), &mut rec));
+            assert!(instance.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_transformed_nested_lights() {
+        // An instance of an instance of two lights and a plain sphere keeps
+        // both lights, and its density still integrates to one
+        let mut object: HittableList = HittableList::default();
+        object.add(Box::new(Sphere::sphere(Vec3::new(-1.0, 0.0, 0.0), 0.5,
+            Box::new(Material::DiffuseLight { diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::one()) }))));
+        object.add(Box::new(Sphere::sphere(Vec3::new(1.0, 0.0, 0.0), 0.5,
+            Box::new(Material::DiffuseLight { diffuse_light: DiffuseLightMaterial::diffuse_light(Vec3::one()) }))));
+        object.add(Box::new(Sphere::sphere(Vec3::new(0.0, 0.0, 3.0), 0.5, Box::default())));
+        let inner: Arc<dyn Hittable> = Arc::new(Transformed::transformed(
+            Arc::new(object), Transform::scale(&Vec3::new(1.0, 2.0, 1.0)).unwrap()));
+        let instance: Transformed = Transformed::transformed(
+            inner, Transform::translate(&Vec3::new(0.0, 3.0, 0.0)));
+        assert_eq!(instance.lights.len(), 1);
+
+        let origin: Vec3 = Vec3::zero();
+        let n: u32 = 200_000;
+        let mut integral: f64 = 0.0;
+        for _ in 0..n {
+            integral += instance.pdf_value(&origin, &Utils::random_unit_vector());
+        }
+        integral *= 4.0 * Utils::pi() / n as f64;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+
+        let mut rec: HitRecord = HitRecord::default();
+        for _ in 0..100 {
+            let direction: Vec3 = instance.random(&origin);
+            assert!(instance.hit(&Ray::ray(origin, direction), 0.001, Utils::infinity(), &mut rec));
+        }
+    }
+}
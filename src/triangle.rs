@@ -0,0 +1,229 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use std::sync::Arc;
+
+// Result of a ray/triangle test: distance along the ray and the
+// barycentric weights of the three vertices
+#[derive(Debug, Copy, Clone)]
+pub struct TriangleHit {
+    pub t: f64,
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013).
+// Rays through a shared edge or vertex hit exactly one of the adjacent
+// triangles, so meshes have no cracks.
+pub fn intersect_triangle(r: &Ray, p0: &Vec3, p1: &Vec3, p2: &Vec3, t_min: f64, t_max: f64) -> Option<TriangleHit> {
+    let dir: Vec3 = r.direction();
+
+    // Permute axes so that z is the largest direction component
+    let kz: usize = if dir.x().abs() > dir.y().abs() {
+        if dir.x().abs() > dir.z().abs() { 0 } else { 2 }
+    } else if dir.y().abs() > dir.z().abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx: usize = (kz + 1) % 3;
+    let mut ky: usize = (kx + 1) % 3;
+    // Keep the winding direction of the triangle
+    if dir.e[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear so that the ray points down +z
+    let sx: f64 = dir.e[kx] / dir.e[kz];
+    let sy: f64 = dir.e[ky] / dir.e[kz];
+    let sz: f64 = 1.0 / dir.e[kz];
+
+    let a: Vec3 = *p0 - r.origin();
+    let b: Vec3 = *p1 - r.origin();
+    let c: Vec3 = *p2 - r.origin();
+
+    let ax: f64 = a.e[kx] - sx * a.e[kz];
+    let ay: f64 = a.e[ky] - sy * a.e[kz];
+    let bx: f64 = b.e[kx] - sx * b.e[kz];
+    let by: f64 = b.e[ky] - sy * b.e[kz];
+    let cx: f64 = c.e[kx] - sx * c.e[kz];
+    let cy: f64 = c.e[ky] - sy * c.e[kz];
+
+    // Scaled barycentric coordinates from 2D edge functions
+    let u: f64 = cx * by - cy * bx;
+    let v: f64 = ax * cy - ay * cx;
+    let w: f64 = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det: f64 = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let az: f64 = sz * a.e[kz];
+    let bz: f64 = sz * b.e[kz];
+    let cz: f64 = sz * c.e[kz];
+    let t: f64 = (u * az + v * bz + w * cz) / det;
+
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some(TriangleHit { t, b0: u / det, b1: v / det, b2: w / det })
+}
+
+pub fn triangle_bounding_box(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> Aabb {
+    Aabb::aabb(*p0, *p1).include_point(p2)
+}
+
+// Solid angle density, seen from `origin`, of picking `direction` by
+// sampling a uniform point on the triangle
+pub fn triangle_pdf_value(p0: &Vec3, p1: &Vec3, p2: &Vec3, origin: &Vec3, direction: &Vec3) -> f64 {
+    let r: Ray = Ray::ray(*origin, *direction);
+    let hit: TriangleHit = match intersect_triangle(&r, p0, p1, p2, 0.001, Utils::infinity()) {
+        Some(hit) => hit,
+        None => return 0.0,
+    };
+
+    let n: Vec3 = Utils::cross(&(*p1 - *p0), &(*p2 - *p0));
+    let area: f64 = 0.5 * n.length();
+    let distance_squared: f64 = hit.t * hit.t * direction.length_squared();
+    let cosine: f64 = direction.dot(&n).abs() / (direction.length() * n.length());
+    distance_squared / (cosine * area)
+}
+
+// Direction from `origin` to a uniform random point on the triangle
+pub fn triangle_random(p0: &Vec3, p1: &Vec3, p2: &Vec3, origin: &Vec3) -> Vec3 {
+    let su: f64 = Utils::random_double().sqrt();
+    let b2: f64 = Utils::random_double() * su;
+    let p: Vec3 = *p0 * (1.0 - su) + *p1 * (su - b2) + *p2 * b2;
+    p - *origin
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
+    mat_ptr: Box<Material>,
+}
+
+impl Triangle {
+    // Vertices in counter-clockwise order when seen from the front
+    pub fn triangle(v0: Vec3, v1: Vec3, v2: Vec3, m: Box<Material>) -> Self {
+        Triangle { v0, v1, v2, mat_ptr: m }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let hit: TriangleHit = match intersect_triangle(r, &self.v0, &self.v1, &self.v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        rec.t = hit.t;
+        rec.p = self.v0 * hit.b0 + self.v1 * hit.b1 + self.v2 * hit.b2;
+        rec.u = hit.b1;
+        rec.v = hit.b2;
+        let outward_normal: Vec3 =
+            Utils::unit_vector(&Utils::cross(&(self.v1 - self.v0), &(self.v2 - self.v0)));
+        rec.set_face_normal(r, &outward_normal);
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = triangle_bounding_box(&self.v0, &self.v1, &self.v2);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        triangle_pdf_value(&self.v0, &self.v1, &self.v2, origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        triangle_random(&self.v0, &self.v1, &self.v2, origin)
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mat_ptr.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::triangle(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Box::default())
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let tri: Triangle = unit_triangle();
+        let r: Ray = Ray::ray(Vec3::new(0.25, 0.25, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+
+        assert!(tri.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.p, Vec3::new(0.25, 0.25, 0.0));
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert_eq!((rec.u, rec.v), (0.25, 0.25));
+    }
+
+    #[test]
+    fn test_triangle_miss() {
+        let tri: Triangle = unit_triangle();
+        let mut rec: HitRecord = HitRecord::default();
+
+        let outside: Ray = Ray::ray(Vec3::new(0.75, 0.75, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!tri.hit(&outside, 0.001, Utils::infinity(), &mut rec));
+
+        let parallel: Ray = Ray::ray(Vec3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(!tri.hit(&parallel, 0.001, Utils::infinity(), &mut rec));
+
+        let too_far: Ray = Ray::ray(Vec3::new(0.25, 0.25, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!tri.hit(&too_far, 0.001, 1.5, &mut rec));
+    }
+
+    #[test]
+    fn test_triangle_shared_edge_is_watertight() {
+        // Two triangles sharing the diagonal of the unit square
+        let p00: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+        let p10: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+        let p01: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+        let p11: Vec3 = Vec3::new(1.0, 1.0, 0.0);
+
+        for k in 1..100 {
+            let s: f64 = k as f64 / 100.0;
+            // Crosses the plane exactly on the diagonal at (s, 1 - s)
+            let r: Ray = Ray::ray(Vec3::new(s - 0.3, 1.2 - s, 1.0), Vec3::new(0.3, -0.2, -1.0));
+            let first = intersect_triangle(&r, &p00, &p10, &p01, 0.0, f64::INFINITY);
+            let second = intersect_triangle(&r, &p10, &p11, &p01, 0.0, f64::INFINITY);
+            assert!(first.is_some() || second.is_some());
+        }
+    }
+}
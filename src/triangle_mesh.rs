@@ -0,0 +1,241 @@
+use crate::vec3::Vec3;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::utils::Utils;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::triangle::*;
+use std::sync::Arc;
+
+// Indices of one mesh triangle into the shared vertex buffers. Normal and
+// UV indices are optional and independent of the position indices, as in
+// Wavefront OBJ.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+// Buffers shared by every triangle of a mesh
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    mat_ptr: Box<Material>,
+}
+
+#[derive(Clone)]
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: MeshFace,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> (&Vec3, &Vec3, &Vec3) {
+        let p: &Vec<Vec3> = &self.mesh.positions;
+        let idx: [usize; 3] = self.face.positions;
+        (&p[idx[0]], &p[idx[1]], &p[idx[2]])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (p0, p1, p2) = self.vertices();
+        let hit: TriangleHit = match intersect_triangle(r, p0, p1, p2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        rec.t = hit.t;
+        rec.p = *p0 * hit.b0 + *p1 * hit.b1 + *p2 * hit.b2;
+
+        match self.face.uvs {
+            Some(idx) => {
+                let uvs: &Vec<(f64, f64)> = &self.mesh.uvs;
+                rec.u = hit.b0 * uvs[idx[0]].0 + hit.b1 * uvs[idx[1]].0 + hit.b2 * uvs[idx[2]].0;
+                rec.v = hit.b0 * uvs[idx[0]].1 + hit.b1 * uvs[idx[1]].1 + hit.b2 * uvs[idx[2]].1;
+            },
+            None => {
+                rec.u = hit.b1;
+                rec.v = hit.b2;
+            }
+        }
+
+        let mut geometric_normal: Vec3 =
+            Utils::unit_vector(&Utils::cross(&(*p1 - *p0), &(*p2 - *p0)));
+
+        match self.face.normals {
+            Some(idx) => {
+                let n: &Vec<Vec3> = &self.mesh.normals;
+                let shading_normal: Vec3 = Utils::unit_vector(
+                    &(n[idx[0]] * hit.b0 + n[idx[1]] * hit.b1 + n[idx[2]] * hit.b2));
+
+                // Trust the authored normals over the winding order
+                if geometric_normal.dot(&shading_normal) < 0.0 {
+                    geometric_normal = -geometric_normal;
+                }
+                rec.set_shading_normal(r, &geometric_normal, &shading_normal);
+            },
+            None => {
+                rec.set_face_normal(r, &geometric_normal);
+            }
+        }
+
+        rec.mat_ptr = self.mesh.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let (p0, p1, p2) = self.vertices();
+        *output_box = triangle_bounding_box(p0, p1, p2);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let (p0, p1, p2) = self.vertices();
+        triangle_pdf_value(p0, p1, p2, origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        let (p0, p1, p2) = self.vertices();
+        triangle_random(p0, p1, p2, origin)
+    }
+
+    // Every triangle of an emissive mesh is a light of its own
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        if self.mesh.mat_ptr.is_emissive() {
+            lights.push(self);
+        }
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mesh.mat_ptr.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+// Indexed triangle mesh with a single material. Triangles share the vertex,
+// normal and UV buffers and are kept in their own BVH.
+pub struct TriangleMesh {
+    root: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl TriangleMesh {
+    pub fn triangle_mesh(
+            positions: Vec<Vec3>,
+            normals: Vec<Vec3>,
+            uvs: Vec<(f64, f64)>,
+            faces: Vec<MeshFace>,
+            m: Box<Material>) -> TriangleMesh {
+        assert!(!faces.is_empty(), "TriangleMesh needs at least one face");
+
+        for face in &faces {
+            assert!(face.positions.iter().all(|&i| i < positions.len()), "Position index out of range");
+            if let Some(idx) = face.normals {
+                assert!(idx.iter().all(|&i| i < normals.len()), "Normal index out of range");
+            }
+            if let Some(idx) = face.uvs {
+                assert!(idx.iter().all(|&i| i < uvs.len()), "UV index out of range");
+            }
+        }
+
+        let mesh: Arc<MeshData> = Arc::new(MeshData { positions, normals, uvs, mat_ptr: m });
+
+        let triangles: Vec<Box<dyn Hittable>> = faces
+            .into_iter()
+            .map(|face| -> Box<dyn Hittable> {
+                Box::new(MeshTriangle { mesh: Arc::clone(&mesh), face })
+            })
+            .collect();
+
+        let root: Box<dyn Hittable> = BvhNode::build(triangles);
+        let mut bbox: Aabb = Aabb::default();
+        root.bounding_box(&mut bbox);
+
+        TriangleMesh { root, bbox }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.root.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+
+    fn collect_lights<'a>(&'a self, lights: &mut Vec<&'a dyn Hittable>) {
+        self.root.collect_lights(lights);
+    }
+
+    fn collect_light_copies(&self, lights: &mut Vec<Arc<dyn Hittable>>) {
+        self.root.collect_light_copies(lights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square in the z = 0 plane made of two triangles
+    fn quad(normals: Vec<Vec3>, with_normals: bool) -> TriangleMesh {
+        let positions: Vec<Vec3> = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let uvs: Vec<(f64, f64)> = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let faces: Vec<MeshFace> = [[0, 1, 2], [0, 2, 3]]
+            .iter()
+            .map(|&idx| MeshFace {
+                positions: idx,
+                normals: if with_normals { Some(idx) } else { None },
+                uvs: Some(idx),
+            })
+            .collect();
+
+        TriangleMesh::triangle_mesh(positions, normals, uvs, faces, Box::default())
+    }
+
+    #[test]
+    fn test_triangle_mesh_uv() {
+        let mesh: TriangleMesh = quad(Vec::new(), false);
+        let r: Ray = Ray::ray(Vec3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+
+        assert!(mesh.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.75).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_triangle_mesh_normal_interpolation() {
+        // Normals tilt towards +x on the right edge of the square
+        let tilted: Vec3 = Utils::unit_vector(&Vec3::new(1.0, 0.0, 1.0));
+        let up: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+        let mesh: TriangleMesh = quad(vec![up, tilted, tilted, up], true);
+
+        let r: Ray = Ray::ray(Vec3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec: HitRecord = HitRecord::default();
+
+        assert!(mesh.hit(&r, 0.001, Utils::infinity(), &mut rec));
+        let expected: Vec3 = Utils::unit_vector(&(up * 0.5 + tilted * 0.5));
+        assert!((rec.normal - expected).length() < 1e-12);
+        assert!(rec.front_face);
+
+        // Seen from below the shading normal is flipped like any other normal
+        let below: Ray = Ray::ray(Vec3::new(0.5, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(mesh.hit(&below, 0.001, Utils::infinity(), &mut rec));
+        assert!(!rec.front_face);
+        assert!((rec.normal + expected).length() < 1e-12);
+    }
+}
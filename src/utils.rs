@@ -1,5 +1,13 @@
 use crate::vec3::Vec3;
-use rand::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    // Each render thread owns its generator. The renderer reseeds it per
+    // pixel, so the image does not depend on how tiles land on threads.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
 
 pub struct Utils {
 
@@ -15,26 +23,7 @@ impl Utils {
     }
 
     pub fn dot(v1: &Vec3, v2: &Vec3) -> f64 {
-        v1.dot(&v2)
-    }
-
-    pub fn write_color(pixel_color: &Vec3, samples_per_pixel: u32) {
-        // Write the translated [0,255] value of each color component.
-        let mut r: f64 = pixel_color.r();
-        let mut g: f64 = pixel_color.g();
-        let mut b: f64 = pixel_color.b();
-
-        let scale: f64 = 1f64 / samples_per_pixel as f64;
-
-        r = (scale * r).sqrt();
-        g = (scale * g).sqrt();
-        b = (scale * b).sqrt();
-
-        let ir: u32 = (255.999 * Utils::clamp(r, 0.0, 0.999)) as u32;
-        let ig: u32 = (255.999 * Utils::clamp(g, 0.0, 0.999)) as u32;
-        let ib: u32 = (255.999 * Utils::clamp(b, 0.0, 0.999)) as u32;
-
-        println!("{} {} {}", ir, ig, ib);
+        v1.dot(v2)
     }
 
     pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -48,7 +37,7 @@ impl Utils {
     }
 
     pub fn infinity() -> f64 {
-        std::f64::MAX
+        f64::MAX
     }
 
     pub fn pi() -> f64 {
@@ -59,9 +48,12 @@ impl Utils {
         degree * std::f64::consts::PI / 180.0_f64
     }
 
+    pub fn seed_random(seed: u64) {
+        RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+    }
+
     pub fn random_double() -> f64 {
-        let mut rng = rand::thread_rng();
-        rng.gen()
+        RNG.with(|rng| rng.borrow_mut().gen())
     }
 
     pub fn random_double_min_max(min: f64, max: f64) -> f64 {
@@ -91,6 +83,7 @@ impl Utils {
         }
     }
 
+    #[allow(dead_code)]
     pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
         let in_unit_normal: Vec3 = Utils::random_in_unit_shpere();
 
@@ -119,7 +112,6 @@ impl Utils {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -142,4 +134,13 @@ mod tests {
         let v2: Vec3 = Vec3::new(0.0, 1.0, 0.0);
         assert_eq!(Utils::cross(&v1, &v2), Vec3::new(0.0, 0.0, 1.0));
     }
+
+    #[test]
+    fn test_utils_seed_random() {
+        Utils::seed_random(42);
+        let a: Vec<f64> = (0..8).map(|_| Utils::random_double()).collect();
+        Utils::seed_random(42);
+        let b: Vec<f64> = (0..8).map(|_| Utils::random_double()).collect();
+        assert_eq!(a, b);
+    }
 }
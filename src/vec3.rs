@@ -40,8 +40,8 @@ impl Vec3 {
     pub fn cross(&self, rhs: &Self) -> Vec3 {
         Vec3 {
             e:[
-                self.e[1] * rhs.e[2] + self.e[2] * rhs.e[1],
-                self.e[2] * rhs.e[0] + self.e[0] * rhs.e[2],
+                self.e[1] * rhs.e[2] - self.e[2] * rhs.e[1],
+                self.e[2] * rhs.e[0] - self.e[0] * rhs.e[2],
                 self.e[0] * rhs.e[1] - self.e[1] * rhs.e[0]
             ]
         }
@@ -244,6 +244,11 @@ mod tests {
         let v1: Vec3 = Vec3::new(1.0, 0.0, 0.0);
         let v2: Vec3 = Vec3::new(0.0, 1.0, 0.0);
         assert_eq!(v1.cross(&v2), Vec3::new(0.0, 0.0, 1.0));
+
+        let v3: Vec3 = Vec3::new(1.0, 2.0, 3.0);
+        let v4: Vec3 = Vec3::new(4.0, 5.0, 6.0);
+        assert_eq!(v3.cross(&v4), Vec3::new(-3.0, 6.0, -3.0));
+        assert_eq!(v3.cross(&v4).dot(&v3), 0.0);
     }
 
     #[test]
@@ -0,0 +1,192 @@
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Dense grid of scalar values over the unit cube, e.g. the density of a
+// cloud. Voxel (x, y, z) is centered at ((x, y, z) + 0.5) / size.
+#[derive(Clone, PartialEq, Debug)]
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    // x varies fastest, then y, then z
+    values: Vec<f32>,
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    pub fn voxel_grid(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> VoxelGrid {
+        assert!(nx > 0 && ny > 0 && nz > 0);
+        assert_eq!(values.len(), nx * ny * nz);
+        let max_value: f64 = values.iter().fold(0.0, |m: f64, v| m.max(*v as f64));
+        VoxelGrid { nx, ny, nz, values, max_value }
+    }
+
+    // Largest value anywhere in the grid, and so of sample() too
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    // Indices outside the grid are clamped to the border voxels
+    pub fn voxel(&self, x: isize, y: isize, z: isize) -> f64 {
+        let x: usize = x.clamp(0, self.nx as isize - 1) as usize;
+        let y: usize = y.clamp(0, self.ny as isize - 1) as usize;
+        let z: usize = z.clamp(0, self.nz as isize - 1) as usize;
+        self.values[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    // Trilinear interpolation between the eight nearest voxel centers, at a
+    // point given in unit cube coordinates
+    pub fn sample(&self, p: &Vec3) -> f64 {
+        let gx: f64 = p.x() * self.nx as f64 - 0.5;
+        let gy: f64 = p.y() * self.ny as f64 - 0.5;
+        let gz: f64 = p.z() * self.nz as f64 - 0.5;
+        let (x0, y0, z0): (f64, f64, f64) = (gx.floor(), gy.floor(), gz.floor());
+        let (fx, fy, fz): (f64, f64, f64) = (gx - x0, gy - y0, gz - z0);
+        let (x0, y0, z0): (isize, isize, isize) = (x0 as isize, y0 as isize, z0 as isize);
+
+        let mut value: f64 = 0.0;
+        for (dz, wz) in [(0, 1.0 - fz), (1, fz)] {
+            for (dy, wy) in [(0, 1.0 - fy), (1, fy)] {
+                for (dx, wx) in [(0, 1.0 - fx), (1, fx)] {
+                    value += wx * wy * wz * self.voxel(x0 + dx, y0 + dy, z0 + dz);
+                }
+            }
+        }
+        value
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A text header followed by raw voxel data:
+//
+//   VOXELS 1
+//   # comments start with '#'
+//   size 64 64 32
+//   channels density temperature
+//   data f32
+//
+// The header ends with the newline after the data line. Then come, for
+// each voxel in grid order, one value per channel in the listed order,
+// either as little-endian 32-bit floats (f32) or as bytes scaled to
+// [0, 1] (u8).
+pub fn decode_voxels(data: &[u8]) -> io::Result<HashMap<String, VoxelGrid>> {
+    let mut size: Option<[usize; 3]> = None;
+    let mut channels: Vec<String> = Vec::new();
+    let mut pos: usize = 0;
+    let mut first: bool = true;
+
+    let format: String = loop {
+        let end: usize = match data[pos..].iter().position(|c| *c == b'\n') {
+            Some(end) => pos + end,
+            None => return Err(invalid_data("truncated voxel header".to_string())),
+        };
+        let line: String = String::from_utf8_lossy(&data[pos..end]).into_owned();
+        pos = end + 1;
+
+        let line: &str = line.split('#').next().unwrap_or("").trim();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if first {
+            if fields != ["VOXELS", "1"] {
+                return Err(invalid_data("not a voxel file, expected 'VOXELS 1'".to_string()));
+            }
+            first = false;
+            continue;
+        }
+
+        match fields.first() {
+            None => {},
+            Some(&"size") => {
+                let parsed: Vec<usize> = fields[1..].iter().filter_map(|f| f.parse::<usize>().ok()).collect();
+                if fields.len() != 4 || parsed.len() != 3 || parsed.contains(&0) {
+                    return Err(invalid_data(format!("invalid voxel grid size '{}'", line)));
+                }
+                size = Some([parsed[0], parsed[1], parsed[2]]);
+            },
+            Some(&"channels") => {
+                channels = fields[1..].iter().map(|f| f.to_string()).collect();
+            },
+            Some(&"data") if fields.len() == 2 => break fields[1].to_string(),
+            Some(other) => return Err(invalid_data(format!("unknown voxel header line '{}'", other))),
+        }
+    };
+
+    let [nx, ny, nz]: [usize; 3] = size.ok_or_else(|| invalid_data("voxel header has no size".to_string()))?;
+    if channels.is_empty() {
+        return Err(invalid_data("voxel header has no channels".to_string()));
+    }
+
+    let count: usize = [ny, nz, channels.len()].iter()
+        .try_fold(nx, |count, n| count.checked_mul(*n))
+        .ok_or_else(|| invalid_data(format!("voxel grid size {}x{}x{} is too large", nx, ny, nz)))?;
+    let raw: &[u8] = &data[pos..];
+    let values: Vec<f32> = match format.as_str() {
+        "f32" if raw.len() / 4 >= count => raw.chunks_exact(4)
+            .take(count)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        "u8" if raw.len() >= count => raw[..count].iter().map(|b| *b as f32 / 255.0).collect(),
+        "f32" | "u8" => return Err(invalid_data(format!(
+            "voxel data is truncated, expected {} {} values", count, format))),
+        other => return Err(invalid_data(format!("unknown voxel data type '{}', expected f32 or u8", other))),
+    };
+
+    let mut grids: HashMap<String, VoxelGrid> = HashMap::new();
+    for (c, name) in channels.iter().enumerate() {
+        let channel: Vec<f32> = values.iter().skip(c).step_by(channels.len()).copied().collect();
+        grids.insert(name.clone(), VoxelGrid::voxel_grid(nx, ny, nz, channel));
+    }
+    Ok(grids)
+}
+
+pub fn load_voxels(path: &Path) -> io::Result<HashMap<String, VoxelGrid>> {
+    decode_voxels(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voxel_grid_trilinear() {
+        // 2x1x1 grid: 0 on the left, 1 on the right
+        let grid: VoxelGrid = VoxelGrid::voxel_grid(2, 1, 1, vec![0.0, 1.0]);
+        assert_eq!(grid.sample(&Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.sample(&Vec3::new(0.75, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.sample(&Vec3::new(0.5, 0.1, 0.9)), 0.5);
+        // Clamped past the outer voxel centers
+        assert_eq!(grid.sample(&Vec3::new(0.0, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.sample(&Vec3::new(1.0, 0.5, 0.5)), 1.0);
+
+        let grid: VoxelGrid = VoxelGrid::voxel_grid(2, 2, 2, (0..8).map(|i| i as f32).collect());
+        assert_eq!(grid.voxel(1, 0, 1), 5.0);
+        assert!((grid.sample(&Vec3::new(0.5, 0.5, 0.5)) - 3.5).abs() < 1e-12);
+        assert_eq!(grid.max_value(), 7.0);
+    }
+
+    #[test]
+    fn test_decode_voxels() {
+        let mut data: Vec<u8> = b"VOXELS 1\n# test\nsize 2 1 1\nchannels density heat\ndata f32\n".to_vec();
+        for v in [0.5f32, 1000.0, 0.25, 2000.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        let grids: HashMap<String, VoxelGrid> = decode_voxels(&data).unwrap();
+        assert_eq!(grids["density"], VoxelGrid::voxel_grid(2, 1, 1, vec![0.5, 0.25]));
+        assert_eq!(grids["heat"], VoxelGrid::voxel_grid(2, 1, 1, vec![1000.0, 2000.0]));
+
+        let bytes: &[u8] = b"VOXELS 1\nsize 1 1 2\nchannels density\ndata u8\n\x00\xff";
+        assert_eq!(decode_voxels(bytes).unwrap()["density"].voxel(0, 0, 1), 1.0);
+
+        assert!(decode_voxels(b"VOXELS 2\n").is_err());
+        assert!(decode_voxels(b"VOXELS 1\nsize 1 1 2\nchannels density\ndata u8\n\x00").is_err());
+        assert!(decode_voxels(b"VOXELS 1\nsize 1 0 2\nchannels density\ndata u8\n").is_err());
+        assert!(decode_voxels(b"VOXELS 1\nsize 4294967296 4294967296 2\nchannels density\ndata u8\n\x00").is_err());
+        assert!(decode_voxels(b"VOXELS 1\nsize 1 1 1\ndata u8\n\x00").is_err());
+        assert!(decode_voxels(b"VOXELS 1\nsize 1 1 1\nchannels density\ndata f16\n\x00\x00").is_err());
+    }
+}